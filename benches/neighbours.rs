@@ -0,0 +1,103 @@
+//! Times the neighbour searches behind bonds and the analyses on a large assembly.
+//!
+//! Run with `cargo bench --bench neighbours [-- file.pdb]`. Without a file, 1d66 is tiled into
+//! a 4×4×4 block of copies, about 110 000 atoms.
+
+use std::time::Instant;
+
+use biopix::hbond;
+use biopix::math::distance;
+use biopix::scene::Scene;
+use biopix::spatial::{Grid, KdTree};
+use biopix::style::Style;
+
+fn time<T>(label: &str, run: impl FnOnce() -> T) -> T {
+    let start = Instant::now();
+    let result = run();
+    println!(
+        "{:<36}{:>10.2} ms",
+        label,
+        start.elapsed().as_secs_f64() * 1000.0
+    );
+    result
+}
+
+fn main() {
+    let file = std::env::args()
+        .skip(1)
+        .find(|argument| !argument.starts_with("--"));
+    let scene = Scene::open(file.as_deref().unwrap_or("1d66.pdb"), &Style::default()).unwrap();
+    let structure = scene.structure.as_ref().unwrap();
+
+    let atoms = structure
+        .atoms
+        .iter()
+        .map(|atom| atom.position)
+        .collect::<Vec<_>>();
+    let positions = if file.is_some() {
+        atoms
+    } else {
+        let mut tiled = Vec::new();
+        for offset in 0..64 {
+            let shift = [offset % 4, offset / 4 % 4, offset / 16].map(|step| step as f32 * 80.0);
+            tiled.extend(
+                atoms
+                    .iter()
+                    .map(|position| [0, 1, 2].map(|axis| position[axis] + shift[axis])),
+            );
+        }
+        tiled
+    };
+    println!("{} atoms", positions.len());
+
+    let grid = time("grid build (4 Å cells)", || Grid::new(&positions, 4.0));
+    let tree = time("k-d tree build", || KdTree::new(&positions));
+
+    let queries = positions.iter().step_by(10).copied().collect::<Vec<_>>();
+    let found = time("grid radius 5 Å, every 10th atom", || {
+        queries
+            .iter()
+            .map(|&query| grid.within(query, 5.0).len())
+            .sum::<usize>()
+    });
+    let from_tree = time("k-d tree radius 5 Å, every 10th atom", || {
+        queries
+            .iter()
+            .map(|&query| tree.within(query, 5.0).len())
+            .sum::<usize>()
+    });
+    assert_eq!(found, from_tree);
+
+    time("grid 8 nearest, every 10th atom", || {
+        for &query in &queries {
+            grid.nearest(query, 8);
+        }
+    });
+    time("k-d tree 8 nearest, every 10th atom", || {
+        for &query in &queries {
+            tree.nearest(query, 8);
+        }
+    });
+
+    let pairs = time("grid pairs within 3.5 Å", || grid.pairs(3.5).len());
+    println!("{} pairs", pairs);
+
+    // The naive search this replaced, on a slice small enough to finish quickly
+    let sample = &positions[..positions.len().min(20_000)];
+    let naive = time("naive pairs within 3.5 Å, 20 000 atoms", || {
+        (0..sample.len())
+            .flat_map(|i| (i + 1..sample.len()).map(move |j| (i, j)))
+            .filter(|&(i, j)| distance(sample[i], sample[j]) <= 3.5)
+            .count()
+    });
+    let gridded = time("grid pairs within 3.5 Å, 20 000 atoms", || {
+        Grid::new(sample, 3.5).pairs(3.5).len()
+    });
+    assert_eq!(naive, gridded);
+
+    let mask = vec![true; structure.atoms.len()];
+    let bonds = time("hydrogen bonds of the input", || {
+        hbond::find(structure, &mask).len()
+    });
+    println!("{} hydrogen bonds", bonds);
+}
@@ -0,0 +1,575 @@
+use std::collections::HashSet;
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+use std::str::FromStr;
+
+use crate::json::Json;
+use crate::scene::Structure;
+use crate::sequence::{self, Kind, Sequence};
+
+/// Penalty for opening a gap and for each further position it spans.
+const GAP_OPEN: i32 = 10;
+const GAP_EXTEND: i32 = 1;
+/// Scores of identical and different bases, as in the NUC.4.4 matrix.
+const NUCLEOTIDE_MATCH: i32 = 5;
+const NUCLEOTIDE_MISMATCH: i32 = -4;
+/// Residues per line of FASTA and Clustal output.
+const LINE_WIDTH: usize = 60;
+/// Stands in for minus infinity without overflowing when penalties are subtracted.
+const UNREACHABLE: i32 = i32::MIN / 4;
+
+const BLOSUM62_ORDER: &str = "ARNDCQEGHILKMFPSTWYVBZX";
+#[rustfmt::skip]
+const BLOSUM62: [[i8; 23]; 23] = [
+    [ 4, -1, -2, -2,  0, -1, -1,  0, -2, -1, -1, -1, -1, -2, -1,  1,  0, -3, -2,  0, -2, -1,  0],
+    [-1,  5,  0, -2, -3,  1,  0, -2,  0, -3, -2,  2, -1, -3, -2, -1, -1, -3, -2, -3, -1,  0, -1],
+    [-2,  0,  6,  1, -3,  0,  0,  0,  1, -3, -3,  0, -2, -3, -2,  1,  0, -4, -2, -3,  3,  0, -1],
+    [-2, -2,  1,  6, -3,  0,  2, -1, -1, -3, -4, -1, -3, -3, -1,  0, -1, -4, -3, -3,  4,  1, -1],
+    [ 0, -3, -3, -3,  9, -3, -4, -3, -3, -1, -1, -3, -1, -2, -3, -1, -1, -2, -2, -1, -3, -3, -2],
+    [-1,  1,  0,  0, -3,  5,  2, -2,  0, -3, -2,  1,  0, -3, -1,  0, -1, -2, -1, -2,  0,  3, -1],
+    [-1,  0,  0,  2, -4,  2,  5, -2,  0, -3, -3,  1, -2, -3, -1,  0, -1, -3, -2, -2,  1,  4, -1],
+    [ 0, -2,  0, -1, -3, -2, -2,  6, -2, -4, -4, -2, -3, -3, -2,  0, -2, -2, -3, -3, -1, -2, -1],
+    [-2,  0,  1, -1, -3,  0,  0, -2,  8, -3, -3, -1, -2, -1, -2, -1, -2, -2,  2, -3,  0,  0, -1],
+    [-1, -3, -3, -3, -1, -3, -3, -4, -3,  4,  2, -3,  1,  0, -3, -2, -1, -3, -1,  3, -3, -3, -1],
+    [-1, -2, -3, -4, -1, -2, -3, -4, -3,  2,  4, -2,  2,  0, -3, -2, -1, -2, -1,  1, -4, -3, -1],
+    [-1,  2,  0, -1, -3,  1,  1, -2, -1, -3, -2,  5, -1, -3, -1,  0, -1, -3, -2, -2,  0,  1, -1],
+    [-1, -1, -2, -3, -1,  0, -2, -3, -2,  1,  2, -1,  5,  0, -2, -1, -1, -1, -1,  1, -3, -1, -1],
+    [-2, -3, -3, -3, -2, -3, -3, -3, -1,  0,  0, -3,  0,  6, -4, -2, -2,  1,  3, -1, -3, -3, -1],
+    [-1, -2, -2, -1, -3, -1, -1, -2, -2, -3, -3, -1, -2, -4,  7, -1, -1, -4, -3, -2, -2, -1, -2],
+    [ 1, -1,  1,  0, -1,  0,  0,  0, -1, -2, -2,  0, -1, -2, -1,  4,  1, -3, -2, -2,  0,  0,  0],
+    [ 0, -1,  0, -1, -1, -1, -1, -2, -2, -1, -1, -1, -1, -2, -1,  1,  5, -2, -2,  0, -1, -1,  0],
+    [-3, -3, -4, -4, -2, -2, -3, -2, -2, -3, -2, -3, -1,  1, -4, -3, -2, 11,  2, -3, -4, -3, -2],
+    [-2, -2, -2, -3, -2, -1, -2, -3,  2, -1, -1, -2, -1,  3, -3, -2, -2,  2,  7, -1, -3, -2, -1],
+    [ 0, -3, -3, -3, -1, -2, -2, -3, -3,  3,  1, -2,  1, -1, -2, -2,  0, -3, -1,  4, -3, -2, -1],
+    [-2, -1,  3,  4, -3,  0,  1, -1,  0, -3, -4,  0, -3, -3, -2,  0, -1, -4, -3, -3,  4,  1, -1],
+    [-1,  0,  0,  1, -3,  3,  4, -2,  0, -3, -3,  1, -1, -3, -1,  0, -1, -3, -2, -2,  1,  4, -1],
+    [ 0, -1, -1, -1, -2, -1, -1, -1, -1, -1, -1, -1, -1, -1, -2,  0,  0, -2, -1, -1, -1, -1, -1],
+];
+
+/// Residue groups Clustal marks with `:` and `.` when a column stays within one of them.
+const STRONG_GROUPS: &[&str] = &[
+    "STA", "NEQK", "NHQK", "NDEQ", "QHRK", "MILV", "MILF", "HY", "FYW",
+];
+const WEAK_GROUPS: &[&str] = &[
+    "CSA", "ATV", "SAG", "STNK", "STPA", "SGND", "SNDEQK", "NDEQHK", "NEQHRK", "FVLIM", "HFY",
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Mode {
+    /// Needleman–Wunsch, end to end.
+    #[default]
+    Global,
+    /// Smith–Waterman, the best scoring stretch only.
+    Local,
+}
+
+impl Mode {
+    pub fn name(self) -> &'static str {
+        match self {
+            Mode::Global => "global",
+            Mode::Local => "local",
+        }
+    }
+}
+
+impl FromStr for Mode {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "global" | "nw" | "needleman-wunsch" => Ok(Mode::Global),
+            "local" | "sw" | "smith-waterman" => Ok(Mode::Local),
+            _ => Err(format!(
+                "Unknown alignment '{}', expected global or local",
+                name
+            )),
+        }
+    }
+}
+
+/// Score of pairing two one letter codes: BLOSUM62 for proteins, with selenocysteine and
+/// pyrrolysine scored as their parents and anything unknown as X.
+pub fn score(a: char, b: char, kind: Kind) -> i32 {
+    match kind {
+        Kind::NucleicAcid if a == b => NUCLEOTIDE_MATCH,
+        Kind::NucleicAcid => NUCLEOTIDE_MISMATCH,
+        Kind::Protein => {
+            let index = |code: char| {
+                let code = match code {
+                    'U' => 'C',
+                    'O' => 'K',
+                    code => code,
+                };
+                BLOSUM62_ORDER
+                    .find(code)
+                    .unwrap_or(BLOSUM62_ORDER.len() - 1)
+            };
+            BLOSUM62[index(a)][index(b)] as i32
+        }
+    }
+}
+
+/// Two chain sequences lined up column by column.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Alignment {
+    pub first: Sequence,
+    pub second: Sequence,
+    pub mode: Mode,
+    /// The monomer of each sequence in every column, `None` for a gap.
+    pub columns: Vec<(Option<usize>, Option<usize>)>,
+    pub score: i32,
+}
+
+impl Alignment {
+    /// Both sequences as aligned one letter rows, `-` for gaps.
+    pub fn rows(&self) -> [String; 2] {
+        let row = |sequence: &Sequence, monomer: Option<usize>| {
+            monomer.map_or('-', |monomer| sequence.monomers[monomer].code)
+        };
+        [
+            self.columns
+                .iter()
+                .map(|&(first, _)| row(&self.first, first))
+                .collect(),
+            self.columns
+                .iter()
+                .map(|&(_, second)| row(&self.second, second))
+                .collect(),
+        ]
+    }
+
+    /// Columns pairing two residues.
+    fn paired(&self) -> impl Iterator<Item = (usize, usize)> + '_ {
+        self.columns
+            .iter()
+            .filter_map(|&(first, second)| Some((first?, second?)))
+    }
+
+    /// Percentage of paired columns holding the same residue.
+    pub fn identity(&self) -> f32 {
+        let (paired, identical) = self.paired().fold((0, 0), |(paired, identical), (a, b)| {
+            let same = self.first.monomers[a].code == self.second.monomers[b].code;
+            (paired + 1, identical + same as usize)
+        });
+        100.0 * identical as f32 / paired.max(1) as f32
+    }
+
+    /// Paired residues modelled in both structures, as the first atom of each in its
+    /// structure's `atoms`.
+    pub fn residue_pairs(&self) -> Vec<(usize, usize)> {
+        self.paired()
+            .filter_map(|(a, b)| {
+                Some((self.first.monomers[a].atom?, self.second.monomers[b].atom?))
+            })
+            .collect()
+    }
+}
+
+/// Aligns two sequences with affine gap penalties (Gotoh's form of Needleman–Wunsch or
+/// Smith–Waterman), scoring pairs with `score` for the first sequence's kind.
+pub fn align(first: &Sequence, second: &Sequence, mode: Mode) -> Alignment {
+    // Best scores ending in a pair, in a gap in the second sequence and in a gap in the first,
+    // each with the state it was reached from
+    const PAIR: u8 = 0;
+    const GAP_SECOND: u8 = 1;
+    const GAP_FIRST: u8 = 2;
+    const START: u8 = 3;
+
+    let a = first.letters().chars().collect::<Vec<_>>();
+    let b = second.letters().chars().collect::<Vec<_>>();
+    let (n, m) = (a.len(), b.len());
+    let cell = |i: usize, j: usize| i * (m + 1) + j;
+    let mut scores = [0, 1, 2].map(|_| vec![UNREACHABLE; (n + 1) * (m + 1)]);
+    let mut from = [0, 1, 2].map(|_| vec![START; (n + 1) * (m + 1)]);
+
+    if mode == Mode::Global {
+        scores[PAIR as usize][0] = 0;
+        for i in 1..=n {
+            scores[GAP_SECOND as usize][cell(i, 0)] = -GAP_OPEN - (i as i32 - 1) * GAP_EXTEND;
+            from[GAP_SECOND as usize][cell(i, 0)] = if i == 1 { PAIR } else { GAP_SECOND };
+        }
+        for j in 1..=m {
+            scores[GAP_FIRST as usize][cell(0, j)] = -GAP_OPEN - (j as i32 - 1) * GAP_EXTEND;
+            from[GAP_FIRST as usize][cell(0, j)] = if j == 1 { PAIR } else { GAP_FIRST };
+        }
+    }
+
+    // Best of the three states at a cell, less the penalty of moving on from each
+    let best = |scores: &[Vec<i32>; 3], index: usize, penalties: [i32; 3]| {
+        (0..3)
+            .map(|state| (scores[state][index] - penalties[state], state as u8))
+            .max_by_key(|&(score, state)| (score, std::cmp::Reverse(state)))
+            .unwrap()
+    };
+
+    let mut end = (0, 0);
+    let mut end_score = 0;
+    for i in 1..=n {
+        for j in 1..=m {
+            let (mut previous, mut state) = best(&scores, cell(i - 1, j - 1), [0; 3]);
+            if mode == Mode::Local && previous < 0 {
+                (previous, state) = (0, START);
+            }
+            let pair = previous + score(a[i - 1], b[j - 1], first.kind);
+            scores[PAIR as usize][cell(i, j)] = pair;
+            from[PAIR as usize][cell(i, j)] = state;
+
+            let (gap, state) = best(&scores, cell(i - 1, j), [GAP_OPEN, GAP_EXTEND, GAP_OPEN]);
+            scores[GAP_SECOND as usize][cell(i, j)] = gap;
+            from[GAP_SECOND as usize][cell(i, j)] = state;
+
+            let (gap, state) = best(&scores, cell(i, j - 1), [GAP_OPEN, GAP_OPEN, GAP_EXTEND]);
+            scores[GAP_FIRST as usize][cell(i, j)] = gap;
+            from[GAP_FIRST as usize][cell(i, j)] = state;
+
+            if mode == Mode::Local && pair > end_score {
+                (end, end_score) = ((i, j), pair);
+            }
+        }
+    }
+
+    let (mut i, mut j, mut state, score) = match mode {
+        Mode::Global => {
+            let (score, state) = best(&scores, cell(n, m), [0; 3]);
+            (n, m, state, score)
+        }
+        Mode::Local => (end.0, end.1, PAIR, end_score),
+    };
+
+    let mut columns = Vec::new();
+    while (i > 0 || j > 0) && state != START {
+        let previous = from[state as usize][cell(i, j)];
+        match state {
+            PAIR => {
+                columns.push((Some(i - 1), Some(j - 1)));
+                (i, j) = (i - 1, j - 1);
+            }
+            GAP_SECOND => {
+                columns.push((Some(i - 1), None));
+                i -= 1;
+            }
+            _ => {
+                columns.push((None, Some(j - 1)));
+                j -= 1;
+            }
+        }
+        state = previous;
+    }
+    columns.reverse();
+
+    Alignment {
+        first: first.clone(),
+        second: second.clone(),
+        mode,
+        columns,
+        score,
+    }
+}
+
+/// Aligns every polymer chain of `reference` with atoms in `mask` to the unused chain of
+/// `mobile` of the same kind it scores best with, preferring the same chain identifier.
+pub fn align_chains(
+    reference: &Structure,
+    mobile: &Structure,
+    mask: &[bool],
+    mode: Mode,
+) -> Vec<Alignment> {
+    let selected = reference
+        .atoms
+        .iter()
+        .zip(mask)
+        .filter(|(_, &hit)| hit)
+        .map(|(atom, _)| atom.residue)
+        .collect::<HashSet<_>>();
+    let mut candidates = sequence::sequences(mobile);
+    let mut alignments = Vec::new();
+
+    for first in sequence::sequences(reference) {
+        let chosen = first.monomers.iter().any(|monomer| {
+            monomer
+                .atom
+                .is_some_and(|atom| selected.contains(&reference.atoms[atom].residue))
+        });
+        if !chosen {
+            continue;
+        }
+
+        let best = candidates
+            .iter()
+            .enumerate()
+            .filter(|(_, second)| second.kind == first.kind)
+            .map(|(index, second)| (index, align(&first, second, mode)))
+            .max_by_key(|(index, alignment)| {
+                (
+                    alignment.score,
+                    alignment.second.chain_id == first.chain_id,
+                    std::cmp::Reverse(*index),
+                )
+            });
+        if let Some((index, alignment)) = best {
+            candidates.remove(index);
+            alignments.push(alignment);
+        }
+    }
+
+    alignments
+}
+
+/// Name of a structure for alignment output: its file name without extension, or `fallback`.
+pub fn name(structure: &Structure, fallback: &str) -> String {
+    structure
+        .source
+        .as_ref()
+        .and_then(|path| path.file_stem())
+        .map_or(fallback.to_string(), |stem| {
+            stem.to_string_lossy().to_string()
+        })
+}
+
+/// One named row per aligned chain, e.g. `1d66_A`, the two structures' rows alternating.
+pub fn entries(alignments: &[Alignment], [first, second]: [&str; 2]) -> Vec<(String, String)> {
+    alignments
+        .iter()
+        .flat_map(|alignment| {
+            let [first_row, second_row] = alignment.rows();
+            [
+                (format!("{}_{}", first, alignment.first.chain_id), first_row),
+                (
+                    format!("{}_{}", second, alignment.second.chain_id),
+                    second_row,
+                ),
+            ]
+        })
+        .collect()
+}
+
+/// Aligned rows as FASTA, gaps kept.
+pub fn fasta(entries: &[(String, String)]) -> String {
+    let mut text = String::new();
+    for (name, row) in entries {
+        let _ = writeln!(text, ">{}", name);
+        for line in row.as_bytes().chunks(LINE_WIDTH) {
+            let _ = writeln!(text, "{}", String::from_utf8_lossy(line));
+        }
+    }
+    text
+}
+
+/// Clustal mark of a column: `*` when identical throughout, `:` or `.` when all residues
+/// fall in one strong or weak group, blank otherwise or with a gap.
+fn conservation(column: &[char]) -> char {
+    let within = |groups: &[&str]| {
+        groups
+            .iter()
+            .any(|group| column.iter().all(|&code| group.contains(code)))
+    };
+    if column.contains(&'-') {
+        ' '
+    } else if column.iter().all(|&code| code == column[0]) {
+        '*'
+    } else if within(STRONG_GROUPS) {
+        ':'
+    } else if within(WEAK_GROUPS) {
+        '.'
+    } else {
+        ' '
+    }
+}
+
+/// Aligned rows in Clustal format, which needs every row the same length, so only one chain
+/// pair or pairs of equal length.
+pub fn clustal(entries: &[(String, String)]) -> Result<String, String> {
+    let length = entries.first().map_or(0, |(_, row)| row.len());
+    if entries.iter().any(|(_, row)| row.len() != length) {
+        return Err(
+            "Clustal output needs rows of one length, align a single chain pair".to_string(),
+        );
+    }
+    let width = entries
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0)
+        + 4;
+    let rows = entries
+        .iter()
+        .map(|(_, row)| row.chars().collect::<Vec<_>>())
+        .collect::<Vec<_>>();
+
+    let mut text = String::from("CLUSTAL W multiple sequence alignment\n\n");
+    for start in (0..length).step_by(LINE_WIDTH) {
+        let end = (start + LINE_WIDTH).min(length);
+        text.push('\n');
+        for ((name, _), row) in entries.iter().zip(&rows) {
+            let line = row[start..end].iter().collect::<String>();
+            let _ = writeln!(text, "{:width$}{}", name, line, width = width);
+        }
+        let marks = (start..end)
+            .map(|index| conservation(&rows.iter().map(|row| row[index]).collect::<Vec<_>>()))
+            .collect::<String>();
+        let _ = writeln!(text, "{:width$}{}", "", marks, width = width);
+    }
+    Ok(text)
+}
+
+/// Writes the rows as FASTA or Clustal, by the file extension.
+pub fn save(entries: &[(String, String)], path: &Path) -> Result<(), String> {
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+    let text = match extension.as_deref() {
+        Some("fasta" | "fa" | "fas" | "faa" | "fna") => fasta(entries),
+        Some("aln" | "clustal" | "clw") => clustal(entries)?,
+        _ => {
+            return Err(format!(
+                "Cannot write {}, expected .fasta, .fa, .aln or .clustal",
+                path.display()
+            ))
+        }
+    };
+    fs::write(path, text).map_err(|error| format!("Failed to save {}: {}", path.display(), error))
+}
+
+/// One line per chain pair with the score and identity.
+pub fn summary(alignments: &[Alignment]) -> String {
+    alignments
+        .iter()
+        .map(|alignment| {
+            format!(
+                "{} to {}: {} alignment, score {}, {:.1}% identity over {} modelled pairs",
+                alignment.first.chain_id,
+                alignment.second.chain_id,
+                alignment.mode.name(),
+                alignment.score,
+                alignment.identity(),
+                alignment.residue_pairs().len()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn to_json(alignments: &[Alignment]) -> Json {
+    Json::Array(
+        alignments
+            .iter()
+            .map(|alignment| {
+                let [first, second] = alignment.rows();
+                Json::object([
+                    ("first_chain", Json::from(alignment.first.chain_id.as_str())),
+                    (
+                        "second_chain",
+                        Json::from(alignment.second.chain_id.as_str()),
+                    ),
+                    ("mode", Json::from(alignment.mode.name())),
+                    ("score", Json::Number(alignment.score as f64)),
+                    ("identity", Json::from(alignment.identity())),
+                    ("first", Json::from(first.as_str())),
+                    ("second", Json::from(second.as_str())),
+                ])
+            })
+            .collect(),
+    )
+}
+
+#[test]
+fn aligns_renumbered_chains() {
+    use crate::scene::Scene;
+    use crate::selection::Selection;
+    use crate::style::Style;
+    use crate::superpose::{self, Fit};
+
+    for (row, values) in BLOSUM62.iter().enumerate() {
+        for (column, value) in values.iter().enumerate() {
+            assert_eq!(*value, BLOSUM62[column][row]);
+        }
+    }
+    assert_eq!(score('W', 'W', Kind::Protein), 11);
+    assert_eq!(score('U', 'C', Kind::Protein), 9);
+    assert_eq!(score('A', 'G', Kind::NucleicAcid), NUCLEOTIDE_MISMATCH);
+
+    let scene = Scene::open("1d66.pdb", &Style::default()).unwrap();
+    let reference = scene.structure.as_ref().unwrap();
+
+    // A copy of chain A with three residues cut out and every residue renumbered
+    let mut pdb = reference.pdb.clone();
+    pdb.remove_chains_by(|chain| chain.id() != "A");
+    for chain in pdb.chains_mut().filter(|chain| chain.id() == "A") {
+        chain.remove_residues_by(|residue| (30..=32).contains(&residue.serial_number()));
+        for residue in chain.residues_mut() {
+            residue.set_serial_number(residue.serial_number() + 100);
+        }
+    }
+    let mobile = Structure::new(pdb, &Style::default());
+
+    let chain: Selection = "chain A".parse().unwrap();
+    let mask = reference.select(&chain);
+    let alignments = align_chains(reference, &mobile, &mask, Mode::Global);
+    assert_eq!(alignments.len(), 1);
+    let alignment = &alignments[0];
+    assert_eq!(alignment.second.chain_id, "A");
+    assert_eq!(alignment.identity(), 100.0);
+    let [first, second] = alignment.rows();
+    assert_eq!(first.len(), second.len());
+    assert_eq!(first.replace('-', ""), alignment.first.letters());
+    assert!(second.contains("---"));
+    let modelled = alignment
+        .first
+        .monomers
+        .iter()
+        .filter(|monomer| monomer.observed())
+        .count();
+    assert_eq!(alignment.residue_pairs().len(), modelled - 3);
+
+    // Numbering no longer matches, the alignment still pairs every alpha carbon
+    assert!(superpose::match_atoms(reference, &mobile, &chain, Fit::Alpha).is_empty());
+    let residues = alignment.residue_pairs();
+    let pairs = superpose::match_residues(reference, &mobile, &chain, Fit::Alpha, &residues);
+    assert_eq!(pairs.len(), modelled - 3);
+    for &(a, b) in &pairs {
+        assert_eq!(
+            reference.atoms[a].residue_name,
+            mobile.atoms[b].residue_name
+        );
+        assert_eq!(reference.atoms[a].name, "CA");
+    }
+    let mut mobile = mobile;
+    assert!(
+        superpose::superpose(reference, &mut mobile, pairs)
+            .unwrap()
+            .rmsd
+            < 1e-3
+    );
+
+    // A local alignment keeps to the modelled stretch the two share
+    let local = align(&alignment.first, &alignment.second, Mode::Local);
+    assert!(local.columns.len() < alignment.columns.len());
+    assert_eq!(local.residue_pairs(), alignment.residue_pairs());
+
+    let entries = entries(&alignments, ["1d66", "cut"]);
+    assert!(fasta(&entries).starts_with(">1d66_A\n"));
+    let clustal = clustal(&entries).unwrap();
+    assert!(clustal.starts_with("CLUSTAL"));
+    assert!(clustal.lines().any(|line| line.starts_with("cut_A")));
+    assert!(clustal.contains('*'));
+    assert_eq!(conservation(&['I', 'L']), ':');
+    assert!("sw".parse::<Mode>().is_ok() && "blast".parse::<Mode>().is_err());
+}
+
+#[test]
+fn parses_align_commands() {
+    use crate::script::ScriptCommand;
+    use crate::superpose::Fit;
+    use std::path::PathBuf;
+
+    assert_eq!(
+        ScriptCommand::parse("align homolog.pdb local pairs.aln"),
+        Ok(Some(ScriptCommand::Align(
+            Some(PathBuf::from("homolog.pdb")),
+            Mode::Local,
+            Fit::Alpha,
+            Some(PathBuf::from("pairs.aln"))
+        )))
+    );
+}
@@ -0,0 +1,286 @@
+use std::fmt::Write as _;
+
+use pdbtbx::Element;
+
+use crate::hbond;
+use crate::json::Json;
+use crate::sasa;
+use crate::scene::{Contact, ContactKind, SceneAtom, Structure, BOND_TOLERANCE};
+use crate::spatial::Grid;
+
+/// Overlap of van der Waals spheres tolerated before a pair counts as clashing, in Å. The
+/// MolProbity clashscore uses the same cutoff.
+pub const DEFAULT_TOLERANCE: f32 = 0.4;
+/// Extra overlap allowed between hydrogen bonding partners, which sit inside each other's
+/// van der Waals spheres by design.
+const HYDROGEN_BOND_OVERLAP: f32 = 0.6;
+
+/// Two atoms closer than their van der Waals radii allow.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Clash {
+    /// Indices into `Structure::atoms`, the first one lower.
+    pub first: usize,
+    pub second: usize,
+    pub distance: f32,
+    /// How far the van der Waals spheres interpenetrate, in Å.
+    pub overlap: f32,
+}
+
+/// Non-bonded pairs of atoms in `mask` overlapping by more than `tolerance`, worst first.
+/// Covalently bonded atoms (1–2 pairs) and atoms sharing a bonded neighbour (1–3 pairs) are
+/// left out. The ends of a torsion (1–4 pairs), which ring geometry holds in contact, are
+/// left out too unless `torsions` is set, as in Probe.
+pub fn find(structure: &Structure, mask: &[bool], tolerance: f32, torsions: bool) -> Vec<Clash> {
+    let atoms = &structure.atoms;
+    let positions = atoms.iter().map(|atom| atom.position).collect::<Vec<_>>();
+    let radii = atoms
+        .iter()
+        .map(|atom| sasa::radius(&atom.element))
+        .collect::<Vec<_>>();
+    let reach = 2.0 * radii.iter().copied().fold(0.0, f32::max);
+    let pairs = Grid::new(&positions, reach).pairs(reach);
+
+    // Bonds from distances alone, across residues too, so that nucleic acid backbones,
+    // disulfides and ligand links are not mistaken for clashes. They are found among all atoms,
+    // as the neighbour two atoms in the mask share may lie outside it.
+    let mut neighbours = vec![Vec::new(); atoms.len()];
+    for &(i, j, length) in &pairs {
+        let (a, b) = (&atoms[i], &atoms[j]);
+        let limit = (a.element.atomic_radius().covalent_single
+            + b.element.atomic_radius().covalent_single) as f32
+            + BOND_TOLERANCE;
+        if length < limit {
+            neighbours[i].push(j);
+            neighbours[j].push(i);
+        }
+    }
+    let excluded = |i: usize, j: usize| {
+        neighbours[i].iter().any(|&second| {
+            second == j
+                || neighbours[second]
+                    .iter()
+                    .any(|&third| third == j || (!torsions && neighbours[third].contains(&j)))
+        })
+    };
+
+    // Hydrogens donate on behalf of the heavy atom they are bonded to
+    let (donors, acceptors): (Vec<_>, Vec<_>) = atoms
+        .iter()
+        .enumerate()
+        .map(|(i, atom)| {
+            if atom.element == Element::H {
+                let donor = neighbours[i].iter().any(|&heavy| {
+                    let heavy = &atoms[heavy];
+                    hbond::classify(&heavy.residue_name, &heavy.name).0
+                });
+                (donor, false)
+            } else {
+                hbond::classify(&atom.residue_name, &atom.name)
+            }
+        })
+        .unzip();
+
+    let mut clashes = pairs
+        .into_iter()
+        .filter(|&(i, j, _)| mask[i] && mask[j] && !excluded(i, j))
+        .filter_map(|(i, j, length)| {
+            let hydrogen_bond = (donors[i] && acceptors[j]) || (donors[j] && acceptors[i]);
+            let allowed = tolerance
+                + if hydrogen_bond {
+                    HYDROGEN_BOND_OVERLAP
+                } else {
+                    0.0
+                };
+            let overlap = radii[i] + radii[j] - length;
+            (overlap > allowed).then_some(Clash {
+                first: i,
+                second: j,
+                distance: length,
+                overlap,
+            })
+        })
+        .collect::<Vec<_>>();
+    clashes.sort_by(|a, b| b.overlap.total_cmp(&a.overlap));
+
+    clashes
+}
+
+/// Clashes per thousand atoms checked, comparable to the MolProbity clashscore.
+pub fn score(clashes: &[Clash], atom_count: usize) -> f32 {
+    1000.0 * clashes.len() as f32 / atom_count.max(1) as f32
+}
+
+/// Discs drawn halfway between each pair of clashing atoms.
+pub fn contacts(structure: &Structure, clashes: &[Clash]) -> Vec<Contact> {
+    clashes
+        .iter()
+        .map(|clash| Contact {
+            kind: ContactKind::Clash,
+            start: structure.atoms[clash.first].position,
+            end: structure.atoms[clash.second].position,
+            atom: clash.first,
+        })
+        .collect()
+}
+
+/// "A:ARG 12 NH1" for an atom.
+fn name(atom: &SceneAtom) -> String {
+    format!("{} {}", atom.describe(), atom.name)
+}
+
+/// Plain text summary with the clash score and every clashing pair, worst first.
+pub fn report(structure: &Structure, clashes: &[Clash], atom_count: usize) -> String {
+    let mut report = format!(
+        "{} clashes among {} atoms, clash score {:.1}\n",
+        clashes.len(),
+        atom_count,
+        score(clashes, atom_count)
+    );
+    for clash in clashes {
+        let _ = writeln!(
+            report,
+            "  {} - {}: {:.2} Å, overlap {:.2} Å",
+            name(&structure.atoms[clash.first]),
+            name(&structure.atoms[clash.second]),
+            clash.distance,
+            clash.overlap
+        );
+    }
+
+    report
+}
+
+pub fn to_json(structure: &Structure, clashes: &[Clash], atom_count: usize) -> Json {
+    let atom = |index: usize| {
+        let atom = &structure.atoms[index];
+        Json::object([
+            ("chain", Json::from(atom.chain_id.as_str())),
+            ("residue", Json::from(atom.residue_name.as_str())),
+            ("number", Json::Number(atom.residue_number as f64)),
+            ("atom", Json::from(atom.name.as_str())),
+        ])
+    };
+
+    Json::object([
+        ("atoms", Json::from(atom_count)),
+        ("score", Json::from(score(clashes, atom_count))),
+        (
+            "clashes",
+            Json::Array(
+                clashes
+                    .iter()
+                    .map(|clash| {
+                        Json::object([
+                            ("first", atom(clash.first)),
+                            ("second", atom(clash.second)),
+                            ("distance", Json::from(clash.distance)),
+                            ("overlap", Json::from(clash.overlap)),
+                        ])
+                    })
+                    .collect(),
+            ),
+        ),
+    ])
+}
+
+#[test]
+fn flags_only_non_bonded_overlaps() {
+    use crate::scene::Scene;
+    use crate::style::Style;
+
+    let mut scene = Scene::open("1d66.pdb", &Style::default()).unwrap();
+    let structure = scene.structure.as_mut().unwrap();
+    let everything = vec![true; structure.atoms.len()];
+    // A refined crystal structure has few clashes, and none between bonded neighbours
+    let clashes = find(structure, &everything, DEFAULT_TOLERANCE, false);
+    assert!(score(&clashes, everything.len()) < 25.0);
+    assert!(clashes.iter().all(|clash| clash.distance > 2.0));
+
+    // Pushing a side chain into its neighbour makes a clash that a loose tolerance forgives
+    let (moved, target) = (0..structure.atoms.len())
+        .flat_map(|i| (i + 1..structure.atoms.len()).map(move |j| (i, j)))
+        .find(|&(i, j)| {
+            let (a, b) = (&structure.atoms[i], &structure.atoms[j]);
+            a.residue + 5 < b.residue
+                && a.chain == b.chain
+                && a.name == "CB"
+                && b.name == "CB"
+                && crate::math::distance(a.position, b.position) < 7.0
+        })
+        .unwrap();
+    let toward = structure.atoms[target].position;
+    let from = structure.atoms[moved].position;
+    let length = crate::math::distance(from, toward);
+    structure.atoms[moved].position =
+        [0, 1, 2].map(|axis| toward[axis] + (from[axis] - toward[axis]) * 2.5 / length);
+
+    let clashes = find(structure, &everything, DEFAULT_TOLERANCE, false);
+    let planted = clashes
+        .iter()
+        .find(|clash| (clash.first, clash.second) == (moved, target))
+        .unwrap();
+    assert!((planted.distance - 2.5).abs() < 1e-3);
+    let radius = sasa::radius(&Element::C);
+    assert!((planted.overlap - (2.0 * radius - 2.5)).abs() < 1e-3);
+    assert!(!find(structure, &everything, 2.0 * radius - 2.4, false)
+        .iter()
+        .any(|clash| (clash.first, clash.second) == (moved, target)));
+    assert!(report(structure, &clashes, everything.len()).contains("clash score"));
+
+    // Checking 1–4 pairs finds the ring atoms across from each other, but never 1–3 pairs
+    let with_torsions = find(structure, &everything, DEFAULT_TOLERANCE, true);
+    assert!(with_torsions.len() > clashes.len());
+    assert!(with_torsions.iter().all(|clash| clash.distance > 2.0));
+}
+
+#[test]
+fn keeps_bonds_through_atoms_outside_the_selection() {
+    use crate::scene::Structure;
+    use crate::selection::Selection;
+    use crate::style::Style;
+
+    // N and C of a residue are 2.4 Å apart and only bonded through CA, so leaving CA out of the
+    // selection must not turn them into a clash
+    let structure = Structure::open("1d66.pdb", &Style::default()).unwrap();
+    let selection: Selection = "not name CA".parse().unwrap();
+    let mask = structure.select(&selection);
+    let clashes = find(&structure, &mask, DEFAULT_TOLERANCE, false);
+    assert!(clashes
+        .iter()
+        .all(|clash| mask[clash.first] && mask[clash.second]));
+    assert!(clashes.iter().all(|clash| clash.distance > 2.0));
+    assert!(!clashes.iter().any(|clash| {
+        let (a, b) = (
+            &structure.atoms[clash.first],
+            &structure.atoms[clash.second],
+        );
+        a.residue == b.residue && a.name == "N" && b.name == "C"
+    }));
+}
+
+#[test]
+fn parses_clash_commands() {
+    use crate::script::ScriptCommand;
+    use std::path::PathBuf;
+
+    assert_eq!(
+        ScriptCommand::parse("clashes 0.5 clashes.json"),
+        Ok(Some(ScriptCommand::Clashes(
+            0.5,
+            false,
+            Some(PathBuf::from("clashes.json"))
+        )))
+    );
+    assert_eq!(
+        ScriptCommand::parse("clashes clashes.txt torsions"),
+        Ok(Some(ScriptCommand::Clashes(
+            DEFAULT_TOLERANCE,
+            true,
+            Some(PathBuf::from("clashes.txt"))
+        )))
+    );
+    assert_eq!(
+        ScriptCommand::parse("clashes torsions"),
+        Ok(Some(ScriptCommand::Clashes(DEFAULT_TOLERANCE, true, None)))
+    );
+}
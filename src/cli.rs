@@ -0,0 +1,206 @@
+use std::path::PathBuf;
+
+use crate::opengl::{RenderSettings, ViewerOptions};
+use crate::quality::QualityPreset;
+use crate::selection::Selection;
+use crate::style::{parse_color, ColorScheme, Representation, Style};
+
+pub const USAGE: &str = "\
+Usage: biopix [view] <file.pdb | script.bpx | session.bps> [options]
+       biopix render <file.pdb | script.bpx | session.bps> [--output <image.png>] [options]
+
+Options:
+  -r, --representation <name>  spacefill, ball-and-stick, cartoon or surface
+  -c, --color <scheme>         element, chain, residue, bfactor, or a color for every atom
+  -s, --select <query>         atoms to show, e.g. \"chain A and resi 10-50\"
+      --orient <turn>,<tilt>   initial rotation in degrees
+      --zoom <factor>          initial magnification
+      --size <width>x<height>  window or image size in pixels
+      --background <color>     #rrggbb, r,g,b in 0..1 or a color name
+      --quality <preset>       draft, normal or publication
+      --shader-dir <dir>       load shaders from <dir> and reload them on change
+      --script <script.bpx>    run the commands in <script.bpx> once the view is ready
+      --session <session.bps>  restore a view saved with the save command
+      --control <address>      accept JSON-RPC requests on a Unix socket path or localhost:<port>
+  -o, --output <image.png>     image written by render
+  -h, --help                   show this message";
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    /// Opens an interactive window.
+    View(Options),
+    /// Runs the script and writes `viewer.output` without showing a window.
+    Render(Options),
+    Help,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Options {
+    /// Structure opened before the script runs, if any.
+    pub file: Option<String>,
+    pub quality: QualityPreset,
+    pub representation: Representation,
+    pub color_scheme: ColorScheme,
+    pub selection: Option<Selection>,
+    pub background: Option<[f32; 3]>,
+    pub viewer: ViewerOptions,
+}
+
+impl Options {
+    pub fn render_settings(&self) -> RenderSettings {
+        let mut settings = RenderSettings::from(self.quality);
+        if let Some(background) = self.background {
+            settings.background = background;
+        }
+
+        settings
+    }
+
+    pub fn style(&self) -> Style {
+        Style {
+            representation: self.representation,
+            color_scheme: self.color_scheme,
+            selection: self.selection.clone(),
+            sphere_detail: self.quality.sphere_detail(),
+        }
+    }
+}
+
+/// Parses the arguments following the program name. A bare file name opens the viewer.
+pub fn parse(args: &[String]) -> Result<Command, String> {
+    let (render, args) = match args.first().map(String::as_str) {
+        None | Some("-h" | "--help" | "help") => return Ok(Command::Help),
+        Some("view") => (false, &args[1..]),
+        Some("render") => (true, &args[1..]),
+        Some(_) => (false, args),
+    };
+
+    let mut options = Options {
+        file: None,
+        quality: QualityPreset::default(),
+        representation: Representation::default(),
+        color_scheme: ColorScheme::default(),
+        selection: None,
+        background: None,
+        viewer: ViewerOptions::default(),
+    };
+
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        let mut value = || {
+            args.next()
+                .map(String::as_str)
+                .ok_or_else(|| format!("Missing value for {}", arg))
+        };
+
+        match arg.as_str() {
+            "-h" | "--help" => return Ok(Command::Help),
+            "-r" | "--representation" => options.representation = value()?.parse()?,
+            "-c" | "--color" => options.color_scheme = value()?.parse()?,
+            "-s" | "--select" => options.selection = Some(value()?.parse()?),
+            "--orient" => {
+                let [turn, tilt] = parse_pair(value()?, ',')
+                    .ok_or_else(|| "Expected --orient <turn>,<tilt> in degrees".to_string())?;
+                options.viewer.orientation = [turn.to_radians(), tilt.to_radians()];
+            }
+            "--zoom" => {
+                options.viewer.zoom = value()?
+                    .parse()
+                    .ok()
+                    .filter(|zoom: &f32| *zoom > 0.0)
+                    .ok_or_else(|| "Expected a positive --zoom factor".to_string())?;
+            }
+            "--size" => {
+                let [width, height] = parse_pair(value()?, 'x')
+                    .filter(|size| size.iter().all(|&side| side >= 1.0))
+                    .ok_or_else(|| "Expected --size <width>x<height>".to_string())?;
+                options.viewer.window_size = Some((width as u32, height as u32));
+            }
+            "--background" => options.background = Some(parse_color(value()?)?),
+            "--quality" => options.quality = value()?.parse()?,
+            "--shader-dir" => options.viewer.shader_dir = Some(PathBuf::from(value()?)),
+            "-o" | "--output" => options.viewer.output = Some(PathBuf::from(value()?)),
+            "--script" => options.viewer.script = Some(PathBuf::from(value()?)),
+            "--session" => options.viewer.session = Some(PathBuf::from(value()?)),
+            "--control" => options.viewer.control = Some(value()?.parse()?),
+            flag if flag.starts_with('-') && flag.len() > 1 => {
+                return Err(format!("Unknown option {}", flag));
+            }
+            path if path.ends_with(".bpx") && options.viewer.script.is_none() => {
+                options.viewer.script = Some(PathBuf::from(path));
+            }
+            path if path.ends_with(".bps") && options.viewer.session.is_none() => {
+                options.viewer.session = Some(PathBuf::from(path));
+            }
+            path if options.file.is_none() => options.file = Some(path.to_string()),
+            extra => return Err(format!("Unexpected argument {}", extra)),
+        }
+    }
+
+    if options.file.is_none()
+        && options.viewer.script.is_none()
+        && options.viewer.session.is_none()
+        && options.viewer.control.is_none()
+    {
+        return Err("Missing structure file, script, session or control address".to_string());
+    }
+
+    // An output path alone also renders headless, so scripts need not name the subcommand
+    let render = render || options.viewer.output.is_some();
+    if render
+        && options.viewer.output.is_none()
+        && options.viewer.script.is_none()
+        && options.viewer.control.is_none()
+    {
+        return Err("render needs --output <image.png>, a script or --control".to_string());
+    }
+    options.viewer.headless = render;
+
+    if render {
+        Ok(Command::Render(options))
+    } else {
+        Ok(Command::View(options))
+    }
+}
+
+fn parse_pair(value: &str, separator: char) -> Option<[f32; 2]> {
+    let (first, second) = value.split_once(separator)?;
+
+    Some([first.trim().parse().ok()?, second.trim().parse().ok()?])
+}
+
+#[test]
+fn parses_render_command() {
+    let args = [
+        "render", "1d66.pdb", "-r", "cartoon", "-c", "chain", "-s", "chain A", "--orient",
+        "90,-45", "--size", "640x480", "-o", "out.png",
+    ]
+    .map(String::from);
+
+    let Ok(Command::Render(options)) = parse(&args) else {
+        panic!("expected a render command");
+    };
+    assert_eq!(options.file.as_deref(), Some("1d66.pdb"));
+    assert!(options.viewer.headless);
+    assert_eq!(options.representation, Representation::Cartoon);
+    assert_eq!(options.color_scheme, ColorScheme::Chain);
+    assert_eq!(
+        options.selection,
+        Some(Selection::Chain(vec!["A".to_string()]))
+    );
+    assert_eq!(options.viewer.window_size, Some((640, 480)));
+    assert!((options.viewer.orientation[1] + std::f32::consts::FRAC_PI_4).abs() < 1e-6);
+
+    assert!(matches!(
+        parse(&["1d66.pdb".to_string()]),
+        Ok(Command::View(_))
+    ));
+    assert!(parse(&["render".to_string(), "1d66.pdb".to_string()]).is_err());
+
+    let Ok(Command::Render(options)) = parse(&["render".to_string(), "figure.bpx".to_string()])
+    else {
+        panic!("a script alone can render");
+    };
+    assert_eq!(options.file, None);
+    assert_eq!(options.viewer.script, Some(PathBuf::from("figure.bpx")));
+}
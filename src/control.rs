@@ -0,0 +1,640 @@
+use std::fs;
+use std::io::{self, BufRead, BufReader, Write};
+use std::net::{Ipv4Addr, TcpListener};
+#[cfg(unix)]
+use std::os::unix::fs::FileTypeExt;
+#[cfg(unix)]
+use std::os::unix::net::UnixListener;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::{mpsc, Arc};
+use std::thread;
+
+use crate::align;
+use crate::clash;
+use crate::hbond;
+use crate::interaction;
+use crate::interface;
+use crate::json::Json;
+use crate::opengl::Renderer;
+use crate::ramachandran;
+use crate::sasa;
+use crate::script::{Interpreter, ScriptCommand};
+use crate::sequence;
+use crate::style::parse_color;
+use crate::superpose;
+
+/// Where the control server listens: a Unix domain socket or a TCP port on localhost.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ControlAddress {
+    Unix(PathBuf),
+    Tcp(u16),
+}
+
+impl FromStr for ControlAddress {
+    type Err = String;
+
+    /// Accepts `tcp:<port>`, `localhost:<port>` or a bare port for TCP, and `unix:<path>` or
+    /// any other path for a Unix socket.
+    fn from_str(address: &str) -> Result<Self, Self::Err> {
+        let port = |port: &str| {
+            port.parse()
+                .map(ControlAddress::Tcp)
+                .map_err(|_| format!("Invalid control port '{}'", port))
+        };
+
+        if let Some(path) = address.strip_prefix("unix:") {
+            return Ok(ControlAddress::Unix(PathBuf::from(path)));
+        }
+        if let Some(rest) = address
+            .strip_prefix("tcp:")
+            .or_else(|| address.strip_prefix("localhost:"))
+            .or_else(|| address.strip_prefix("127.0.0.1:"))
+        {
+            return port(rest);
+        }
+        if address.chars().all(|c| c.is_ascii_digit()) && !address.is_empty() {
+            return port(address);
+        }
+
+        Ok(ControlAddress::Unix(PathBuf::from(address)))
+    }
+}
+
+/// JSON-RPC 2.0 error, sent back instead of a result.
+#[derive(Debug, Clone, PartialEq)]
+pub struct RpcError {
+    pub code: i32,
+    pub message: String,
+}
+
+impl RpcError {
+    pub const PARSE_ERROR: i32 = -32700;
+    pub const INVALID_REQUEST: i32 = -32600;
+    pub const METHOD_NOT_FOUND: i32 = -32601;
+    pub const INVALID_PARAMS: i32 = -32602;
+    /// The request was understood but the command failed.
+    pub const FAILED: i32 = -32000;
+
+    pub fn new(code: i32, message: impl Into<String>) -> RpcError {
+        RpcError {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+/// A request read from a client, waiting for the event loop to answer it.
+pub struct ControlRequest {
+    pub method: String,
+    pub params: Json,
+    id: Json,
+    reply: mpsc::Sender<String>,
+}
+
+impl ControlRequest {
+    pub fn respond(self, result: Result<Json, RpcError>) {
+        let _ = self.reply.send(response(&self.id, result));
+    }
+}
+
+fn response(id: &Json, result: Result<Json, RpcError>) -> String {
+    let outcome = match result {
+        Ok(result) => ("result", result),
+        Err(error) => (
+            "error",
+            Json::object([
+                ("code", Json::Number(error.code as f64)),
+                ("message", Json::from(error.message)),
+            ]),
+        ),
+    };
+
+    Json::object([("jsonrpc", Json::from("2.0")), ("id", id.clone()), outcome]).to_string()
+}
+
+/// Background listener handing newline delimited JSON-RPC requests to the event loop. Each
+/// connection gets its own thread, which blocks until its request is answered.
+pub struct ControlServer {
+    pub address: ControlAddress,
+}
+
+impl ControlServer {
+    /// Binds `address` and starts accepting clients. `forward` passes a request on to the event
+    /// loop and returns false once the viewer is gone.
+    pub fn start<F>(address: ControlAddress, forward: F) -> io::Result<ControlServer>
+    where
+        F: Fn(ControlRequest) -> bool + Send + Sync + 'static,
+    {
+        let forward = Arc::new(forward);
+
+        match &address {
+            ControlAddress::Tcp(port) => {
+                let listener = TcpListener::bind((Ipv4Addr::LOCALHOST, *port))?;
+                thread::spawn(move || {
+                    for stream in listener.incoming().flatten() {
+                        let forward = forward.clone();
+                        thread::spawn(move || {
+                            if let Ok(reader) = stream.try_clone() {
+                                serve(BufReader::new(reader), stream, forward.as_ref());
+                            }
+                        });
+                    }
+                });
+            }
+            #[cfg(unix)]
+            ControlAddress::Unix(path) => {
+                // A socket left behind by a previous run would make bind fail, anything else at
+                // the path is not ours to delete
+                if let Ok(metadata) = fs::symlink_metadata(path) {
+                    if !metadata.file_type().is_socket() {
+                        return Err(io::Error::new(
+                            io::ErrorKind::AlreadyExists,
+                            format!("{} exists and is not a socket", path.display()),
+                        ));
+                    }
+                    fs::remove_file(path)?;
+                }
+                let listener = UnixListener::bind(path)?;
+                thread::spawn(move || {
+                    for stream in listener.incoming().flatten() {
+                        let forward = forward.clone();
+                        thread::spawn(move || {
+                            if let Ok(reader) = stream.try_clone() {
+                                serve(BufReader::new(reader), stream, forward.as_ref());
+                            }
+                        });
+                    }
+                });
+            }
+            #[cfg(not(unix))]
+            ControlAddress::Unix(_) => {
+                return Err(io::Error::new(
+                    io::ErrorKind::Unsupported,
+                    "Unix sockets are not available on this platform, use a TCP port",
+                ));
+            }
+        }
+
+        Ok(ControlServer { address })
+    }
+}
+
+impl Drop for ControlServer {
+    fn drop(&mut self) {
+        if let ControlAddress::Unix(path) = &self.address {
+            let _ = fs::remove_file(path);
+        }
+    }
+}
+
+/// Answers requests, one per line, until the client disconnects.
+fn serve(reader: impl BufRead, mut writer: impl Write, forward: &dyn Fn(ControlRequest) -> bool) {
+    for line in reader.lines() {
+        let Ok(line) = line else {
+            return;
+        };
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        let answer = match parse_request(&line) {
+            Ok((id, method, params)) => {
+                let (reply, answer) = mpsc::channel();
+                let request = ControlRequest {
+                    method,
+                    params,
+                    id: id.clone(),
+                    reply,
+                };
+
+                if forward(request) {
+                    answer.recv().ok()
+                } else {
+                    None
+                }
+                .unwrap_or_else(|| {
+                    response(
+                        &id,
+                        Err(RpcError::new(RpcError::FAILED, "The viewer has closed")),
+                    )
+                })
+            }
+            Err((id, error)) => response(&id, Err(error)),
+        };
+
+        if writeln!(writer, "{}", answer)
+            .and_then(|_| writer.flush())
+            .is_err()
+        {
+            return;
+        }
+    }
+}
+
+fn parse_request(line: &str) -> Result<(Json, String, Json), (Json, RpcError)> {
+    let request = Json::parse(line)
+        .map_err(|error| (Json::Null, RpcError::new(RpcError::PARSE_ERROR, error)))?;
+    let id = request.get("id").cloned().unwrap_or(Json::Null);
+
+    let method = request
+        .get("method")
+        .and_then(Json::as_str)
+        .ok_or_else(|| {
+            (
+                id.clone(),
+                RpcError::new(RpcError::INVALID_REQUEST, "Missing method"),
+            )
+        })?
+        .to_string();
+    let params = request
+        .get("params")
+        .cloned()
+        .unwrap_or(Json::Object(vec![]));
+
+    Ok((id, method, params))
+}
+
+/// Script command equivalent of a request, `None` for methods answered by `dispatch` itself.
+pub fn request_command(method: &str, params: &Json) -> Result<Option<ScriptCommand>, RpcError> {
+    let invalid = |message: String| RpcError::new(RpcError::INVALID_PARAMS, message);
+    let string = |key: &str| {
+        params
+            .get(key)
+            .and_then(Json::as_str)
+            .ok_or_else(|| invalid(format!("{} needs a \"{}\" string", method, key)))
+    };
+    let number = |key: &str| {
+        params
+            .get(key)
+            .and_then(Json::as_f64)
+            .map(|value| value as f32)
+            .ok_or_else(|| invalid(format!("{} needs a \"{}\" number", method, key)))
+    };
+
+    let command = match method {
+        "load" => ScriptCommand::Load(PathBuf::from(string("path")?)),
+        "select" => ScriptCommand::Select(string("selection")?.parse().map_err(invalid)?),
+        "color" => ScriptCommand::Color(string("scheme")?.parse().map_err(invalid)?),
+        "show" => ScriptCommand::Show(string("representation")?.parse().map_err(invalid)?),
+        "hide" => ScriptCommand::Hide(match params.get("representation") {
+            Some(_) => Some(string("representation")?.parse().map_err(invalid)?),
+            None => None,
+        }),
+        "label" => ScriptCommand::Label(true),
+        "unlabel" => ScriptCommand::Label(false),
+        "orient" => ScriptCommand::Orient(number("turn")?, number("tilt")?),
+        "rotate" => ScriptCommand::Rotate(number("turn")?, number("tilt")?),
+        "zoom" => match number("factor")? {
+            factor if factor > 0.0 => ScriptCommand::Zoom(factor),
+            _ => return Err(invalid("zoom needs a positive \"factor\"".to_string())),
+        },
+        "background" => ScriptCommand::Background(parse_color(string("color")?).map_err(invalid)?),
+        "screenshot" => ScriptCommand::Png(PathBuf::from(string("path")?)),
+        "export" => ScriptCommand::Export(PathBuf::from(string("path")?)),
+        "write" => ScriptCommand::Write(
+            PathBuf::from(string("path")?),
+            params
+                .get("transformed")
+                .and_then(Json::as_bool)
+                .unwrap_or(false),
+        ),
+        "save_session" => ScriptCommand::Save(PathBuf::from(string("path")?)),
+        "restore_session" => ScriptCommand::Restore(PathBuf::from(string("path")?)),
+        "run" => match ScriptCommand::parse(string("command")?).map_err(invalid)? {
+            Some(command) => command,
+            None => return Err(invalid("run needs a command".to_string())),
+        },
+        "hbonds" => ScriptCommand::HydrogenBonds(
+            params
+                .get("path")
+                .map(|_| string("path"))
+                .transpose()?
+                .map(PathBuf::from),
+        ),
+        "interactions" => ScriptCommand::Interactions(
+            params
+                .get("path")
+                .map(|_| string("path"))
+                .transpose()?
+                .map(PathBuf::from),
+        ),
+        "interface" => ScriptCommand::Interface(match params.get("cutoff") {
+            Some(_) => match number("cutoff")? {
+                cutoff if cutoff > 0.0 => cutoff,
+                _ => return Err(invalid("interface needs a positive \"cutoff\"".to_string())),
+            },
+            None => interface::DEFAULT_CUTOFF,
+        }),
+        "sasa" => ScriptCommand::SurfaceArea(
+            match params.get("method") {
+                Some(_) => string("method")?.parse().map_err(invalid)?,
+                None => sasa::Method::default(),
+            },
+            params
+                .get("path")
+                .map(|_| string("path"))
+                .transpose()?
+                .map(PathBuf::from),
+        ),
+        "clashes" => ScriptCommand::Clashes(
+            match params.get("tolerance") {
+                Some(_) => match number("tolerance")? {
+                    tolerance if tolerance >= 0.0 => tolerance,
+                    _ => return Err(invalid("clashes needs a \"tolerance\" of 0 or more".into())),
+                },
+                None => clash::DEFAULT_TOLERANCE,
+            },
+            params
+                .get("torsions")
+                .and_then(Json::as_bool)
+                .unwrap_or(false),
+            params
+                .get("path")
+                .map(|_| string("path"))
+                .transpose()?
+                .map(PathBuf::from),
+        ),
+        "superpose" => ScriptCommand::Superpose(
+            params
+                .get("path")
+                .map(|_| string("path"))
+                .transpose()?
+                .map(PathBuf::from),
+            match params.get("fit") {
+                Some(_) => string("fit")?.parse().map_err(invalid)?,
+                None => superpose::Fit::default(),
+            },
+            params
+                .get("numbered")
+                .and_then(Json::as_bool)
+                .unwrap_or(false),
+        ),
+        "align" => ScriptCommand::Align(
+            params
+                .get("path")
+                .map(|_| string("path"))
+                .transpose()?
+                .map(PathBuf::from),
+            match params.get("mode") {
+                Some(_) => string("mode")?.parse().map_err(invalid)?,
+                None => align::Mode::default(),
+            },
+            match params.get("fit") {
+                Some(_) => string("fit")?.parse().map_err(invalid)?,
+                None => superpose::Fit::default(),
+            },
+            params
+                .get("export")
+                .map(|_| string("export"))
+                .transpose()?
+                .map(PathBuf::from),
+        ),
+        "center" => ScriptCommand::Center,
+        "ramachandran" => match params.get("show").and_then(Json::as_bool) {
+            Some(false) => ScriptCommand::HideRamachandran,
+            _ => ScriptCommand::Ramachandran(
+                params
+                    .get("path")
+                    .map(|_| string("path"))
+                    .transpose()?
+                    .map(PathBuf::from),
+            ),
+        },
+        "sequence" => match params.get("show").and_then(Json::as_bool) {
+            Some(false) => ScriptCommand::HideSequence,
+            _ => ScriptCommand::Sequence,
+        },
+        "get_camera" => return Ok(None),
+        _ => {
+            return Err(RpcError::new(
+                RpcError::METHOD_NOT_FOUND,
+                format!("Unknown method '{}'", method),
+            ))
+        }
+    };
+
+    Ok(Some(command))
+}
+
+/// Carries out a request on the event loop thread.
+pub fn dispatch(
+    interpreter: &mut Interpreter,
+    renderer: &mut Renderer,
+    method: &str,
+    params: &Json,
+) -> Result<Json, RpcError> {
+    let Some(command) = request_command(method, params)? else {
+        return Ok(Json::object([
+            (
+                "turn",
+                Json::from(renderer.x_rotate.unwrap_or(0.0).to_degrees()),
+            ),
+            (
+                "tilt",
+                Json::from(renderer.y_rotate.unwrap_or(0.0).to_degrees()),
+            ),
+            ("scale", Json::from(renderer.scale)),
+            ("width", Json::from(renderer.width as f64)),
+            ("height", Json::from(renderer.height as f64)),
+        ]));
+    };
+
+    let failed = |error: String| RpcError::new(RpcError::FAILED, error);
+
+    let result = match command {
+        ScriptCommand::HydrogenBonds(table) => {
+            let bonds = interpreter
+                .hydrogen_bonds(renderer, table)
+                .map_err(failed)?;
+            renderer
+                .scene
+                .structure
+                .as_ref()
+                .map_or(Json::Null, |structure| hbond::to_json(structure, &bonds))
+        }
+        ScriptCommand::Interactions(report) => {
+            let interactions = interpreter.interactions(renderer, report).map_err(failed)?;
+            renderer
+                .scene
+                .structure
+                .as_ref()
+                .map_or(Json::Null, |structure| {
+                    interaction::to_json(structure, &interactions)
+                })
+        }
+        ScriptCommand::Interface(cutoff) => {
+            let interfaces = interpreter.interfaces(renderer, cutoff).map_err(failed)?;
+            renderer
+                .scene
+                .structure
+                .as_ref()
+                .map_or(Json::Null, |structure| {
+                    interface::to_json(structure, &interfaces)
+                })
+        }
+        ScriptCommand::SurfaceArea(method, table) => {
+            let areas = interpreter
+                .surface_areas(renderer, method, table)
+                .map_err(failed)?;
+            renderer
+                .scene
+                .structure
+                .as_ref()
+                .map_or(Json::Null, |structure| {
+                    let mask = structure.select(&interpreter.selection);
+                    sasa::to_json(structure, &areas, &mask)
+                })
+        }
+        ScriptCommand::Clashes(tolerance, torsions, report) => {
+            let (clashes, checked) = interpreter
+                .clashes(renderer, tolerance, torsions, report)
+                .map_err(failed)?;
+            renderer
+                .scene
+                .structure
+                .as_ref()
+                .map_or(Json::Null, |structure| {
+                    clash::to_json(structure, &clashes, checked)
+                })
+        }
+        ScriptCommand::Superpose(file, fit, numbered) => {
+            let superposition = interpreter
+                .superpose(renderer, file, fit, numbered)
+                .map_err(failed)?;
+            superpose::to_json(&superposition)
+        }
+        ScriptCommand::Sequence => {
+            let sequences = interpreter.sequences(renderer).map_err(failed)?;
+            sequence::to_json(&sequences)
+        }
+        ScriptCommand::Align(file, mode, fit, export) => {
+            let (alignments, superposition) = interpreter
+                .align(renderer, file, mode, fit, export)
+                .map_err(failed)?;
+            Json::object([
+                ("alignments", align::to_json(&alignments)),
+                ("superposition", superpose::to_json(&superposition)),
+            ])
+        }
+        ScriptCommand::Ramachandran(image) => {
+            let points = interpreter.ramachandran(renderer, image).map_err(failed)?;
+            renderer
+                .scene
+                .structure
+                .as_ref()
+                .map_or(Json::Null, |structure| {
+                    ramachandran::to_json(structure, &points)
+                })
+        }
+        command => {
+            let path = match &command {
+                ScriptCommand::Png(path)
+                | ScriptCommand::Export(path)
+                | ScriptCommand::Write(path, _)
+                | ScriptCommand::Save(path) => {
+                    Json::from(interpreter.base_dir.join(path).display().to_string())
+                }
+                _ => Json::Null,
+            };
+            interpreter.execute(renderer, command).map_err(failed)?;
+
+            if method == "select" {
+                let count = renderer
+                    .scene
+                    .select(&interpreter.selection)
+                    .iter()
+                    .filter(|&&hit| hit)
+                    .count();
+                Json::object([("count", Json::from(count))])
+            } else {
+                path
+            }
+        }
+    };
+
+    Ok(result)
+}
+
+#[cfg(unix)]
+#[test]
+fn local_client_round_trip() {
+    use std::io::Read;
+    use std::os::unix::net::UnixStream;
+    use std::sync::Mutex;
+
+    let path = std::env::temp_dir().join(format!("biopix-test-{}.sock", std::process::id()));
+    let (sender, requests) = mpsc::channel::<ControlRequest>();
+    let sender = Mutex::new(sender);
+    let server = ControlServer::start(ControlAddress::Unix(path.clone()), move |request| {
+        sender.lock().unwrap().send(request).is_ok()
+    })
+    .unwrap();
+
+    // Stands in for the event loop, answering with the method's name
+    thread::spawn(move || {
+        for request in requests {
+            match request_command(&request.method, &request.params) {
+                Ok(_) => {
+                    let method = Json::from(request.method.as_str());
+                    request.respond(Ok(method));
+                }
+                Err(error) => request.respond(Err(error)),
+            }
+        }
+    });
+
+    let mut client = UnixStream::connect(&path).unwrap();
+    client
+        .write_all(
+            b"{\"jsonrpc\":\"2.0\",\"id\":1,\"method\":\"color\",\"params\":{\"scheme\":\"chain\"}}\n\
+              {\"jsonrpc\":\"2.0\",\"id\":2,\"method\":\"spin\"}\n\
+              {\"jsonrpc\":\"2.0\",\"id\":3,\"method\":\"zoom\",\"params\":{}}\nnot json\n",
+        )
+        .unwrap();
+    client.shutdown(std::net::Shutdown::Write).unwrap();
+
+    let mut replies = String::new();
+    client.read_to_string(&mut replies).unwrap();
+    let replies = replies
+        .lines()
+        .map(|line| Json::parse(line).unwrap())
+        .collect::<Vec<_>>();
+    let error_code = |reply: &Json| {
+        reply
+            .get("error")
+            .and_then(|error| error.get("code"))
+            .and_then(Json::as_f64)
+    };
+
+    assert_eq!(replies.len(), 4);
+    assert_eq!(replies[0].get("result"), Some(&Json::from("color")));
+    assert_eq!(replies[0].get("id"), Some(&Json::Number(1.0)));
+    assert_eq!(
+        error_code(&replies[1]),
+        Some(RpcError::METHOD_NOT_FOUND as f64)
+    );
+    assert_eq!(
+        error_code(&replies[2]),
+        Some(RpcError::INVALID_PARAMS as f64)
+    );
+    assert_eq!(error_code(&replies[3]), Some(RpcError::PARSE_ERROR as f64));
+
+    drop(server);
+    assert!(!path.exists());
+
+    fs::write(&path, "not a socket").unwrap();
+    let error = ControlServer::start(ControlAddress::Unix(path.clone()), |_| true).err();
+    assert_eq!(
+        error.map(|error| error.kind()),
+        Some(io::ErrorKind::AlreadyExists)
+    );
+    assert!(path.exists());
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!("localhost:4242".parse(), Ok(ControlAddress::Tcp(4242)));
+    assert_eq!(
+        "/tmp/biopix.sock".parse(),
+        Ok(ControlAddress::Unix(PathBuf::from("/tmp/biopix.sock")))
+    );
+}
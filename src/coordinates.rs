@@ -0,0 +1,128 @@
+use std::cell::Cell;
+use std::path::Path;
+
+use pdbtbx::StrictnessLevel;
+
+use crate::scene::Structure;
+
+/// Writes the atoms in `mask`, aligned with `structure.atoms`, as PDB or mmCIF depending on the
+/// extension of `path`. With `transformed` the superposition transform is applied, otherwise the
+/// coordinates are written as they were read.
+pub fn save(
+    structure: &Structure,
+    mask: &[bool],
+    transformed: bool,
+    path: &Path,
+) -> Result<(), String> {
+    if !mask.contains(&true) {
+        return Err("The selection is empty".to_string());
+    }
+
+    let mut pdb = structure.pdb.clone();
+
+    // Atoms without an element are not part of `structure.atoms`, they are only kept when the
+    // whole model is written
+    let everything = mask.iter().all(|&selected| selected);
+    let index = Cell::new(0);
+    pdb.remove_atoms_by(|atom| {
+        if atom.element().is_none() {
+            return !everything;
+        }
+        let selected = mask.get(index.get()).copied().unwrap_or(false);
+        index.set(index.get() + 1);
+        !selected
+    });
+    pdb.remove_empty();
+
+    if transformed {
+        match &structure.superposition {
+            Some(transformation) => pdb.apply_transformation(transformation),
+            None => return Err("The structure has not been superposed".to_string()),
+        }
+    }
+
+    let filename = path.to_string_lossy();
+    pdbtbx::save(&pdb, &filename, StrictnessLevel::Loose).map_err(|errors| {
+        let errors = errors
+            .iter()
+            .map(|error| error.to_string())
+            .collect::<Vec<_>>();
+        format!("Failed to save {}:\n{}", filename, errors.join("\n"))
+    })
+}
+
+#[test]
+fn writes_only_the_selected_atoms() {
+    use crate::scene::Structure;
+    use crate::selection::Selection;
+    use crate::style::Style;
+    use pdbtbx::TransformationMatrix;
+
+    let mut structure = Structure::open("1d66.pdb", &Style::default()).unwrap();
+    let selection: Selection = "chain A and resi 10-20".parse().unwrap();
+    let mask = structure.select(&selection);
+    let count = mask.iter().filter(|&&selected| selected).count();
+    let original = structure
+        .hierarchy()
+        .zip(&mask)
+        .filter(|(_, &selected)| selected)
+        .map(|((_, _, atom), _)| atom.pos())
+        .collect::<Vec<_>>();
+    let read_back = |path: &Path| {
+        let written = Structure::open(&path.to_string_lossy(), &Style::default()).unwrap();
+        std::fs::remove_file(path).unwrap();
+        assert_eq!(written.atoms.len(), count);
+        assert_eq!(
+            written
+                .select(&selection)
+                .iter()
+                .filter(|&&hit| hit)
+                .count(),
+            count
+        );
+        assert!(written.pdb.chains().all(|chain| chain.id() == "A"));
+        written
+            .hierarchy()
+            .map(|(_, _, atom)| atom.pos())
+            .collect::<Vec<_>>()
+    };
+    let close = |a: (f64, f64, f64), b: (f64, f64, f64)| {
+        (a.0 - b.0).abs() < 1e-3 && (a.1 - b.1).abs() < 1e-3 && (a.2 - b.2).abs() < 1e-3
+    };
+
+    for extension in ["pdb", "cif"] {
+        let path =
+            std::env::temp_dir().join(format!("biopix-test-{}.{}", std::process::id(), extension));
+        save(&structure, &mask, false, &path).unwrap();
+        let written = read_back(&path);
+        assert!(written.iter().zip(&original).all(|(&a, &b)| close(a, b)));
+    }
+
+    // The transform is only applied on request, and only once the structure was superposed
+    let path = std::env::temp_dir().join(format!("biopix-test-{}-moved.pdb", std::process::id()));
+    assert!(save(&structure, &mask, true, &path).is_err());
+    let mut shift = TransformationMatrix::identity();
+    shift.matrix_mut()[0][3] = 5.0;
+    structure.superposition = Some(shift);
+    save(&structure, &mask, true, &path).unwrap();
+    let moved = read_back(&path);
+    assert!(moved
+        .iter()
+        .zip(&original)
+        .all(|(&a, &(x, y, z))| close(a, (x + 5.0, y, z))));
+}
+
+#[test]
+fn parses_write_commands() {
+    use crate::script::ScriptCommand;
+    use std::path::PathBuf;
+
+    assert_eq!(
+        ScriptCommand::parse("write site.cif transformed"),
+        Ok(Some(ScriptCommand::Write(PathBuf::from("site.cif"), true)))
+    );
+    assert_eq!(
+        ScriptCommand::parse("write site.pdb"),
+        Ok(Some(ScriptCommand::Write(PathBuf::from("site.pdb"), false)))
+    );
+}
@@ -1,3 +1,4 @@
+use crate::math::{cross, normalize};
 use crate::object::Object;
 use std::f32;
 
@@ -9,6 +10,7 @@ pub struct Cylinder {
     pub colors: Vec<f32>,
 
     color: [f32; 3],
+    opacity: f32,
     sector_count: u32,
     radius: f32,
     height: f32,
@@ -27,6 +29,7 @@ impl Cylinder {
             base_center_index: 0,
             top_center_index: 0,
             color,
+            opacity: 1.0,
             sector_count,
             radius,
             height,
@@ -37,7 +40,64 @@ impl Cylinder {
         cyl.generate_indices();
         cyl.interlaced_vertices_generator();
 
-        return cyl;
+        cyl
+    }
+
+    /// A cylinder whose axis runs from `start` to `end`, e.g. a bond between two atoms.
+    pub fn between(
+        start: [f32; 3],
+        end: [f32; 3],
+        radius: f32,
+        sector_count: u32,
+        color: [f32; 3],
+    ) -> Cylinder {
+        let delta = [end[0] - start[0], end[1] - start[1], end[2] - start[2]];
+        let height = (delta[0] * delta[0] + delta[1] * delta[1] + delta[2] * delta[2]).sqrt();
+        let mut cyl = Cylinder::new(radius, height, sector_count, color);
+
+        // Orthonormal basis whose z axis is the cylinder's axis
+        let axis = normalize(delta);
+        let helper = if axis[0].abs() < 0.9 {
+            [1.0, 0.0, 0.0]
+        } else {
+            [0.0, 1.0, 0.0]
+        };
+        let u = normalize(cross(helper, axis));
+        let v = cross(axis, u);
+        let transform = |point: &mut [f32]| {
+            let [x, y, z] = [point[0], point[1], point[2]];
+            for i in 0..3 {
+                point[i] = u[i] * x + v[i] * y + axis[i] * z;
+            }
+        };
+
+        cyl.vertices.chunks_mut(3).for_each(transform);
+        cyl.normal_vertices.chunks_mut(3).for_each(transform);
+        cyl.translate(
+            (start[0] + end[0]) / 2.0,
+            (start[1] + end[1]) / 2.0,
+            (start[2] + end[2]) / 2.0,
+        );
+
+        cyl
+    }
+
+    pub fn radius(&self) -> f32 {
+        self.radius
+    }
+
+    /// Centres of the base and top caps, wherever the cylinder has been moved.
+    pub fn ends(&self) -> ([f32; 3], [f32; 3]) {
+        let point = |index: u32| {
+            let index = index as usize * 3;
+            [
+                self.vertices[index],
+                self.vertices[index + 1],
+                self.vertices[index + 2],
+            ]
+        };
+
+        (point(self.base_center_index), point(self.top_center_index))
     }
 
     fn get_unit_circle_vertices(&self) -> Vec<f32> {
@@ -51,17 +111,16 @@ impl Cylinder {
             unit_circle_vertices.push(0.0);
         }
 
-        return unit_circle_vertices;
+        unit_circle_vertices
     }
 
-    fn generate_vertices(&mut self) -> () {
+    fn generate_vertices(&mut self) {
         // Get the unit circle vertices on the XY-plane
         let unit_vertices = self.get_unit_circle_vertices();
 
         // Put side vertices into the arrays
         for i in 0..2 {
             let h = -self.height / 2.0 + i as f32 * self.height; // z value; -h/2 to h/2
-            let t = 1.0 - i as f32; // vertical tex coord; 1 to 0
 
             for j in 0..=self.sector_count {
                 let k = j * 3;
@@ -70,8 +129,8 @@ impl Cylinder {
                 let uz = unit_vertices[k as usize + 2];
 
                 // Position vector
-                self.vertices.push(ux as f32 * self.radius); // vx
-                self.vertices.push(uy as f32 * self.radius); // vy
+                self.vertices.push(ux * self.radius); // vx
+                self.vertices.push(uy * self.radius); // vy
                 self.vertices.push(h); // vz
                 self.colors.append(&mut self.color.to_vec());
 
@@ -113,12 +172,11 @@ impl Cylinder {
         }
     }
 
-    fn generate_indices(&mut self) -> () {
-        let mut k1 = 0;
-        let mut k2 = self.sector_count + 1;
-
+    fn generate_indices(&mut self) {
         // indices for the side surface
-        for i in 0..self.sector_count {
+        for k1 in 0..self.sector_count {
+            let k2 = k1 + self.sector_count + 1;
+
             // 2 triangles per sector
             // k1 => k1+1 => k2
             self.indices.push(k1);
@@ -129,16 +187,13 @@ impl Cylinder {
             self.indices.push(k2);
             self.indices.push(k1 + 1);
             self.indices.push(k2 + 1);
-
-            k1 += 1;
-            k2 += 1;
         }
 
         // indices for the base surface
         // NOTE: baseCenterIndex and topCenterIndices are pre-computed during vertex generation
         //       please see the previous code snippet
-        let mut k = self.base_center_index + 1; // FIXME: THis must be k++
         for i in 0..self.sector_count {
+            let k = self.base_center_index + 1 + i;
             if i < self.sector_count - 1 {
                 self.indices.push(self.base_center_index);
                 self.indices.push(k + 1);
@@ -149,12 +204,11 @@ impl Cylinder {
                 self.indices.push(self.base_center_index + 1);
                 self.indices.push(k);
             }
-            k += 1;
         }
 
         // indices for the top surface
-        let mut k = self.top_center_index + 1; // FIXME: THis must be k++
         for i in 0..self.sector_count {
+            let k = self.top_center_index + 1 + i;
             if i < self.sector_count - 1 {
                 self.indices.push(self.top_center_index);
                 self.indices.push(k);
@@ -165,7 +219,6 @@ impl Cylinder {
                 self.indices.push(k);
                 self.indices.push(self.top_center_index + 1);
             }
-            k += 1;
         }
     }
 
@@ -175,7 +228,7 @@ impl Cylinder {
             .chunks(3)
             .zip(self.normal_vertices().chunks(3))
             .zip(self.colors().chunks(3))
-            .flat_map(|(a, b)| a.0.into_iter().chain(a.1).chain(b))
+            .flat_map(|(a, b)| a.0.iter().chain(a.1).chain(b))
             .copied()
             .collect::<Vec<f32>>();
     }
@@ -183,30 +236,38 @@ impl Cylinder {
 
 impl Object for Cylinder {
     fn indices(&self) -> &Vec<u32> {
-        return &self.indices;
+        &self.indices
     }
 
     fn vertices(&self) -> &Vec<f32> {
-        return &self.vertices;
+        &self.vertices
     }
 
     fn vertices_mut(&mut self) -> &mut Vec<f32> {
-        return &mut self.vertices;
+        &mut self.vertices
     }
 
     fn colors(&self) -> &Vec<f32> {
-        return &self.colors;
+        &self.colors
     }
 
     fn normal_vertices(&self) -> &Vec<f32> {
-        return &self.normal_vertices;
+        &self.normal_vertices
     }
 
     fn interlaced_vertices(&self) -> &Vec<f32> {
-        return &self.interlaced_vertices;
+        &self.interlaced_vertices
     }
 
     fn generate_interlaced_vertices(&mut self) {
         self.interlaced_vertices_generator();
     }
+
+    fn opacity(&self) -> f32 {
+        self.opacity
+    }
+
+    fn set_opacity(&mut self, opacity: f32) {
+        self.opacity = opacity.clamp(0.0, 1.0);
+    }
 }
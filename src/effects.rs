@@ -0,0 +1,67 @@
+/// Black (or any color) silhouettes drawn where depth or normals change abruptly.
+#[derive(Debug, Clone, Copy)]
+pub struct OutlineSettings {
+    pub enabled: bool,
+    /// Line width in pixels.
+    pub thickness: f32,
+    pub color: [f32; 3],
+    /// Depth jump, in clip space units, that counts as a silhouette.
+    pub depth_threshold: f32,
+    /// Minimum `1 - cos(angle)` between neighbouring normals that counts as a crease.
+    pub normal_threshold: f32,
+}
+
+impl Default for OutlineSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            thickness: 1.5,
+            color: [0.0, 0.0, 0.0],
+            depth_threshold: 0.01,
+            normal_threshold: 0.4,
+        }
+    }
+}
+
+/// Depth cueing: fragments fade into the background color between `start` and `end`, given as
+/// normalized depth where 0.0 is the near plane and 1.0 the far plane.
+#[derive(Debug, Clone, Copy)]
+pub struct FogSettings {
+    pub enabled: bool,
+    pub start: f32,
+    pub end: f32,
+}
+
+impl Default for FogSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            start: 0.45,
+            end: 0.7,
+        }
+    }
+}
+
+impl FogSettings {
+    /// How much of the background color replaces a fragment at normalized `depth`.
+    pub fn factor(&self, depth: f32) -> f32 {
+        if !self.enabled || self.end <= self.start {
+            return 0.0;
+        }
+
+        ((depth - self.start) / (self.end - self.start)).clamp(0.0, 1.0)
+    }
+}
+
+#[test]
+fn fog_ramps_between_start_and_end() {
+    let fog = FogSettings {
+        enabled: true,
+        start: 0.2,
+        end: 0.6,
+    };
+
+    assert_eq!(fog.factor(0.1), 0.0);
+    assert!((fog.factor(0.4) - 0.5).abs() < 1e-6);
+    assert_eq!(fog.factor(0.9), 1.0);
+}
@@ -0,0 +1,241 @@
+use crate::gl;
+
+/// Storage layout of a framebuffer's color texture.
+#[derive(Debug, Clone, Copy)]
+pub struct TextureFormat {
+    pub internal_format: gl::types::GLenum,
+    pub format: gl::types::GLenum,
+    pub data_type: gl::types::GLenum,
+    pub filter: gl::types::GLenum,
+}
+
+impl TextureFormat {
+    /// Four half-float channels, sampled without filtering.
+    pub const RGBA16F: TextureFormat = TextureFormat {
+        internal_format: gl::RGBA16F,
+        format: gl::RGBA,
+        data_type: gl::FLOAT,
+        filter: gl::NEAREST,
+    };
+
+    /// Displayable color, linearly filtered.
+    pub const RGBA8: TextureFormat = TextureFormat {
+        internal_format: gl::RGBA8,
+        format: gl::RGBA,
+        data_type: gl::UNSIGNED_BYTE,
+        filter: gl::LINEAR,
+    };
+
+    /// A single half-float channel, sampled without filtering.
+    pub const R16F: TextureFormat = TextureFormat {
+        internal_format: gl::R16F,
+        format: gl::RED,
+        data_type: gl::FLOAT,
+        filter: gl::NEAREST,
+    };
+
+    /// A single normalized channel, linearly filtered.
+    pub const R8: TextureFormat = TextureFormat {
+        internal_format: gl::R8,
+        format: gl::RED,
+        data_type: gl::UNSIGNED_BYTE,
+        filter: gl::LINEAR,
+    };
+}
+
+/// Offscreen render target backed by a single color texture and an optional depth renderbuffer.
+pub struct Framebuffer {
+    pub fbo: gl::types::GLuint,
+    pub texture: gl::types::GLuint,
+    pub depth: Option<gl::types::GLuint>,
+
+    format: TextureFormat,
+}
+
+impl Framebuffer {
+    pub fn new(
+        gl: &gl::Gl,
+        width: i32,
+        height: i32,
+        format: TextureFormat,
+        with_depth: bool,
+    ) -> Framebuffer {
+        unsafe {
+            let mut framebuffer = Framebuffer {
+                fbo: 0,
+                texture: 0,
+                depth: None,
+                format,
+            };
+
+            gl.GenFramebuffers(1, &mut framebuffer.fbo);
+            gl.GenTextures(1, &mut framebuffer.texture);
+
+            if with_depth {
+                let mut depth = 0;
+                gl.GenRenderbuffers(1, &mut depth);
+                framebuffer.depth = Some(depth);
+            }
+
+            framebuffer.resize(gl, width, height);
+
+            gl.BindFramebuffer(gl::FRAMEBUFFER, framebuffer.fbo);
+            gl.FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0,
+                gl::TEXTURE_2D,
+                framebuffer.texture,
+                0,
+            );
+
+            if let Some(depth) = framebuffer.depth {
+                gl.FramebufferRenderbuffer(
+                    gl::FRAMEBUFFER,
+                    gl::DEPTH_ATTACHMENT,
+                    gl::RENDERBUFFER,
+                    depth,
+                );
+            }
+
+            if gl.CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+                eprintln!("Offscreen framebuffer {} is incomplete", framebuffer.fbo);
+            }
+
+            gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+
+            framebuffer
+        }
+    }
+
+    /// Reallocates the attachments, discarding their contents.
+    pub fn resize(&self, gl: &gl::Gl, width: i32, height: i32) {
+        unsafe {
+            gl.BindTexture(gl::TEXTURE_2D, self.texture);
+            gl.TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                self.format.internal_format as i32,
+                width,
+                height,
+                0,
+                self.format.format,
+                self.format.data_type,
+                std::ptr::null(),
+            );
+            gl.TexParameteri(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_MIN_FILTER,
+                self.format.filter as i32,
+            );
+            gl.TexParameteri(
+                gl::TEXTURE_2D,
+                gl::TEXTURE_MAG_FILTER,
+                self.format.filter as i32,
+            );
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+            gl.BindTexture(gl::TEXTURE_2D, 0);
+
+            if let Some(depth) = self.depth {
+                gl.BindRenderbuffer(gl::RENDERBUFFER, depth);
+                gl.RenderbufferStorage(gl::RENDERBUFFER, gl::DEPTH_COMPONENT24, width, height);
+                gl.BindRenderbuffer(gl::RENDERBUFFER, 0);
+            }
+        }
+    }
+
+    pub fn bind(&self, gl: &gl::Gl) {
+        unsafe {
+            gl.BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
+        }
+    }
+
+    /// Attaches another framebuffer's texture as an extra color output of this one.
+    pub fn attach_color(&self, gl: &gl::Gl, index: u32, other: &Framebuffer) {
+        unsafe {
+            gl.BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
+            gl.FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                gl::COLOR_ATTACHMENT0 + index,
+                gl::TEXTURE_2D,
+                other.texture,
+                0,
+            );
+            gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+    }
+
+    pub fn delete(&self, gl: &gl::Gl) {
+        unsafe {
+            gl.DeleteTextures(1, &self.texture);
+            if let Some(depth) = self.depth {
+                gl.DeleteRenderbuffers(1, &depth);
+            }
+            gl.DeleteFramebuffers(1, &self.fbo);
+        }
+    }
+}
+
+/// Two triangles covering the whole viewport, used by the post-processing passes.
+pub struct ScreenQuad {
+    pub vao: gl::types::GLuint,
+    pub vbo: gl::types::GLuint,
+}
+
+impl ScreenQuad {
+    pub fn new(gl: &gl::Gl) -> ScreenQuad {
+        unsafe {
+            let vertices: [f32; 12] = [
+                -1.0, -1.0, 1.0, -1.0, 1.0, 1.0, -1.0, -1.0, 1.0, 1.0, -1.0, 1.0,
+            ];
+
+            let mut quad = ScreenQuad { vao: 0, vbo: 0 };
+
+            gl.GenVertexArrays(1, &mut quad.vao);
+            gl.BindVertexArray(quad.vao);
+
+            gl.GenBuffers(1, &mut quad.vbo);
+            gl.BindBuffer(gl::ARRAY_BUFFER, quad.vbo);
+            gl.BufferData(
+                gl::ARRAY_BUFFER,
+                std::mem::size_of_val(&vertices) as gl::types::GLsizeiptr,
+                vertices.as_ptr() as *const _,
+                gl::STATIC_DRAW,
+            );
+
+            gl.BindVertexArray(0);
+
+            quad
+        }
+    }
+
+    /// Draws the quad with `program`, feeding its `position` attribute.
+    pub fn draw(&self, gl: &gl::Gl, program: gl::types::GLuint) {
+        unsafe {
+            gl.BindVertexArray(self.vao);
+            gl.BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+
+            let pos_attrib = gl.GetAttribLocation(program, c"position".as_ptr());
+            if pos_attrib >= 0 {
+                gl.VertexAttribPointer(
+                    pos_attrib as gl::types::GLuint,
+                    2,
+                    gl::FLOAT,
+                    0,
+                    2 * std::mem::size_of::<f32>() as gl::types::GLsizei,
+                    std::ptr::null(),
+                );
+                gl.EnableVertexAttribArray(pos_attrib as gl::types::GLuint);
+            }
+
+            gl.DrawArrays(gl::TRIANGLES, 0, 6);
+        }
+    }
+
+    pub fn delete(&self, gl: &gl::Gl) {
+        unsafe {
+            gl.DeleteBuffers(1, &self.vbo);
+            gl.DeleteVertexArrays(1, &self.vao);
+        }
+    }
+}
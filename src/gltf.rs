@@ -0,0 +1,429 @@
+use std::fs;
+use std::path::Path;
+
+use crate::json::Json;
+use crate::object::Object;
+use crate::scene::Scene;
+
+const ARRAY_BUFFER: usize = 34962;
+const ELEMENT_ARRAY_BUFFER: usize = 34963;
+const FLOAT: usize = 5126;
+const UNSIGNED_INT: usize = 5125;
+/// Position, normal and color, as in `Object::interlaced_vertices`.
+const VERTEX_STRIDE: usize = 9 * 4;
+
+/// Writes the scene's meshes as glTF 2.0, binary when `path` ends in `.glb` and JSON with an
+/// embedded buffer otherwise. Every residue becomes a node below a node for its chain.
+pub fn save(scene: &Scene, path: &Path) -> Result<(), String> {
+    let (document, buffer) = document(scene);
+
+    let binary = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("glb"));
+    let bytes = if binary {
+        glb(&document, &buffer)
+    } else {
+        embed_buffer(document, &buffer).to_string().into_bytes()
+    };
+
+    fs::write(path, bytes).map_err(|error| format!("Failed to save {}: {}", path.display(), error))
+}
+
+/// Meshes grouped under one name, e.g. a residue.
+struct Group {
+    name: String,
+    chain: Option<String>,
+    /// Object vertices in the interlaced layout and their indices, by opacity.
+    primitives: Vec<(f32, Vec<f32>, Vec<u32>)>,
+}
+
+impl Group {
+    fn add(&mut self, object: &dyn Object) {
+        let opacity = object.opacity();
+        let index = match self
+            .primitives
+            .iter()
+            .position(|(other, _, _)| *other == opacity)
+        {
+            Some(index) => index,
+            None => {
+                self.primitives.push((opacity, Vec::new(), Vec::new()));
+                self.primitives.len() - 1
+            }
+        };
+        let (_, vertices, indices) = &mut self.primitives[index];
+
+        let offset = (vertices.len() / 9) as u32;
+        for ((position, normal), color) in object
+            .vertices()
+            .chunks(3)
+            .zip(object.normal_vertices().chunks(3))
+            .zip(object.colors().chunks(3))
+        {
+            let length = normal.iter().map(|n| n * n).sum::<f32>().sqrt().max(1e-6);
+            vertices.extend_from_slice(position);
+            vertices.extend(normal.iter().map(|n| n / length));
+            vertices.extend_from_slice(color);
+        }
+        indices.extend(object.indices().iter().map(|index| index + offset));
+    }
+}
+
+/// Sorts the scene's objects into chain and residue groups, in the order they first appear.
+fn groups(scene: &Scene) -> Vec<Group> {
+    let mut groups: Vec<Group> = Vec::new();
+    let mut group_of = |atom: Option<usize>| {
+        let (name, chain) = match (atom, &scene.structure) {
+            (Some(atom), Some(structure)) => {
+                let atom = &structure.atoms[atom];
+                (
+                    format!(
+                        "{}/{}{}",
+                        atom.chain_id, atom.residue_name, atom.residue_number
+                    ),
+                    Some(atom.chain_id.clone()),
+                )
+            }
+            _ => ("objects".to_string(), None),
+        };
+
+        match groups.iter().position(|group| group.name == name) {
+            Some(index) => index,
+            None => {
+                groups.push(Group {
+                    name,
+                    chain,
+                    primitives: Vec::new(),
+                });
+                groups.len() - 1
+            }
+        }
+    };
+
+    let mut assigned = Vec::new();
+    for (index, sphere) in scene.spheres.iter().enumerate() {
+        assigned.push((
+            group_of(scene.sphere_atoms.get(index).copied()),
+            sphere as &dyn Object,
+        ));
+    }
+    for (index, cylinder) in scene.cyliders.iter().enumerate() {
+        assigned.push((
+            group_of(scene.cylinder_atoms.get(index).copied()),
+            cylinder as &dyn Object,
+        ));
+    }
+
+    for (group, object) in assigned {
+        groups[group].add(object);
+    }
+
+    groups
+}
+
+/// The glTF JSON and the binary buffer its accessors point into.
+fn document(scene: &Scene) -> (Json, Vec<u8>) {
+    let mut buffer = Vec::<u8>::new();
+    let mut buffer_views = Vec::new();
+    let mut accessors = Vec::new();
+    let mut materials = Vec::<(f32, Json)>::new();
+    let mut meshes = Vec::new();
+    let mut nodes = Vec::new();
+    let mut chains = Vec::<(Option<String>, Vec<Json>)>::new();
+
+    for group in groups(scene) {
+        let mut primitives = Vec::new();
+
+        for (opacity, vertices, indices) in &group.primitives {
+            let vertex_view = buffer_views.len();
+            buffer_views.push(Json::object([
+                ("buffer", Json::from(0)),
+                ("byteOffset", Json::from(buffer.len())),
+                ("byteLength", Json::from(vertices.len() * 4)),
+                ("byteStride", Json::from(VERTEX_STRIDE)),
+                ("target", Json::from(ARRAY_BUFFER)),
+            ]));
+            buffer.extend(vertices.iter().flat_map(|value| value.to_le_bytes()));
+
+            let index_view = buffer_views.len();
+            buffer_views.push(Json::object([
+                ("buffer", Json::from(0)),
+                ("byteOffset", Json::from(buffer.len())),
+                ("byteLength", Json::from(indices.len() * 4)),
+                ("target", Json::from(ELEMENT_ARRAY_BUFFER)),
+            ]));
+            buffer.extend(indices.iter().flat_map(|value| value.to_le_bytes()));
+
+            // POSITION needs its bounds
+            let mut min = [f32::MAX; 3];
+            let mut max = [f32::MIN; 3];
+            for vertex in vertices.chunks(9) {
+                for axis in 0..3 {
+                    min[axis] = min[axis].min(vertex[axis]);
+                    max[axis] = max[axis].max(vertex[axis]);
+                }
+            }
+
+            let count = vertices.len() / 9;
+            let first = accessors.len();
+            for (offset, bounds) in [(0, Some((min, max))), (12, None), (24, None)] {
+                let mut accessor = vec![
+                    ("bufferView".to_string(), Json::from(vertex_view)),
+                    ("byteOffset".to_string(), Json::from(offset)),
+                    ("componentType".to_string(), Json::from(FLOAT)),
+                    ("count".to_string(), Json::from(count)),
+                    ("type".to_string(), Json::from("VEC3")),
+                ];
+                if let Some((min, max)) = bounds {
+                    accessor.push(("min".to_string(), vector(min)));
+                    accessor.push(("max".to_string(), vector(max)));
+                }
+                accessors.push(Json::Object(accessor));
+            }
+            accessors.push(Json::object([
+                ("bufferView", Json::from(index_view)),
+                ("componentType", Json::from(UNSIGNED_INT)),
+                ("count", Json::from(indices.len())),
+                ("type", Json::from("SCALAR")),
+            ]));
+
+            let material = match materials.iter().position(|(other, _)| other == opacity) {
+                Some(material) => material,
+                None => {
+                    materials.push((*opacity, opacity_material(*opacity)));
+                    materials.len() - 1
+                }
+            };
+
+            primitives.push(Json::object([
+                (
+                    "attributes",
+                    Json::object([
+                        ("POSITION", Json::from(first)),
+                        ("NORMAL", Json::from(first + 1)),
+                        ("COLOR_0", Json::from(first + 2)),
+                    ]),
+                ),
+                ("indices", Json::from(first + 3)),
+                ("material", Json::from(material)),
+            ]));
+        }
+
+        let mesh = meshes.len();
+        meshes.push(Json::object([
+            ("name", Json::from(group.name.as_str())),
+            ("primitives", Json::Array(primitives)),
+        ]));
+
+        let node = nodes.len();
+        nodes.push(Json::object([
+            ("name", Json::from(group.name)),
+            ("mesh", Json::from(mesh)),
+        ]));
+        match chains.iter_mut().find(|(chain, _)| *chain == group.chain) {
+            Some((_, children)) => children.push(Json::from(node)),
+            None => chains.push((group.chain, vec![Json::from(node)])),
+        }
+    }
+
+    // Chain nodes come after the residues they group, and objects without a chain sit at the root
+    let mut roots = Vec::new();
+    for (chain, children) in chains {
+        match chain {
+            Some(chain) => {
+                roots.push(Json::from(nodes.len()));
+                nodes.push(Json::Object(vec![
+                    ("name".to_string(), Json::from(format!("chain {}", chain))),
+                    ("children".to_string(), Json::Array(children)),
+                ]));
+            }
+            None => roots.extend(children),
+        }
+    }
+
+    let mut document = vec![
+        (
+            "asset".to_string(),
+            Json::object([
+                ("version", Json::from("2.0")),
+                ("generator", Json::from("Biopix")),
+            ]),
+        ),
+        ("scene".to_string(), Json::from(0)),
+        (
+            "scenes".to_string(),
+            Json::Array(vec![Json::object([("nodes", Json::Array(roots))])]),
+        ),
+        ("nodes".to_string(), Json::Array(nodes)),
+    ];
+    if !meshes.is_empty() {
+        document.extend([
+            ("meshes".to_string(), Json::Array(meshes)),
+            (
+                "materials".to_string(),
+                Json::Array(
+                    materials
+                        .into_iter()
+                        .map(|(_, material)| material)
+                        .collect(),
+                ),
+            ),
+            ("accessors".to_string(), Json::Array(accessors)),
+            ("bufferViews".to_string(), Json::Array(buffer_views)),
+            (
+                "buffers".to_string(),
+                Json::Array(vec![Json::object([(
+                    "byteLength",
+                    Json::from(buffer.len()),
+                )])]),
+            ),
+        ]);
+    }
+
+    (Json::Object(document), buffer)
+}
+
+fn vector(values: [f32; 3]) -> Json {
+    Json::Array(values.map(Json::from).to_vec())
+}
+
+/// Rough, non-metallic material; the vertex colors carry the hue.
+fn opacity_material(opacity: f32) -> Json {
+    let mut material = vec![(
+        "pbrMetallicRoughness".to_string(),
+        Json::object([
+            (
+                "baseColorFactor",
+                Json::Array(vec![
+                    Json::from(1.0),
+                    Json::from(1.0),
+                    Json::from(1.0),
+                    Json::from(opacity),
+                ]),
+            ),
+            ("metallicFactor", Json::from(0.0)),
+            ("roughnessFactor", Json::from(0.6)),
+        ]),
+    )];
+    if opacity < 1.0 {
+        material.push(("alphaMode".to_string(), Json::from("BLEND")));
+    }
+
+    Json::Object(material)
+}
+
+/// Puts the buffer into the document as a base64 data URI, for `.gltf` files.
+fn embed_buffer(mut document: Json, buffer: &[u8]) -> Json {
+    if let Json::Object(members) = &mut document {
+        if let Some((_, Json::Array(buffers))) =
+            members.iter_mut().find(|(key, _)| key == "buffers")
+        {
+            buffers[0] = Json::object([
+                ("byteLength", Json::from(buffer.len())),
+                (
+                    "uri",
+                    Json::from(format!(
+                        "data:application/octet-stream;base64,{}",
+                        base64(buffer)
+                    )),
+                ),
+            ]);
+        }
+    }
+
+    document
+}
+
+/// Binary glTF: a header, the JSON chunk padded with spaces and the buffer padded with zeros.
+fn glb(document: &Json, buffer: &[u8]) -> Vec<u8> {
+    let mut json = document.to_string().into_bytes();
+    while !json.len().is_multiple_of(4) {
+        json.push(b' ');
+    }
+    let mut binary = buffer.to_vec();
+    while !binary.len().is_multiple_of(4) {
+        binary.push(0);
+    }
+
+    let mut length = 12 + 8 + json.len();
+    if !binary.is_empty() {
+        length += 8 + binary.len();
+    }
+
+    let mut bytes = Vec::with_capacity(length);
+    bytes.extend_from_slice(b"glTF");
+    bytes.extend_from_slice(&2u32.to_le_bytes());
+    bytes.extend_from_slice(&(length as u32).to_le_bytes());
+    bytes.extend_from_slice(&(json.len() as u32).to_le_bytes());
+    bytes.extend_from_slice(b"JSON");
+    bytes.extend_from_slice(&json);
+    if !binary.is_empty() {
+        bytes.extend_from_slice(&(binary.len() as u32).to_le_bytes());
+        bytes.extend_from_slice(b"BIN\0");
+        bytes.extend_from_slice(&binary);
+    }
+
+    bytes
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let value = chunk
+            .iter()
+            .enumerate()
+            .fold(0u32, |value, (index, &byte)| {
+                value | (byte as u32) << (16 - 8 * index)
+            });
+
+        for index in 0..4 {
+            if index <= chunk.len() {
+                encoded.push(ALPHABET[(value >> (18 - 6 * index) & 63) as usize] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+
+    encoded
+}
+
+#[test]
+fn exports_chains_and_residues_as_nodes() {
+    use crate::style::Style;
+
+    assert_eq!(base64(b"Biopix!"), "QmlvcGl4IQ==");
+
+    let scene = Scene::open("1d66.pdb", &Style::default()).unwrap();
+    let (document, buffer) = document(&scene);
+
+    let nodes = document.get("nodes").and_then(Json::as_array).unwrap();
+    assert!(nodes
+        .iter()
+        .any(|node| node.get("name").and_then(Json::as_str) == Some("chain A")));
+    assert!(nodes
+        .iter()
+        .filter_map(|node| node.get("name").and_then(Json::as_str))
+        .any(|name| name.starts_with("A/") && name.ends_with(|c: char| c.is_ascii_digit())));
+
+    let vertices = scene
+        .spheres
+        .iter()
+        .map(|sphere| sphere.vertices.len() / 3)
+        .sum::<usize>();
+    let indices = scene
+        .spheres
+        .iter()
+        .map(|sphere| sphere.indices.len())
+        .sum::<usize>();
+    assert_eq!(buffer.len(), vertices * VERTEX_STRIDE + indices * 4);
+
+    let bytes = glb(&document, &buffer);
+    assert_eq!(&bytes[..4], b"glTF");
+    assert_eq!(
+        u32::from_le_bytes(bytes[8..12].try_into().unwrap()) as usize,
+        bytes.len()
+    );
+}
@@ -0,0 +1,344 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+
+use pdbtbx::Element;
+
+use crate::json::Json;
+use crate::math::{distance, dot};
+use crate::scene::{Contact, ContactKind, SceneAtom, Structure};
+use crate::spatial::Grid;
+
+/// Donor to acceptor distance range in Å.
+const MIN_DISTANCE: f32 = 2.5;
+const MAX_DISTANCE: f32 = 3.5;
+/// Hydrogen to acceptor distance limit when hydrogens are present.
+const MAX_HYDROGEN_DISTANCE: f32 = 2.7;
+/// Smallest donor–hydrogen···acceptor angle for explicit hydrogens, in degrees.
+const MIN_HYDROGEN_ANGLE: f32 = 120.0;
+/// Smallest angle at the donor or acceptor between its covalent neighbour and the partner
+/// when hydrogens are implicit, in degrees.
+const MIN_HEAVY_ANGLE: f32 = 90.0;
+/// Longest covalent bond to a hydrogen or to a heavy neighbour.
+const MAX_HYDROGEN_BOND_LENGTH: f32 = 1.2;
+const MAX_HEAVY_BOND_LENGTH: f32 = 1.9;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct HydrogenBond {
+    /// Indices into `Structure::atoms`.
+    pub donor: usize,
+    pub acceptor: usize,
+    /// The hydrogen, when the structure has explicit ones.
+    pub hydrogen: Option<usize>,
+    /// Donor to acceptor distance in Å.
+    pub distance: f32,
+    /// Donor–hydrogen···acceptor angle, or neighbour–donor···acceptor without hydrogens, in
+    /// degrees.
+    pub angle: f32,
+}
+
+/// Whether an atom can donate and accept hydrogen bonds, from its residue and atom name.
+pub fn classify(residue: &str, atom: &str) -> (bool, bool) {
+    let donor = match (residue, atom) {
+        ("PRO", "N") => false,
+        (_, "N") => true,
+        ("ARG", "NE" | "NH1" | "NH2")
+        | ("ASN", "ND2")
+        | ("GLN", "NE2")
+        | ("HIS", "ND1" | "NE2")
+        | ("LYS", "NZ")
+        | ("SER", "OG")
+        | ("THR", "OG1")
+        | ("TYR", "OH")
+        | ("TRP", "NE1")
+        | ("CYS", "SG") => true,
+        ("DA" | "A", "N6") | ("DC" | "C", "N4") | ("DG" | "G", "N1" | "N2") => true,
+        ("DT" | "U", "N3") | ("A" | "C" | "G" | "U", "O2'") => true,
+        ("HOH" | "WAT", "O") => true,
+        _ => false,
+    };
+
+    let acceptor = matches!(
+        (residue, atom),
+        (_, "O" | "OXT")
+            | ("ASP", "OD1" | "OD2")
+            | ("GLU", "OE1" | "OE2")
+            | ("ASN", "OD1")
+            | ("GLN", "OE1")
+            | ("HIS", "ND1" | "NE2")
+            | ("SER", "OG")
+            | ("THR", "OG1")
+            | ("TYR", "OH")
+            | ("MET", "SD")
+            | ("DA" | "A", "N1" | "N3" | "N7")
+            | ("DC" | "C", "O2" | "N3")
+            | ("DG" | "G", "O6" | "N3" | "N7")
+            | ("DT" | "U", "O2" | "O4")
+            | (
+                _,
+                "OP1" | "OP2" | "O1P" | "O2P" | "O3'" | "O5'" | "O4'" | "O2'"
+            )
+    );
+
+    (donor, acceptor)
+}
+
+/// Hydrogen bonds with at least one atom in `mask`, aligned with `structure.atoms`.
+pub fn find(structure: &Structure, mask: &[bool]) -> Vec<HydrogenBond> {
+    let atoms = &structure.atoms;
+    let positions = atoms.iter().map(|atom| atom.position).collect::<Vec<_>>();
+    let grid = Grid::new(&positions, MAX_DISTANCE);
+    let roles = atoms
+        .iter()
+        .map(|atom| classify(&atom.residue_name, &atom.name))
+        .collect::<Vec<_>>();
+
+    let mut bonds = Vec::<HydrogenBond>::new();
+    // Hydroxyls, histidines and water both donate and accept, so their bonds can pass from
+    // either side. One bond is kept per pair of atoms, the more linear one.
+    let mut pairs = HashMap::<(usize, usize), usize>::new();
+    for donor in (0..atoms.len()).filter(|&index| roles[index].0) {
+        let mut acceptors = grid.within(atoms[donor].position, MAX_DISTANCE);
+        acceptors.sort_unstable();
+        for acceptor in acceptors {
+            if !roles[acceptor].1
+                || atoms[donor].residue == atoms[acceptor].residue
+                || !(mask[donor] || mask[acceptor])
+            {
+                continue;
+            }
+
+            let distance = distance(atoms[donor].position, atoms[acceptor].position);
+            if distance < MIN_DISTANCE {
+                continue;
+            }
+
+            let Some(bond) = geometry(atoms, &grid, donor, acceptor, distance) else {
+                continue;
+            };
+            let pair = (donor.min(acceptor), donor.max(acceptor));
+            match pairs.get(&pair) {
+                Some(&index) if bonds[index].angle < bond.angle => bonds[index] = bond,
+                Some(_) => {}
+                None => {
+                    pairs.insert(pair, bonds.len());
+                    bonds.push(bond);
+                }
+            }
+        }
+    }
+
+    bonds
+}
+
+/// Checks the angles of a donor and acceptor `length` apart.
+fn geometry(
+    atoms: &[SceneAtom],
+    grid: &Grid,
+    donor: usize,
+    acceptor: usize,
+    length: f32,
+) -> Option<HydrogenBond> {
+    let [d, a] = [atoms[donor].position, atoms[acceptor].position];
+    let neighbours = |index: usize, cutoff: f32| {
+        let mut found = Vec::new();
+        grid.for_each_within(atoms[index].position, cutoff, |other, length| {
+            if other != index {
+                found.push((other, length));
+            }
+        });
+        found.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+        found
+    };
+
+    let hydrogens = neighbours(donor, MAX_HYDROGEN_BOND_LENGTH)
+        .into_iter()
+        .filter(|&(index, _)| atoms[index].element == Element::H)
+        .collect::<Vec<_>>();
+    if !hydrogens.is_empty() {
+        let (hydrogen, angle) = hydrogens
+            .into_iter()
+            .filter(|&(index, _)| distance(atoms[index].position, a) <= MAX_HYDROGEN_DISTANCE)
+            .map(|(index, _)| (index, angle(d, atoms[index].position, a)))
+            .max_by(|(_, a), (_, b)| a.total_cmp(b))?;
+
+        return (angle >= MIN_HYDROGEN_ANGLE).then_some(HydrogenBond {
+            donor,
+            acceptor,
+            hydrogen: Some(hydrogen),
+            distance: length,
+            angle,
+        });
+    }
+
+    // Without hydrogens, the partner must lie roughly away from each atom's covalent neighbour
+    let heavy_neighbour = |index: usize| {
+        neighbours(index, MAX_HEAVY_BOND_LENGTH)
+            .into_iter()
+            .map(|(neighbour, _)| neighbour)
+            .find(|&neighbour| atoms[neighbour].element != Element::H)
+    };
+
+    let donor_angle = match heavy_neighbour(donor) {
+        Some(neighbour) => angle(atoms[neighbour].position, d, a),
+        None => 180.0,
+    };
+    let acceptor_angle = match heavy_neighbour(acceptor) {
+        Some(neighbour) => angle(d, a, atoms[neighbour].position),
+        None => 180.0,
+    };
+
+    (donor_angle >= MIN_HEAVY_ANGLE && acceptor_angle >= MIN_HEAVY_ANGLE).then_some(HydrogenBond {
+        donor,
+        acceptor,
+        hydrogen: None,
+        distance: length,
+        angle: donor_angle,
+    })
+}
+
+/// Dashed lines for `bonds`, from donor to acceptor.
+pub fn contacts(structure: &Structure, bonds: &[HydrogenBond]) -> Vec<Contact> {
+    bonds
+        .iter()
+        .map(|bond| Contact {
+            kind: ContactKind::HydrogenBond,
+            start: structure.atoms[bond.donor].position,
+            end: structure.atoms[bond.acceptor].position,
+            atom: bond.donor,
+        })
+        .collect()
+}
+
+/// Comma separated table with one row per bond.
+pub fn table(structure: &Structure, bonds: &[HydrogenBond]) -> String {
+    let mut table = String::from(
+        "donor_chain,donor_residue,donor_number,donor_atom,\
+         acceptor_chain,acceptor_residue,acceptor_number,acceptor_atom,distance,angle\n",
+    );
+    for bond in bonds {
+        let [donor, acceptor] = [bond.donor, bond.acceptor].map(|index| &structure.atoms[index]);
+        let _ = writeln!(
+            table,
+            "{},{},{},{},{},{},{},{},{:.2},{:.1}",
+            donor.chain_id,
+            donor.residue_name,
+            donor.residue_number,
+            donor.name,
+            acceptor.chain_id,
+            acceptor.residue_name,
+            acceptor.residue_number,
+            acceptor.name,
+            bond.distance,
+            bond.angle
+        );
+    }
+
+    table
+}
+
+pub fn to_json(structure: &Structure, bonds: &[HydrogenBond]) -> Json {
+    let atom = |index: usize| {
+        let atom = &structure.atoms[index];
+        Json::object([
+            ("chain", Json::from(atom.chain_id.as_str())),
+            ("residue", Json::from(atom.residue_name.as_str())),
+            ("number", Json::Number(atom.residue_number as f64)),
+            ("atom", Json::from(atom.name.as_str())),
+        ])
+    };
+
+    Json::Array(
+        bonds
+            .iter()
+            .map(|bond| {
+                Json::object([
+                    ("donor", atom(bond.donor)),
+                    ("acceptor", atom(bond.acceptor)),
+                    ("distance", Json::from(bond.distance)),
+                    ("angle", Json::from(bond.angle)),
+                ])
+            })
+            .collect(),
+    )
+}
+
+/// Angle at `vertex` between the directions to `a` and `b`, in degrees.
+fn angle(a: [f32; 3], vertex: [f32; 3], b: [f32; 3]) -> f32 {
+    let u = [0, 1, 2].map(|axis| a[axis] - vertex[axis]);
+    let v = [0, 1, 2].map(|axis| b[axis] - vertex[axis]);
+    let lengths = distance(a, vertex) * distance(b, vertex);
+
+    (dot(u, v) / lengths.max(f32::EPSILON))
+        .clamp(-1.0, 1.0)
+        .acos()
+        .to_degrees()
+}
+
+#[test]
+fn finds_protein_dna_hydrogen_bonds() {
+    use crate::scene::Scene;
+    use crate::style::Style;
+
+    assert_eq!(classify("ARG", "NH1"), (true, false));
+    assert_eq!(classify("PRO", "N"), (false, false));
+    assert_eq!(classify("DG", "O6"), (false, true));
+
+    let scene = Scene::open("1d66.pdb", &Style::default()).unwrap();
+    let structure = scene.structure.as_ref().unwrap();
+    let protein = structure.select(&"protein".parse().unwrap());
+    let bonds = find(structure, &protein);
+
+    assert!(bonds.iter().all(|bond| {
+        (MIN_DISTANCE..=MAX_DISTANCE).contains(&bond.distance) && bond.angle >= MIN_HEAVY_ANGLE
+    }));
+    // Gal4 reads the CGG triplets through side chain to base and backbone contacts
+    let dna = structure.select(&"resn DA,DC,DG,DT".parse().unwrap());
+    assert!(bonds
+        .iter()
+        .any(|bond| protein[bond.donor] && dna[bond.acceptor]));
+
+    let table = table(structure, &bonds);
+    assert_eq!(table.lines().count(), bonds.len() + 1);
+    let mut pairs = bonds
+        .iter()
+        .map(|bond| (bond.donor.min(bond.acceptor), bond.donor.max(bond.acceptor)))
+        .collect::<Vec<_>>();
+    pairs.sort_unstable();
+    pairs.dedup();
+    assert_eq!(pairs.len(), bonds.len());
+}
+
+#[test]
+fn bonds_between_donor_acceptors_count_once() {
+    use crate::scene::Structure;
+    use crate::style::Style;
+
+    // Either water can donate to the other
+    let path = std::env::temp_dir().join(format!("biopix-test-{}-water.pdb", std::process::id()));
+    std::fs::write(
+        &path,
+        "HETATM    1  O   HOH A   1       0.000   0.000   0.000  1.00  0.00           O  \n\
+         HETATM    2  O   HOH A   2       2.800   0.000   0.000  1.00  0.00           O  \n\
+         END\n",
+    )
+    .unwrap();
+    let structure = Structure::open(&path.to_string_lossy(), &Style::default()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+
+    let bonds = find(&structure, &[true, true]);
+    assert_eq!(bonds.len(), 1);
+    assert!((bonds[0].distance - 2.8).abs() < 1e-3);
+    assert_eq!(table(&structure, &bonds).lines().count(), 2);
+}
+
+#[test]
+fn parses_hbond_commands() {
+    use crate::script::ScriptCommand;
+
+    assert_eq!(
+        ScriptCommand::parse("hbonds off"),
+        Ok(Some(ScriptCommand::ClearContacts(vec![
+            ContactKind::HydrogenBond
+        ])))
+    );
+}
@@ -0,0 +1,431 @@
+use std::collections::HashMap;
+use std::fmt::Write as _;
+use std::ops::Range;
+
+use pdbtbx::Element;
+
+use crate::json::Json;
+use crate::math::{cross, distance, dot, normalize};
+use crate::scene::{Contact, ContactKind, SceneAtom, Structure};
+use crate::spatial::Grid;
+
+/// Contact kinds this analysis finds.
+pub const KINDS: [ContactKind; 4] = [
+    ContactKind::SaltBridge,
+    ContactKind::PiStacking,
+    ContactKind::CationPi,
+    ContactKind::Hydrophobic,
+];
+
+/// Largest distance between the centers of two opposite charges.
+const SALT_BRIDGE_DISTANCE: f32 = 5.5;
+/// Largest distance between two ring centers.
+const STACKING_DISTANCE: f32 = 5.5;
+/// Ring planes closer to parallel than this stack face to face, in degrees.
+const PARALLEL_ANGLE: f32 = 30.0;
+/// Ring planes closer to perpendicular than this stack edge to face, in degrees.
+const T_SHAPED_ANGLE: f32 = 60.0;
+/// Largest distance between a ring center and a cation.
+const CATION_PI_DISTANCE: f32 = 6.0;
+/// Largest lateral displacement of the partner from the ring axis.
+const MAX_OFFSET: f32 = 2.0;
+/// Largest distance between two hydrophobic carbons.
+const HYDROPHOBIC_DISTANCE: f32 = 4.0;
+
+/// Ring atoms of aromatic side chains and nucleobases, in walking order around the ring.
+const RINGS: &[(&[&str], &[&str])] = &[
+    (&["PHE", "TYR"], &["CG", "CD1", "CE1", "CZ", "CE2", "CD2"]),
+    (&["TRP"], &["CG", "CD1", "NE1", "CE2", "CD2"]),
+    (&["TRP"], &["CD2", "CE2", "CZ2", "CH2", "CZ3", "CE3"]),
+    (&["HIS"], &["CG", "ND1", "CE1", "NE2", "CD2"]),
+    (
+        &["DA", "DG", "A", "G"],
+        &["N1", "C2", "N3", "C4", "C5", "C6"],
+    ),
+    (&["DA", "DG", "A", "G"], &["C4", "C5", "N7", "C8", "N9"]),
+    (
+        &["DC", "DT", "C", "U"],
+        &["N1", "C2", "N3", "C4", "C5", "C6"],
+    ),
+];
+
+/// Atoms sharing a positive charge.
+const CATIONS: &[(&[&str], &[&str])] = &[
+    (&["LYS"], &["NZ"]),
+    (&["ARG"], &["NE", "NH1", "NH2"]),
+    (&["HIS"], &["ND1", "NE2"]),
+];
+
+/// Atoms sharing a negative charge, besides nucleic acid phosphates.
+const ANIONS: &[(&[&str], &[&str])] = &[(&["ASP"], &["OD1", "OD2"]), (&["GLU"], &["OE1", "OE2"])];
+
+/// Side chains whose carbons make hydrophobic contacts.
+const HYDROPHOBIC: &[&str] = &[
+    "ALA", "VAL", "LEU", "ILE", "MET", "PHE", "TRP", "PRO", "TYR",
+];
+
+/// A charged group, aromatic ring or single atom, located at the centroid of its atoms.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Group {
+    /// Indices into `Structure::atoms`, all of one residue.
+    pub atoms: Vec<usize>,
+    pub center: [f32; 3],
+    /// Unit normal of a ring's plane, zero for other groups.
+    pub normal: [f32; 3],
+}
+
+impl Group {
+    fn new(structure: &Structure, atoms: Vec<usize>) -> Group {
+        let positions = atoms
+            .iter()
+            .map(|&index| structure.atoms[index].position)
+            .collect::<Vec<_>>();
+        let center = [0, 1, 2].map(|axis| {
+            positions.iter().map(|position| position[axis]).sum::<f32>() / positions.len() as f32
+        });
+
+        // Newell's method, summing the cross products of consecutive ring atoms
+        let mut normal = [0.0; 3];
+        if positions.len() > 2 {
+            for (index, &position) in positions.iter().enumerate() {
+                let next = positions[(index + 1) % positions.len()];
+                let product = cross(
+                    [0, 1, 2].map(|axis| position[axis] - center[axis]),
+                    [0, 1, 2].map(|axis| next[axis] - center[axis]),
+                );
+                normal = [0, 1, 2].map(|axis| normal[axis] + product[axis]);
+            }
+            normal = normalize(normal);
+        }
+
+        Group {
+            atoms,
+            center,
+            normal,
+        }
+    }
+
+    fn residue<'a>(&self, structure: &'a Structure) -> &'a SceneAtom {
+        &structure.atoms[self.atoms[0]]
+    }
+
+    fn selected(&self, mask: &[bool]) -> bool {
+        self.atoms.iter().any(|&index| mask[index])
+    }
+
+    /// Distance of `point` from the ring's axis, measured in the ring plane.
+    fn offset(&self, point: [f32; 3]) -> f32 {
+        let delta = [0, 1, 2].map(|axis| point[axis] - self.center[axis]);
+        let height = dot(delta, self.normal);
+
+        (dot(delta, delta) - height * height).max(0.0).sqrt()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Interaction {
+    pub kind: ContactKind,
+    pub first: Group,
+    pub second: Group,
+    /// Distance between the group centers in Å.
+    pub distance: f32,
+    /// Angle between the ring planes of a π-stack, in degrees.
+    pub angle: Option<f32>,
+}
+
+/// Salt bridges, π-stacking, cation-π and hydrophobic contacts with at least one partner in
+/// `mask`, aligned with `structure.atoms`.
+pub fn find(structure: &Structure, mask: &[bool]) -> Vec<Interaction> {
+    let residues = residues(&structure.atoms);
+    let rings = groups(structure, &residues, RINGS);
+    let cations = groups(structure, &residues, CATIONS);
+    let mut anions = groups(structure, &residues, ANIONS);
+    // Nucleic acid phosphates carry one negative charge over both free oxygens
+    for range in &residues {
+        let oxygens = range
+            .clone()
+            .filter(|&index| {
+                matches!(
+                    structure.atoms[index].name.as_str(),
+                    "OP1" | "OP2" | "O1P" | "O2P"
+                )
+            })
+            .collect::<Vec<_>>();
+        if oxygens.len() == 2 {
+            anions.push(Group::new(structure, oxygens));
+        }
+    }
+
+    let mut interactions = Vec::new();
+    let mut add = |kind, first: &Group, second: &Group, angle| {
+        if first.residue(structure).residue != second.residue(structure).residue
+            && (first.selected(mask) || second.selected(mask))
+        {
+            interactions.push(Interaction {
+                kind,
+                first: first.clone(),
+                second: second.clone(),
+                distance: distance(first.center, second.center),
+                angle,
+            });
+        }
+    };
+
+    let centers = |groups: &[Group]| groups.iter().map(|group| group.center).collect::<Vec<_>>();
+    // Indices of the grid's groups near `group`, in order
+    let near = |grid: &Grid, group: &Group, radius: f32| {
+        let mut found = grid.within(group.center, radius);
+        found.sort_unstable();
+        found
+    };
+
+    let anion_grid = Grid::new(&centers(&anions), SALT_BRIDGE_DISTANCE);
+    for cation in &cations {
+        for anion in near(&anion_grid, cation, SALT_BRIDGE_DISTANCE) {
+            add(ContactKind::SaltBridge, cation, &anions[anion], None);
+        }
+    }
+
+    let ring_grid = Grid::new(&centers(&rings), STACKING_DISTANCE);
+    for (first, second, _) in ring_grid.pairs(STACKING_DISTANCE) {
+        let (first, second) = (&rings[first], &rings[second]);
+        let angle = dot(first.normal, second.normal)
+            .abs()
+            .min(1.0)
+            .acos()
+            .to_degrees();
+        let offset = first.offset(second.center).min(second.offset(first.center));
+        if (angle <= PARALLEL_ANGLE || angle >= T_SHAPED_ANGLE) && offset <= MAX_OFFSET {
+            add(ContactKind::PiStacking, first, second, Some(angle));
+        }
+    }
+
+    // Histidine is counted as a ring here rather than as a cation
+    for cation in cations
+        .iter()
+        .filter(|cation| cation.residue(structure).residue_name != "HIS")
+    {
+        for ring in near(&ring_grid, cation, CATION_PI_DISTANCE) {
+            if rings[ring].offset(cation.center) <= MAX_OFFSET {
+                add(ContactKind::CationPi, cation, &rings[ring], None);
+            }
+        }
+    }
+
+    // Only the closest pair of carbons is kept for each pair of residues, skipping residues
+    // next to each other in a chain
+    let carbons = (0..structure.atoms.len())
+        .filter(|&index| is_hydrophobic(&structure.atoms[index]))
+        .collect::<Vec<_>>();
+    let positions = carbons
+        .iter()
+        .map(|&index| structure.atoms[index].position)
+        .collect::<Vec<_>>();
+    let mut closest = HashMap::<(usize, usize), (usize, usize, f32)>::new();
+    for (a, b, length) in Grid::new(&positions, HYDROPHOBIC_DISTANCE).pairs(HYDROPHOBIC_DISTANCE) {
+        let (a, b) = (carbons[a], carbons[b]);
+        let (first, second) = (&structure.atoms[a], &structure.atoms[b]);
+        if first.chain == second.chain && first.residue.abs_diff(second.residue) <= 1 {
+            continue;
+        }
+        let pair = closest
+            .entry((first.residue, second.residue))
+            .or_insert((a, b, length));
+        if length < pair.2 {
+            *pair = (a, b, length);
+        }
+    }
+    let mut pairs = closest.into_values().collect::<Vec<_>>();
+    pairs.sort_by_key(|&(a, b, _)| (a, b));
+    for (a, b, _) in pairs {
+        add(
+            ContactKind::Hydrophobic,
+            &Group::new(structure, vec![a]),
+            &Group::new(structure, vec![b]),
+            None,
+        );
+    }
+
+    interactions
+}
+
+/// Every complete group of `table` in each residue.
+fn groups(
+    structure: &Structure,
+    residues: &[Range<usize>],
+    table: &[(&[&str], &[&str])],
+) -> Vec<Group> {
+    let mut groups = Vec::new();
+    for range in residues {
+        let residue = structure.atoms[range.start].residue_name.as_str();
+        for (_, names) in table.iter().filter(|(names, _)| names.contains(&residue)) {
+            let atoms = names
+                .iter()
+                .map(|&name| {
+                    range
+                        .clone()
+                        .find(|&index| structure.atoms[index].name == name)
+                })
+                .collect::<Option<Vec<_>>>();
+            if let Some(atoms) = atoms {
+                groups.push(Group::new(structure, atoms));
+            }
+        }
+    }
+
+    groups
+}
+
+/// Atom ranges of each residue, which are contiguous in hierarchy order.
+fn residues(atoms: &[SceneAtom]) -> Vec<Range<usize>> {
+    let mut ranges = Vec::<Range<usize>>::new();
+    for (index, atom) in atoms.iter().enumerate() {
+        match ranges.last_mut() {
+            Some(range) if atoms[range.start].residue == atom.residue => range.end = index + 1,
+            _ => ranges.push(index..index + 1),
+        }
+    }
+
+    ranges
+}
+
+fn is_hydrophobic(atom: &SceneAtom) -> bool {
+    atom.element == Element::C
+        && match atom.residue_name.as_str() {
+            // The methyl group of thymine
+            "DT" => matches!(atom.name.as_str(), "C7" | "C5M"),
+            residue => HYDROPHOBIC.contains(&residue) && !matches!(atom.name.as_str(), "C" | "CA"),
+        }
+}
+
+/// Dashed lines between the group centers of `interactions`.
+pub fn contacts(interactions: &[Interaction]) -> Vec<Contact> {
+    interactions
+        .iter()
+        .map(|interaction| Contact {
+            kind: interaction.kind,
+            start: interaction.first.center,
+            end: interaction.second.center,
+            atom: interaction.first.atoms[0],
+        })
+        .collect()
+}
+
+/// Plain text table with one interaction per line.
+pub fn report(structure: &Structure, interactions: &[Interaction]) -> String {
+    let group = |group: &Group| {
+        let residue = group.residue(structure);
+        let atoms = group
+            .atoms
+            .iter()
+            .map(|&index| structure.atoms[index].name.as_str())
+            .collect::<Vec<_>>();
+        format!(
+            "{}:{} {} {}",
+            residue.chain_id,
+            residue.residue_name,
+            residue.residue_number,
+            atoms.join(",")
+        )
+    };
+
+    let mut report = format!(
+        "{:<16}{:<28}{:<28}{:>8}{:>8}\n",
+        "interaction", "first", "second", "distance", "angle"
+    );
+    for interaction in interactions {
+        let angle = interaction
+            .angle
+            .map_or("-".to_string(), |angle| format!("{:.1}", angle));
+        let _ = writeln!(
+            report,
+            "{:<16}{:<28}{:<28}{:>8.2}{:>8}",
+            interaction.kind.name(),
+            group(&interaction.first),
+            group(&interaction.second),
+            interaction.distance,
+            angle
+        );
+    }
+
+    report
+}
+
+pub fn to_json(structure: &Structure, interactions: &[Interaction]) -> Json {
+    let group = |group: &Group| {
+        let residue = group.residue(structure);
+        Json::object([
+            ("chain", Json::from(residue.chain_id.as_str())),
+            ("residue", Json::from(residue.residue_name.as_str())),
+            ("number", Json::Number(residue.residue_number as f64)),
+            (
+                "atoms",
+                Json::Array(
+                    group
+                        .atoms
+                        .iter()
+                        .map(|&index| Json::from(structure.atoms[index].name.as_str()))
+                        .collect(),
+                ),
+            ),
+        ])
+    };
+
+    Json::Array(
+        interactions
+            .iter()
+            .map(|interaction| {
+                Json::object([
+                    ("kind", Json::from(interaction.kind.name())),
+                    ("first", group(&interaction.first)),
+                    ("second", group(&interaction.second)),
+                    ("distance", Json::from(interaction.distance)),
+                    ("angle", interaction.angle.map_or(Json::Null, Json::from)),
+                ])
+            })
+            .collect(),
+    )
+}
+
+#[test]
+fn profiles_protein_dna_interface() {
+    use crate::scene::Scene;
+    use crate::style::Style;
+
+    let scene = Scene::open("1d66.pdb", &Style::default()).unwrap();
+    let structure = scene.structure.as_ref().unwrap();
+    let protein = structure.select(&"protein".parse().unwrap());
+    let interactions = find(structure, &protein);
+
+    let count = |kind| {
+        interactions
+            .iter()
+            .filter(|interaction| interaction.kind == kind)
+            .count()
+    };
+    // Lysines and arginines reach the phosphate backbone
+    assert!(count(ContactKind::SaltBridge) > 0);
+    assert!(count(ContactKind::Hydrophobic) > 0);
+
+    for interaction in &interactions {
+        assert!(interaction.first.selected(&protein) || interaction.second.selected(&protein));
+        if interaction.kind == ContactKind::PiStacking {
+            let angle = interaction.angle.unwrap();
+            assert!(angle <= PARALLEL_ANGLE || angle >= T_SHAPED_ANGLE);
+        }
+    }
+
+    // A phenylalanine ring lies flat in its plane
+    let phenylalanines = groups(structure, &residues(&structure.atoms), &RINGS[..1]);
+    for ring in phenylalanines {
+        for &index in &ring.atoms {
+            let position = structure.atoms[index].position;
+            let delta = [0, 1, 2].map(|axis| position[axis] - ring.center[axis]);
+            assert!(dot(delta, ring.normal).abs() < 0.1);
+        }
+    }
+
+    let report = report(structure, &interactions);
+    assert_eq!(report.lines().count(), interactions.len() + 1);
+    assert!(report.contains("salt bridge"));
+}
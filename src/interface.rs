@@ -0,0 +1,249 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fmt::Write as _;
+
+use crate::json::Json;
+use crate::sasa;
+use crate::scene::{SceneAtom, Structure};
+use crate::selection::Selection;
+use crate::spatial::Grid;
+
+/// Residues with atoms closer than this to the other chain are in the interface, in Å.
+pub const DEFAULT_CUTOFF: f32 = 5.0;
+
+/// The residues by which two chains touch.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Interface {
+    /// Indices of the two chains within the structure.
+    pub chains: (usize, usize),
+    /// Indices into `Structure::atoms` of one atom of each contacting residue, per chain.
+    pub first: Vec<usize>,
+    pub second: Vec<usize>,
+    /// Solvent accessible area hidden by the contact, summed over both sides, in Å².
+    pub buried_area: f32,
+}
+
+impl Interface {
+    /// Atoms of the interface residues, aligned with `structure.atoms`.
+    pub fn mask(&self, structure: &Structure) -> Vec<bool> {
+        let residues = self
+            .first
+            .iter()
+            .chain(&self.second)
+            .map(|&index| structure.atoms[index].residue)
+            .collect::<BTreeSet<_>>();
+
+        structure
+            .atoms
+            .iter()
+            .map(|atom| residues.contains(&atom.residue))
+            .collect()
+    }
+
+    /// A query matching the interface residues, to make them the current selection.
+    pub fn selection(&self, structure: &Structure) -> Selection {
+        let side = |residues: &[usize]| {
+            let chain = structure.atoms[residues[0]].chain_id.clone();
+            let numbers = residues
+                .iter()
+                .map(|&index| {
+                    let number = structure.atoms[index].residue_number;
+                    (number, number)
+                })
+                .collect();
+            Selection::And(
+                Box::new(Selection::Chain(vec![chain])),
+                Box::new(Selection::ResidueRange(numbers)),
+            )
+        };
+
+        Selection::Or(Box::new(side(&self.first)), Box::new(side(&self.second)))
+    }
+}
+
+/// Interfaces between every pair of chains with atoms in `mask`, leaving out water.
+pub fn find(structure: &Structure, mask: &[bool], cutoff: f32) -> Vec<Interface> {
+    let atoms = &structure.atoms;
+    let chain_count = atoms.iter().map(|atom| atom.chain + 1).max().unwrap_or(0);
+    let chains = (0..chain_count)
+        .map(|chain| {
+            (0..atoms.len())
+                .filter(|&index| {
+                    mask[index] && atoms[index].chain == chain && !atoms[index].is_water()
+                })
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+    let alone = chains
+        .iter()
+        .map(|chain| area(atoms, chain))
+        .collect::<Vec<_>>();
+
+    // Contacting atoms of each pair of chains, found in one sweep over the structure
+    let members = chains.concat();
+    let positions = members
+        .iter()
+        .map(|&index| atoms[index].position)
+        .collect::<Vec<_>>();
+    let mut contacts = BTreeMap::<(usize, usize), (Vec<usize>, Vec<usize>)>::new();
+    for (i, j, _) in Grid::new(&positions, cutoff).pairs(cutoff) {
+        let (mut i, mut j) = (members[i], members[j]);
+        if atoms[i].chain == atoms[j].chain {
+            continue;
+        }
+        if atoms[i].chain > atoms[j].chain {
+            (i, j) = (j, i);
+        }
+        let (first, second) = contacts
+            .entry((atoms[i].chain, atoms[j].chain))
+            .or_default();
+        first.push(i);
+        second.push(j);
+    }
+
+    let mut interfaces = Vec::new();
+    for ((a, b), (first, second)) in contacts {
+        let complex = chains[a]
+            .iter()
+            .chain(&chains[b])
+            .copied()
+            .collect::<Vec<_>>();
+        interfaces.push(Interface {
+            chains: (a, b),
+            first: one_per_residue(atoms, first),
+            second: one_per_residue(atoms, second),
+            buried_area: alone[a] + alone[b] - area(atoms, &complex),
+        });
+    }
+
+    interfaces
+}
+
+/// Total solvent accessible area of the atoms at `indices`.
+fn area(atoms: &[SceneAtom], indices: &[usize]) -> f32 {
+    let positions = indices
+        .iter()
+        .map(|&index| atoms[index].position)
+        .collect::<Vec<_>>();
+    let radii = indices
+        .iter()
+        .map(|&index| sasa::radius(&atoms[index].element))
+        .collect::<Vec<_>>();
+
+    sasa::shrake_rupley(&positions, &radii, sasa::SPHERE_POINTS)
+        .iter()
+        .sum()
+}
+
+/// The first of `indices` in each residue, in structure order.
+fn one_per_residue(atoms: &[SceneAtom], mut indices: Vec<usize>) -> Vec<usize> {
+    indices.sort_unstable();
+    indices.dedup_by_key(|index| atoms[*index].residue);
+    indices
+}
+
+/// Plain text summary with the residues of both sides of each interface.
+pub fn report(structure: &Structure, interfaces: &[Interface]) -> String {
+    let residues = |indices: &[usize]| {
+        indices
+            .iter()
+            .map(|&index| {
+                let atom = &structure.atoms[index];
+                format!("{} {}", atom.residue_name, atom.residue_number)
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    };
+
+    let mut report = String::new();
+    for interface in interfaces {
+        let [first, second] = [interface.first[0], interface.second[0]]
+            .map(|index| structure.atoms[index].chain_id.as_str());
+        let _ = writeln!(
+            report,
+            "Chains {} and {}: {} + {} residues, {:.1} Å² buried\n  {}: {}\n  {}: {}",
+            first,
+            second,
+            interface.first.len(),
+            interface.second.len(),
+            interface.buried_area,
+            first,
+            residues(&interface.first),
+            second,
+            residues(&interface.second)
+        );
+    }
+
+    report
+}
+
+pub fn to_json(structure: &Structure, interfaces: &[Interface]) -> Json {
+    let side = |indices: &[usize]| {
+        Json::Array(
+            indices
+                .iter()
+                .map(|&index| {
+                    let atom = &structure.atoms[index];
+                    Json::object([
+                        ("residue", Json::from(atom.residue_name.as_str())),
+                        ("number", Json::Number(atom.residue_number as f64)),
+                    ])
+                })
+                .collect(),
+        )
+    };
+
+    Json::Array(
+        interfaces
+            .iter()
+            .map(|interface| {
+                let [first, second] = [interface.first[0], interface.second[0]]
+                    .map(|index| structure.atoms[index].chain_id.as_str());
+                Json::object([
+                    ("chains", Json::Array(vec![first.into(), second.into()])),
+                    ("first", side(&interface.first)),
+                    ("second", side(&interface.second)),
+                    ("buried_area", Json::from(interface.buried_area)),
+                ])
+            })
+            .collect(),
+    )
+}
+
+#[test]
+fn finds_protein_dna_interface() {
+    use crate::scene::Scene;
+    use crate::style::Style;
+
+    let scene = Scene::open("1d66.pdb", &Style::default()).unwrap();
+    let structure = scene.structure.as_ref().unwrap();
+    let everything = vec![true; structure.atoms.len()];
+    let interfaces = find(structure, &everything, DEFAULT_CUTOFF);
+
+    let between = |a: &str, b: &str| {
+        interfaces
+            .iter()
+            .find(|interface| {
+                let chains = [interface.first[0], interface.second[0]]
+                    .map(|index| structure.atoms[index].chain_id.as_str());
+                chains == [a, b] || chains == [b, a]
+            })
+            .unwrap()
+    };
+    // The two DNA strands pair along their whole length, burying far more than a protein contact
+    let duplex = between("D", "E");
+    let protein_dna = between("A", "D");
+    assert!(duplex.buried_area > protein_dna.buried_area);
+    assert!(protein_dna.buried_area > 0.0);
+    assert!(protein_dna
+        .first
+        .iter()
+        .all(|&index| structure.atoms[index].chain == protein_dna.chains.0));
+
+    // The selection made from an interface picks exactly its residues
+    let mask = protein_dna.mask(structure);
+    assert_eq!(structure.select(&protein_dna.selection(structure)), mask);
+    assert_eq!(
+        report(structure, &interfaces).matches("Chains").count(),
+        interfaces.len()
+    );
+}
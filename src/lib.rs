@@ -0,0 +1,37 @@
+pub mod align;
+pub mod clash;
+pub mod cli;
+pub mod control;
+pub mod coordinates;
+pub mod cylinder;
+pub mod effects;
+pub mod font;
+pub mod framebuffer;
+pub mod gltf;
+pub mod hbond;
+pub mod interaction;
+pub mod interface;
+pub mod json;
+pub mod math;
+pub mod mesh;
+pub mod object;
+pub mod oit;
+pub mod opengl;
+pub mod quality;
+pub mod ramachandran;
+pub mod sasa;
+pub mod scene;
+pub mod script;
+pub mod selection;
+pub mod sequence;
+pub mod session;
+pub mod shader;
+pub mod shadow;
+pub mod spatial;
+pub mod sphere;
+pub mod ssao;
+pub mod style;
+pub mod superpose;
+pub mod svg;
+
+use opengl::gl;
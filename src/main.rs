@@ -1,19 +1,42 @@
-mod cylinder;
-mod object;
-mod opengl;
-mod scene;
-mod sphere;
-
-use opengl::*;
+use biopix::cli::{self, Command};
+use biopix::opengl;
+use biopix::scene;
+use biopix::script::Interpreter;
 use std::env;
+use std::process::ExitCode;
 
-pub fn main() {
-    let args = env::args().collect::<Vec<String>>();
+pub fn main() -> ExitCode {
+    let args = env::args().skip(1).collect::<Vec<String>>();
 
-    if args.len() < 2 {
-        println!("Missing file argument! Please pass filename as argument.");
-    }
+    let options = match cli::parse(&args) {
+        Ok(Command::View(options) | Command::Render(options)) => options,
+        Ok(Command::Help) => {
+            println!("{}", cli::USAGE);
+            return ExitCode::SUCCESS;
+        }
+        Err(error) => {
+            eprintln!("{}\n\n{}", error, cli::USAGE);
+            return ExitCode::FAILURE;
+        }
+    };
 
-    let render_scene: scene::Scene = scene::Scene::from(args.get(1).unwrap());
-    opengl::init(&render_scene);
+    let settings = options.render_settings();
+    let style = options.style();
+    let render_scene = match &options.file {
+        Some(file) => match scene::Scene::open(file, &style) {
+            Ok(render_scene) => render_scene,
+            Err(error) => {
+                eprintln!("{}", error);
+                return ExitCode::FAILURE;
+            }
+        },
+        None => scene::Scene::default(),
+    };
+    opengl::init(
+        render_scene,
+        settings,
+        options.viewer,
+        Interpreter::new(style),
+    );
+    ExitCode::SUCCESS
 }
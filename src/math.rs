@@ -0,0 +1,50 @@
+/// Column major 4x4 identity, the layout `UniformMatrix4fv` expects.
+pub const IDENTITY: [f32; 16] = [
+    1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0, 0.0, 0.0, 0.0, 0.0, 1.0,
+];
+
+pub fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+pub fn cross(a: [f32; 3], b: [f32; 3]) -> [f32; 3] {
+    [
+        a[1] * b[2] - a[2] * b[1],
+        a[2] * b[0] - a[0] * b[2],
+        a[0] * b[1] - a[1] * b[0],
+    ]
+}
+
+pub fn distance(a: [f32; 3], b: [f32; 3]) -> f32 {
+    let delta = [a[0] - b[0], a[1] - b[1], a[2] - b[2]];
+
+    dot(delta, delta).sqrt()
+}
+
+pub fn normalize(vector: [f32; 3]) -> [f32; 3] {
+    let length = dot(vector, vector).sqrt().max(f32::EPSILON);
+
+    [vector[0] / length, vector[1] / length, vector[2] / length]
+}
+
+/// Turns then tilts `point` the way the vertex shader does, giving view space before scaling.
+pub fn view_rotate(point: [f32; 3], turn: f32, tilt: f32) -> [f32; 3] {
+    let (sin_tilt, cos_tilt) = tilt.sin_cos();
+    let [x, y, z] = point;
+    let (y, z) = (cos_tilt * y + sin_tilt * z, cos_tilt * z - sin_tilt * y);
+
+    let (sin_turn, cos_turn) = (-turn).sin_cos();
+    [cos_turn * x + sin_turn * z, y, cos_turn * z - sin_turn * x]
+}
+
+/// Pixel position and depth of `point` in a `width` by `height` view drawn with `turn`, `tilt`
+/// and `scale`. Clip space spans -1..1 on both axes, without keeping the aspect ratio, as on
+/// screen; smaller depths are nearer.
+pub fn project(
+    point: [f32; 3],
+    (turn, tilt, scale): (f32, f32, f32),
+    (width, height): (f32, f32),
+) -> ([f32; 2], f32) {
+    let [x, y, z] = view_rotate(point, turn, tilt).map(|value| value * scale);
+    ([(x + 1.0) / 2.0 * width, (1.0 - y) / 2.0 * height], z)
+}
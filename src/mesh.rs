@@ -0,0 +1,492 @@
+use std::collections::{HashMap, HashSet};
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use crate::math::cross;
+use crate::object::Object;
+use crate::scene::Scene;
+
+/// Grid spacing the union is sampled on, in Å. At the usual 1 cm per 10 Å this is a quarter of
+/// a millimetre, about what a printer resolves.
+const VOXEL: f32 = 0.25;
+/// Most grid spacings along the longest side of the scene; larger scenes are sampled coarser.
+const MAX_VOXELS: f32 = 256.0;
+
+/// The six tetrahedra a grid cube is split into, as corner offsets. All share the main diagonal,
+/// so neighbouring cubes split their common faces the same way and the surface stays closed.
+const TETRAHEDRA: [[[i32; 3]; 4]; 6] = [
+    [[0, 0, 0], [1, 0, 0], [1, 1, 0], [1, 1, 1]],
+    [[0, 0, 0], [1, 0, 0], [1, 0, 1], [1, 1, 1]],
+    [[0, 0, 0], [0, 1, 0], [1, 1, 0], [1, 1, 1]],
+    [[0, 0, 0], [0, 1, 0], [0, 1, 1], [1, 1, 1]],
+    [[0, 0, 0], [0, 0, 1], [1, 0, 1], [1, 1, 1]],
+    [[0, 0, 0], [0, 0, 1], [0, 1, 1], [1, 1, 1]],
+];
+
+/// The union of every object of a scene as one closed, outward facing triangle mesh, for 3D
+/// printing. The objects are sampled on a grid and the surface extracted with marching
+/// tetrahedra, so overlapping spheres and sticks merge into a single shell.
+#[derive(Debug, Clone, Default)]
+pub struct Mesh {
+    pub positions: Vec<[f32; 3]>,
+    pub colors: Vec<[f32; 3]>,
+    pub triangles: Vec<[u32; 3]>,
+}
+
+/// A convex object as the intersection of the half-spaces behind its faces.
+struct Solid {
+    /// Outward unit normal and offset of each face plane.
+    planes: Vec<([f32; 3], f32)>,
+    min: [f32; 3],
+    max: [f32; 3],
+    color: [f32; 3],
+    inradius: f32,
+    /// How far the faces are pushed out, so that objects thinner than the grid still print.
+    grow: f32,
+}
+
+impl Solid {
+    fn new(object: &dyn Object) -> Option<Solid> {
+        let positions = object
+            .vertices()
+            .chunks(3)
+            .map(|position| [position[0], position[1], position[2]])
+            .collect::<Vec<_>>();
+        let count = positions.len().max(1) as f32;
+        let centroid = positions.iter().fold([0.0; 3], |sum, position| {
+            [0, 1, 2].map(|axis| sum[axis] + position[axis] / count)
+        });
+
+        let mut planes = Vec::new();
+        for triangle in object.indices().chunks(3) {
+            let [a, b, c] = [0, 1, 2].map(|corner| positions[triangle[corner] as usize]);
+            let normal = cross(
+                [b[0] - a[0], b[1] - a[1], b[2] - a[2]],
+                [c[0] - a[0], c[1] - a[1], c[2] - a[2]],
+            );
+            let length = dot(normal, normal).sqrt();
+            if length < 1e-9 {
+                continue;
+            }
+            let mut normal = normal.map(|value| value / length);
+            let mut offset = dot(normal, a);
+            // The centroid lies behind every face of a convex object
+            if dot(normal, centroid) > offset {
+                normal = normal.map(|value| -value);
+                offset = -offset;
+            }
+            planes.push((normal, offset));
+        }
+
+        let inradius = planes
+            .iter()
+            .map(|&(normal, offset)| offset - dot(normal, centroid))
+            .fold(f32::INFINITY, f32::min);
+        if planes.is_empty() || inradius <= 0.0 {
+            return None;
+        }
+
+        let bound = |pick: fn(f32, f32) -> f32, start: f32| {
+            positions.iter().fold([start; 3], |bound, position| {
+                [0, 1, 2].map(|axis| pick(bound[axis], position[axis]))
+            })
+        };
+        let color = object.colors();
+
+        Some(Solid {
+            min: bound(f32::min, f32::INFINITY),
+            max: bound(f32::max, f32::NEG_INFINITY),
+            color: [color[0], color[1], color[2]],
+            planes,
+            inradius,
+            grow: 0.0,
+        })
+    }
+
+    /// How far `point` lies inside, negative outside. Stops early once the result is known to be
+    /// no more than `floor`.
+    fn depth(&self, point: [f32; 3], floor: f32) -> f32 {
+        let mut outside = f32::NEG_INFINITY;
+        for &(normal, offset) in &self.planes {
+            outside = outside.max(dot(normal, point) - offset - self.grow);
+            if -outside <= floor {
+                break;
+            }
+        }
+
+        -outside
+    }
+
+    /// Thickens the solid to an inradius of at least `radius`.
+    fn thicken(&mut self, radius: f32) {
+        self.grow = (radius - self.inradius).max(0.0);
+        self.min = self.min.map(|value| value - self.grow);
+        self.max = self.max.map(|value| value + self.grow);
+    }
+}
+
+fn dot(a: [f32; 3], b: [f32; 3]) -> f32 {
+    a[0] * b[0] + a[1] * b[1] + a[2] * b[2]
+}
+
+impl Mesh {
+    pub fn from_scene(scene: &Scene) -> Mesh {
+        let spheres = scene.spheres.iter().map(|sphere| sphere as &dyn Object);
+        let cylinders = scene
+            .cyliders
+            .iter()
+            .map(|cylinder| cylinder as &dyn Object);
+        let mut solids = spheres
+            .chain(cylinders)
+            .filter_map(Solid::new)
+            .collect::<Vec<_>>();
+
+        let extent = [0, 1, 2]
+            .map(|axis| {
+                let low = solids
+                    .iter()
+                    .map(|solid| solid.min[axis])
+                    .fold(f32::INFINITY, f32::min);
+                let high = solids
+                    .iter()
+                    .map(|solid| solid.max[axis])
+                    .fold(f32::NEG_INFINITY, f32::max);
+                high - low
+            })
+            .into_iter()
+            .fold(0.0, f32::max);
+        let voxel = VOXEL.max(extent / MAX_VOXELS);
+        // Sticks thinner than the grid would fall between its points and break apart
+        for solid in &mut solids {
+            solid.thicken(voxel);
+        }
+
+        // Depth inside the union at each grid point near an object, and the object reaching
+        // deepest, which gives the color
+        let mut field = HashMap::<[i32; 3], (f32, usize)>::new();
+        for (index, solid) in solids.iter().enumerate() {
+            let low = solid.min.map(|value| (value / voxel).floor() as i32 - 1);
+            let high = solid.max.map(|value| (value / voxel).ceil() as i32 + 1);
+            for x in low[0]..=high[0] {
+                for y in low[1]..=high[1] {
+                    for z in low[2]..=high[2] {
+                        let sample = field.entry([x, y, z]).or_insert((f32::NEG_INFINITY, index));
+                        let point = [x, y, z].map(|step| step as f32 * voxel);
+                        let depth = solid.depth(point, sample.0);
+                        if depth > sample.0 {
+                            *sample = (depth, index);
+                        }
+                    }
+                }
+            }
+        }
+
+        // Every cube with a corner inside, in a fixed order so exports are reproducible
+        let mut cubes = field
+            .iter()
+            .filter(|(_, &(depth, _))| depth > 0.0)
+            .flat_map(|(&[x, y, z], _)| {
+                (0..8)
+                    .map(move |corner| [x - (corner & 1), y - (corner >> 1 & 1), z - (corner >> 2)])
+            })
+            .collect::<HashSet<_>>()
+            .into_iter()
+            .collect::<Vec<_>>();
+        cubes.sort_unstable();
+
+        let mut mesh = Mesh::default();
+        let mut edges = HashMap::new();
+        let depth = |point: [i32; 3]| field.get(&point).map_or(-voxel, |&(depth, _)| depth);
+        for cube in cubes {
+            for tetrahedron in TETRAHEDRA {
+                let corners =
+                    tetrahedron.map(|offset| [0, 1, 2].map(|axis| cube[axis] + offset[axis]));
+                let (inside, outside): (Vec<_>, Vec<_>) = corners
+                    .iter()
+                    .copied()
+                    .partition(|&corner| depth(corner) > 0.0);
+                if inside.is_empty() || outside.is_empty() {
+                    continue;
+                }
+
+                let mut vertex =
+                    |a: [i32; 3], b: [i32; 3]| {
+                        *edges.entry((a, b)).or_insert_with(|| {
+                            let (depth_a, depth_b) = (depth(a), depth(b));
+                            let t = depth_a / (depth_a - depth_b);
+                            mesh.positions.push([0, 1, 2].map(|axis| {
+                                (a[axis] as f32 + t * (b[axis] - a[axis]) as f32) * voxel
+                            }));
+                            mesh.colors.push(solids[field[&a].1].color);
+                            mesh.positions.len() as u32 - 1
+                        })
+                    };
+                let polygon = match (inside.as_slice(), outside.as_slice()) {
+                    (&[a], &[b, c, d]) => vec![vertex(a, b), vertex(a, c), vertex(a, d)],
+                    (&[a, b, c], &[d]) => vec![vertex(a, d), vertex(b, d), vertex(c, d)],
+                    (&[a, b], &[c, d]) => {
+                        vec![vertex(a, c), vertex(a, d), vertex(b, d), vertex(b, c)]
+                    }
+                    _ => unreachable!(),
+                };
+
+                // Faces point from the inside corners towards the outside ones
+                let center = |points: &[[i32; 3]]| {
+                    let count = points.len() as f32;
+                    [0, 1, 2].map(|axis| {
+                        points.iter().map(|point| point[axis] as f32).sum::<f32>() / count
+                    })
+                };
+                let (inner, outer) = (center(&inside), center(&outside));
+                let outward = [0, 1, 2].map(|axis| outer[axis] - inner[axis]);
+                for triangle in [[0, 1, 2], [0, 2, 3]] {
+                    let Some(&[a, b, c]) = triangle
+                        .iter()
+                        .map(|&corner| polygon.get(corner).copied())
+                        .collect::<Option<Vec<_>>>()
+                        .as_deref()
+                    else {
+                        continue;
+                    };
+                    let [pa, pb, pc] = [a, b, c].map(|index| mesh.positions[index as usize]);
+                    let normal = cross(
+                        [pb[0] - pa[0], pb[1] - pa[1], pb[2] - pa[2]],
+                        [pc[0] - pa[0], pc[1] - pa[1], pc[2] - pa[2]],
+                    );
+                    mesh.triangles.push(if dot(normal, outward) < 0.0 {
+                        [a, c, b]
+                    } else {
+                        [a, b, c]
+                    });
+                }
+            }
+        }
+
+        mesh
+    }
+
+    /// Volume of the tetrahedron between a triangle and the origin, positive when the triangle
+    /// winds counter-clockwise seen from outside.
+    fn signed_volume(&self, [a, b, c]: [u32; 3]) -> f32 {
+        let [a, b, c] = [a, b, c].map(|index| self.positions[index as usize]);
+        let normal = cross(b, c);
+
+        (a[0] * normal[0] + a[1] * normal[1] + a[2] * normal[2]) / 6.0
+    }
+
+    pub fn volume(&self) -> f32 {
+        self.triangles
+            .iter()
+            .map(|&triangle| self.signed_volume(triangle))
+            .sum()
+    }
+
+    /// True when every edge is shared by exactly two triangles, traversed once in each direction.
+    pub fn is_watertight(&self) -> bool {
+        let mut edges = HashMap::new();
+        for &[a, b, c] in &self.triangles {
+            for edge in [(a, b), (b, c), (c, a)] {
+                *edges.entry(edge).or_insert(0) += 1;
+            }
+        }
+
+        edges
+            .iter()
+            .all(|(&(a, b), &count)| count == 1 && edges.get(&(b, a)) == Some(&1))
+    }
+
+    fn facet_normal(&self, [a, b, c]: [u32; 3]) -> [f32; 3] {
+        let [a, b, c] = [a, b, c].map(|index| self.positions[index as usize]);
+        let normal = cross(
+            [b[0] - a[0], b[1] - a[1], b[2] - a[2]],
+            [c[0] - a[0], c[1] - a[1], c[2] - a[2]],
+        );
+        let length = (normal[0].powi(2) + normal[1].powi(2) + normal[2].powi(2)).sqrt();
+
+        if length > 0.0 {
+            normal.map(|value| value / length)
+        } else {
+            normal
+        }
+    }
+
+    /// Wavefront OBJ, with one material per color in a `.mtl` file next to it.
+    pub fn obj(&self, material_file: &str) -> (String, String) {
+        let mut colors = Vec::<[f32; 3]>::new();
+        let mut materials = HashMap::<[u32; 3], usize>::new();
+        let mut faces = HashMap::<usize, Vec<[u32; 3]>>::new();
+        for &triangle in &self.triangles {
+            let color = self.colors[triangle[0] as usize];
+            let material = *materials.entry(color.map(f32::to_bits)).or_insert_with(|| {
+                colors.push(color);
+                colors.len() - 1
+            });
+            faces.entry(material).or_default().push(triangle);
+        }
+
+        let mut obj = format!("# Biopix\nmtllib {}\n", material_file);
+        for position in &self.positions {
+            let _ = writeln!(obj, "v {} {} {}", position[0], position[1], position[2]);
+        }
+        for (material, _) in colors.iter().enumerate() {
+            let _ = writeln!(obj, "usemtl color{}", material);
+            for [a, b, c] in &faces[&material] {
+                // OBJ indices start at one
+                let _ = writeln!(obj, "f {} {} {}", a + 1, b + 1, c + 1);
+            }
+        }
+
+        let mut mtl = String::from("# Biopix\n");
+        for (material, color) in colors.iter().enumerate() {
+            let _ = writeln!(
+                mtl,
+                "newmtl color{}\nKd {} {} {}\nKa 0 0 0\nd 1\nillum 1\n",
+                material, color[0], color[1], color[2]
+            );
+        }
+
+        (obj, mtl)
+    }
+
+    /// ASCII PLY with a color per vertex.
+    pub fn ply(&self) -> String {
+        let mut ply = format!(
+            "ply\nformat ascii 1.0\ncomment Biopix\nelement vertex {}\n\
+             property float x\nproperty float y\nproperty float z\n\
+             property uchar red\nproperty uchar green\nproperty uchar blue\n\
+             element face {}\nproperty list uchar uint vertex_indices\nend_header\n",
+            self.positions.len(),
+            self.triangles.len()
+        );
+
+        for (position, color) in self.positions.iter().zip(&self.colors) {
+            let [red, green, blue] = color.map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round());
+            let _ = writeln!(
+                ply,
+                "{} {} {} {} {} {}",
+                position[0], position[1], position[2], red, green, blue
+            );
+        }
+        for [a, b, c] in &self.triangles {
+            let _ = writeln!(ply, "3 {} {} {}", a, b, c);
+        }
+
+        ply
+    }
+
+    /// Binary STL: an 80 byte header, the triangle count and 50 bytes per triangle.
+    pub fn stl(&self) -> Vec<u8> {
+        let mut stl = Vec::with_capacity(84 + 50 * self.triangles.len());
+
+        let mut header = [0u8; 80];
+        header[..6].copy_from_slice(b"Biopix");
+        stl.extend_from_slice(&header);
+        stl.extend_from_slice(&(self.triangles.len() as u32).to_le_bytes());
+
+        for &triangle in &self.triangles {
+            let corners = triangle.map(|index| self.positions[index as usize]);
+            for vector in std::iter::once(self.facet_normal(triangle)).chain(corners) {
+                for value in vector {
+                    stl.extend_from_slice(&value.to_le_bytes());
+                }
+            }
+            // Attribute byte count
+            stl.extend_from_slice(&[0, 0]);
+        }
+
+        stl
+    }
+}
+
+/// Writes the scene as one merged mesh in the format named by the extension of `path`: `.obj`
+/// (with a `.mtl` beside it), `.ply` or `.stl`.
+pub fn save(scene: &Scene, path: &Path) -> Result<(), String> {
+    let mesh = Mesh::from_scene(scene);
+    let write = |path: &Path, bytes: &[u8]| {
+        fs::write(path, bytes)
+            .map_err(|error| format!("Failed to save {}: {}", path.display(), error))
+    };
+
+    let extension = path
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+    match extension.as_deref() {
+        Some("obj") => {
+            let material_path = path.with_extension("mtl");
+            let material_file = material_path
+                .file_name()
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default();
+            let (obj, mtl) = mesh.obj(&material_file);
+            write(path, obj.as_bytes())?;
+            write(&material_path, mtl.as_bytes())
+        }
+        Some("ply") => write(path, mesh.ply().as_bytes()),
+        Some("stl") => write(path, &mesh.stl()),
+        _ => Err(format!(
+            "Cannot write a mesh to {}, expected .obj, .ply or .stl",
+            path.display()
+        )),
+    }
+}
+
+#[test]
+fn merged_mesh_is_closed_and_outward() {
+    use crate::style::{Representation, Style};
+
+    let style = Style {
+        representation: Representation::BallAndStick,
+        sphere_detail: (12, 8),
+        selection: Some("chain A and resi 10-12".parse().unwrap()),
+        ..Style::default()
+    };
+    let scene = Scene::open("1d66.pdb", &style).unwrap();
+    let mesh = Mesh::from_scene(&scene);
+
+    assert!(mesh.is_watertight());
+    // Sampled at print resolution, a few residues stay at a few thousand triangles
+    assert!(mesh.triangles.len() < 10_000);
+
+    // Overlapping atoms merge, so the union holds less than the spheres added up
+    let spacefill = Style {
+        representation: Representation::Spacefill,
+        ..style.clone()
+    };
+    let atoms = Scene::open("1d66.pdb", &spacefill).unwrap();
+    let union = Mesh::from_scene(&atoms);
+    let separate = atoms
+        .spheres
+        .iter()
+        .map(|sphere| {
+            let shell = Mesh {
+                positions: sphere
+                    .vertices()
+                    .chunks(3)
+                    .map(|position| [position[0], position[1], position[2]])
+                    .collect(),
+                colors: Vec::new(),
+                triangles: sphere
+                    .indices()
+                    .chunks(3)
+                    .map(|triangle| [triangle[0], triangle[1], triangle[2]])
+                    .collect(),
+            };
+            shell.volume().abs()
+        })
+        .sum::<f32>();
+    assert!(union.is_watertight());
+    assert!(union.volume() > 0.0 && union.volume() < separate);
+
+    let stl = mesh.stl();
+    assert_eq!(stl.len(), 84 + 50 * mesh.triangles.len());
+    let (obj, mtl) = mesh.obj("scene.mtl");
+    assert_eq!(
+        obj.lines().filter(|line| line.starts_with("f ")).count(),
+        mesh.triangles.len()
+    );
+    assert!(mtl.contains("newmtl color0"));
+    assert!(mesh
+        .ply()
+        .contains(&format!("element face {}", mesh.triangles.len())));
+}
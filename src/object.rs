@@ -8,6 +8,9 @@ pub trait Object {
     fn normal_vertices(&self) -> &Vec<f32>;
     fn indices(&self) -> &Vec<u32>;
     fn generate_interlaced_vertices(&mut self);
+    /// 1.0 is opaque, anything lower is drawn in the order independent transparency pass.
+    fn opacity(&self) -> f32;
+    fn set_opacity(&mut self, opacity: f32);
 
     fn translate(&mut self, x: f32, y: f32, z: f32) {
         let vertices = self.vertices_mut();
@@ -99,7 +102,10 @@ pub trait Object {
 
     fn interlaced_vertices(&self) -> &Vec<f32>;
 
-    unsafe fn drawer(&self, renderer: &mut opengl::Renderer) {
+    /// # Safety
+    ///
+    /// The renderer's context must be current with its vertex array and buffers bound.
+    unsafe fn drawer(&self, renderer: &opengl::Renderer) {
         let vertices = self.interlaced_vertices();
         let indices = self.indices();
 
@@ -117,90 +123,64 @@ pub trait Object {
             gl::STATIC_DRAW,
         );
 
-        // POSITION Attribute
-        let pos_attrib = renderer.gl.GetAttribLocation(
-            renderer.program.unwrap(),
-            b"position\0".as_ptr() as *const _,
-        );
-
-        renderer.gl.VertexAttribPointer(
-            pos_attrib as gl::types::GLuint,
-            3,
-            gl::FLOAT,
-            0,
-            9 * std::mem::size_of::<f32>() as gl::types::GLsizei,
-            std::ptr::null(),
-        );
-
-        // Normal Attribute
-        let normal_attrib = renderer
-            .gl
-            .GetAttribLocation(renderer.program.unwrap(), b"normal\0".as_ptr() as *const _);
-
-        renderer.gl.VertexAttribPointer(
-            normal_attrib as gl::types::GLuint,
-            3,
-            gl::FLOAT,
-            0,
-            9 * std::mem::size_of::<f32>() as gl::types::GLsizei,
-            (3 * std::mem::size_of::<f32>()) as *const _,
-        );
-
-        // COLOR Attribute
-        let color_attrib = renderer
-            .gl
-            .GetAttribLocation(renderer.program.unwrap(), b"color\0".as_ptr() as *const _);
-
-        renderer.gl.VertexAttribPointer(
-            color_attrib as gl::types::GLuint,
-            3,
-            gl::FLOAT,
-            0,
-            9 * std::mem::size_of::<f32>() as gl::types::GLsizei,
-            (6 * std::mem::size_of::<f32>()) as *const _,
-        );
-
-        renderer
-            .gl
-            .EnableVertexAttribArray(pos_attrib as gl::types::GLuint);
-
-        renderer
-            .gl
-            .EnableVertexAttribArray(normal_attrib as gl::types::GLuint);
-
-        renderer
-            .gl
-            .EnableVertexAttribArray(color_attrib as gl::types::GLuint);
+        // POSITION, NORMAL and COLOR attributes, interlaced in that order. Passes that do not
+        // read an attribute get -1 back from the driver, so skip those.
+        for (name, offset) in [(c"position", 0), (c"normal", 3), (c"color", 6)] {
+            let attrib = renderer
+                .gl
+                .GetAttribLocation(renderer.program.unwrap(), name.as_ptr());
+
+            if attrib < 0 {
+                continue;
+            }
+
+            renderer.gl.VertexAttribPointer(
+                attrib as gl::types::GLuint,
+                3,
+                gl::FLOAT,
+                0,
+                9 * std::mem::size_of::<f32>() as gl::types::GLsizei,
+                (offset * std::mem::size_of::<f32>()) as *const _,
+            );
+
+            renderer
+                .gl
+                .EnableVertexAttribArray(attrib as gl::types::GLuint);
+        }
 
         // Scale Attribute
         let scale_attrib = renderer
             .gl
-            .GetUniformLocation(renderer.program.unwrap(), b"scale\0".as_ptr() as *const _);
+            .GetUniformLocation(renderer.program.unwrap(), c"scale".as_ptr());
         renderer.gl.Uniform1f(scale_attrib, renderer.scale);
 
         // X Rotation Attribute
-        let x_rotate_attrib = renderer.gl.GetUniformLocation(
-            renderer.program.unwrap(),
-            b"x_rotate\0".as_ptr() as *const _,
-        );
+        let x_rotate_attrib = renderer
+            .gl
+            .GetUniformLocation(renderer.program.unwrap(), c"x_rotate".as_ptr());
         renderer
             .gl
             .Uniform1f(x_rotate_attrib, renderer.x_rotate.unwrap_or(0.0));
 
         // Y Rotation Attribute
-        let y_rotate_attrib = renderer.gl.GetUniformLocation(
-            renderer.program.unwrap(),
-            b"y_rotate\0".as_ptr() as *const _,
-        );
+        let y_rotate_attrib = renderer
+            .gl
+            .GetUniformLocation(renderer.program.unwrap(), c"y_rotate".as_ptr());
         renderer
             .gl
             .Uniform1f(y_rotate_attrib, renderer.y_rotate.unwrap_or(0.0));
 
+        // Opacity Attribute
+        let opacity_attrib = renderer
+            .gl
+            .GetUniformLocation(renderer.program.unwrap(), c"opacity".as_ptr());
+        renderer.gl.Uniform1f(opacity_attrib, self.opacity());
+
         renderer.gl.DrawElements(
             gl::TRIANGLES,
             indices.len() as i32,
             gl::UNSIGNED_INT,
-            0 as *const _,
+            std::ptr::null(),
         );
     }
 }
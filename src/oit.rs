@@ -0,0 +1,48 @@
+use crate::framebuffer::{Framebuffer, TextureFormat};
+use crate::gl;
+
+/// Targets of weighted blended order independent transparency (McGuire and Bavoil, 2013).
+///
+/// GL 3.3 has no per-buffer blend functions, so a single `BlendFuncSeparate` serves both
+/// outputs: `accumulation` sums weighted premultiplied color in rgb while its alpha multiplies
+/// up the revealage, and `weights` sums the weighted coverage in its red channel.
+pub struct Oit {
+    pub accumulation: Framebuffer,
+    pub weights: Framebuffer,
+}
+
+impl Oit {
+    pub fn new(gl: &gl::Gl, width: i32, height: i32) -> Oit {
+        let accumulation = Framebuffer::new(gl, width, height, TextureFormat::RGBA16F, true);
+        let weights = Framebuffer::new(gl, width, height, TextureFormat::R16F, false);
+
+        accumulation.attach_color(gl, 1, &weights);
+
+        Oit {
+            accumulation,
+            weights,
+        }
+    }
+
+    /// Binds both outputs and clears them to "nothing accumulated, everything revealed".
+    pub fn begin(&self, gl: &gl::Gl) {
+        self.accumulation.bind(gl);
+
+        unsafe {
+            gl.DrawBuffers(2, [gl::COLOR_ATTACHMENT0, gl::COLOR_ATTACHMENT1].as_ptr());
+            gl.ClearBufferfv(gl::COLOR, 0, [0.0, 0.0, 0.0, 1.0].as_ptr());
+            gl.ClearBufferfv(gl::COLOR, 1, [0.0, 0.0, 0.0, 0.0].as_ptr());
+            gl.Clear(gl::DEPTH_BUFFER_BIT);
+        }
+    }
+
+    pub fn resize(&self, gl: &gl::Gl, width: i32, height: i32) {
+        self.accumulation.resize(gl, width, height);
+        self.weights.resize(gl, width, height);
+    }
+
+    pub fn delete(&self, gl: &gl::Gl) {
+        self.accumulation.delete(gl);
+        self.weights.delete(gl);
+    }
+}
@@ -1,11 +1,14 @@
 use std::ffi::{CStr, CString};
-use std::fs::File;
-use std::io::Read;
 
 use std::num::NonZeroU32;
 use std::ops::Deref;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
 
-use winit::event::{Event, WindowEvent};
+use winit::dpi::PhysicalSize;
+use winit::event::{
+    ElementState, Event, KeyboardInput, ModifiersState, VirtualKeyCode, WindowEvent,
+};
 use winit::event_loop::EventLoopBuilder;
 use winit::platform::run_return::EventLoopExtRunReturn;
 use winit::window::{Window, WindowBuilder};
@@ -13,56 +16,145 @@ use winit::window::{Window, WindowBuilder};
 use raw_window_handle::HasRawWindowHandle;
 
 use glutin::config::{Config, ConfigTemplateBuilder};
-use glutin::context::{ContextApi, ContextAttributesBuilder};
+use glutin::context::{ContextApi, ContextAttributesBuilder, GlProfile, Version};
 use glutin::display::GetGlDisplay;
 use glutin::prelude::*;
 use glutin::surface::{Surface, SurfaceAttributesBuilder, SwapInterval, WindowSurface};
 
 use glutin_winit::{self, DisplayBuilder};
-use lazy_static::lazy_static;
+
+use crate::control::{self, ControlAddress, ControlRequest, ControlServer};
+use crate::effects::{FogSettings, OutlineSettings};
+use crate::framebuffer::{Framebuffer, ScreenQuad, TextureFormat};
+use crate::math::{normalize, IDENTITY};
+use crate::oit::Oit;
+use crate::ramachandran::{self, Panel, Plot};
+use crate::scene::{RenderLayer, Scene};
+use crate::script::{Console, Interpreter};
+use crate::sequence::{Bar, SequencePanel, CELL_WIDTH};
+use crate::session::{Camera, Session};
+use crate::shader::{self, GlslTarget, ShaderError, ShaderProgram, ShaderWatcher};
+use crate::shadow::{light_matrix, LightSettings, ShadowMap, SHADOW_RADIUS};
+use crate::ssao::{Ssao, SsaoSettings};
 
 pub mod gl {
     #![allow(clippy::all)]
     include!(concat!(env!("OUT_DIR"), "/gl_bindings.rs"));
 }
 
-fn get_fragment_shader() -> Vec<u8> {
-    let mut fragment_file = File::open("src/shaders/fragment.glsl").unwrap();
-
-    let mut fragment_content = Vec::new();
-
-    fragment_file.read_to_end(&mut fragment_content).unwrap();
-
-    fragment_content.push(0);
-
-    return fragment_content;
+/// Farthest the cursor may move in pixels between press and release for a click, which picks
+/// an atom instead of turning the view.
+const CLICK_DISTANCE: f64 = 3.0;
+
+/// User facing switches for the optional rendering passes.
+#[derive(Debug, Clone, Copy)]
+pub struct RenderSettings {
+    /// Requested MSAA samples per pixel, 0 disables multisampling.
+    pub samples: u8,
+    /// Falls back to FXAA when the window surface has fewer samples than requested.
+    pub fxaa: bool,
+    /// Sector and stack count used when tessellating atom spheres.
+    pub sphere_detail: (u32, u32),
+    pub background: [f32; 3],
+    pub light: LightSettings,
+    pub ssao: SsaoSettings,
+    pub outline: OutlineSettings,
+    pub fog: FogSettings,
 }
 
-fn get_vertex_shaders() -> Vec<u8> {
-    let mut vertex_file = File::open("src/shaders/vertex.glsl").unwrap();
-
-    let mut vertex_contents = Vec::new();
-
-    vertex_file.read_to_end(&mut vertex_contents).unwrap();
-
-    vertex_contents.push(0);
+impl Default for RenderSettings {
+    fn default() -> Self {
+        Self {
+            samples: 0,
+            fxaa: false,
+            sphere_detail: (crate::scene::SPHERE_SECTOR, crate::scene::SPHERE_STACK),
+            background: [0.1, 0.1, 0.1],
+            light: LightSettings::default(),
+            ssao: SsaoSettings::default(),
+            outline: OutlineSettings::default(),
+            fog: FogSettings::default(),
+        }
+    }
+}
 
-    return vertex_contents;
+/// Window and view state set up before the first frame.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ViewerOptions {
+    /// Overrides the embedded shaders with the files in this directory and reloads them
+    /// whenever they change.
+    pub shader_dir: Option<PathBuf>,
+    /// Inner window size in pixels, the platform default when `None`.
+    pub window_size: Option<(u32, u32)>,
+    /// Initial turn and tilt in radians, as set by dragging horizontally and vertically.
+    pub orientation: [f32; 2],
+    pub zoom: f32,
+    /// Session restored once the renderer is ready, before the script runs.
+    pub session: Option<PathBuf>,
+    /// Command script run once the renderer is ready.
+    pub script: Option<PathBuf>,
+    /// Renders a single frame into this PNG file after the script ran.
+    pub output: Option<PathBuf>,
+    /// Keeps the window hidden and exits once the script and output are done, unless a control
+    /// server keeps it running.
+    pub headless: bool,
+    /// Serves JSON-RPC requests from other processes on this address.
+    pub control: Option<ControlAddress>,
 }
 
-lazy_static! {
-    static ref VERTEX_SHADER: Vec<u8> = get_vertex_shaders();
-    static ref FRAGMENT_SHADER: Vec<u8> = get_fragment_shader();
+impl Default for ViewerOptions {
+    fn default() -> Self {
+        Self {
+            shader_dir: None,
+            window_size: None,
+            orientation: [0.0, 0.0],
+            zoom: 1.0,
+            session: None,
+            script: None,
+            output: None,
+            headless: false,
+            control: None,
+        }
+    }
 }
 
-pub fn init<'a>(scene: &'a crate::scene::Scene) -> () {
-    let mut event_loop = EventLoopBuilder::new().build();
+/// Opens the viewer on `scene`. Script lines, from `options.script` or typed into the console
+/// opened with `:` or sent to the control server, run through `interpreter`.
+pub fn init(
+    scene: Scene,
+    settings: RenderSettings,
+    mut options: ViewerOptions,
+    mut interpreter: Interpreter,
+) {
+    let mut event_loop = EventLoopBuilder::<ControlRequest>::with_user_event().build();
+
+    // Requests are answered on the event loop, where the renderer lives
+    let _control_server = options.control.clone().and_then(|address| {
+        let proxy = Mutex::new(event_loop.create_proxy());
+        match ControlServer::start(address.clone(), move |request| {
+            proxy.lock().unwrap().send_event(request).is_ok()
+        }) {
+            Ok(server) => {
+                println!("Listening for control requests on {:?}", address);
+                Some(server)
+            }
+            Err(error) => {
+                eprintln!(
+                    "Failed to start the control server on {:?}: {}",
+                    address, error
+                );
+                None
+            }
+        }
+    });
 
-    let window_builder = Some(
-        WindowBuilder::new()
-            .with_title("Biopix")
-            .with_transparent(false),
-    );
+    let mut window_builder = WindowBuilder::new()
+        .with_title("Biopix")
+        .with_transparent(false)
+        .with_visible(!options.headless);
+    if let Some((width, height)) = options.window_size {
+        window_builder = window_builder.with_inner_size(PhysicalSize::new(width, height));
+    }
+    let window_builder = Some(window_builder);
 
     let template = ConfigTemplateBuilder::new();
 
@@ -70,15 +162,25 @@ pub fn init<'a>(scene: &'a crate::scene::Scene) -> () {
 
     let (mut window, gl_config) = display_builder
         .build(&event_loop, template, |configs| {
+            // Closest to the requested sample count, preferring more samples over fewer
+            let samples_distance = |config: &Config| {
+                let samples = config.num_samples();
+                if samples >= settings.samples {
+                    (0, samples - settings.samples)
+                } else {
+                    (1, settings.samples - samples)
+                }
+            };
+
             configs
                 .reduce(|accum, config| {
                     let transparency_check = config.supports_transparency().unwrap_or(false)
                         & !accum.supports_transparency().unwrap_or(false);
 
-                    if transparency_check || config.num_samples() > accum.num_samples() {
-                        config
-                    } else {
-                        accum
+                    match samples_distance(&config).cmp(&samples_distance(&accum)) {
+                        std::cmp::Ordering::Less => config,
+                        std::cmp::Ordering::Equal if transparency_check => config,
+                        _ => accum,
                     }
                 })
                 .unwrap()
@@ -89,10 +191,14 @@ pub fn init<'a>(scene: &'a crate::scene::Scene) -> () {
 
     let gl_display = gl_config.display();
 
-    let context_attributes = ContextAttributesBuilder::new().build(raw_window_handle);
+    // The shaders are translated for whichever of these two the driver accepts
+    let context_attributes = ContextAttributesBuilder::new()
+        .with_context_api(ContextApi::OpenGl(Some(Version::new(3, 3))))
+        .with_profile(GlProfile::Core)
+        .build(raw_window_handle);
 
     let fallback_context_attributes = ContextAttributesBuilder::new()
-        .with_context_api(ContextApi::Gles(None))
+        .with_context_api(ContextApi::Gles(Some(Version::new(3, 0))))
         .build(raw_window_handle);
 
     let mut not_current_gl_context = Some(unsafe {
@@ -108,16 +214,22 @@ pub fn init<'a>(scene: &'a crate::scene::Scene) -> () {
     let mut state = None;
     let mut renderer = None;
     let mut mouse_hold = false;
+    // Sequence and first and last residues being dragged over in the sequence bar
+    let mut sequence_drag: Option<(usize, usize, usize)> = None;
+    // Where the button went down in the view, a click if released there
+    let mut press: Option<(f64, f64)> = None;
 
     let mut prev_x = 0.0;
     let mut prev_y = 0.0;
 
-    let mut x_diff = 0.0;
-    let mut y_diff = 0.0;
+    let mut scene = Some(scene);
+    let mut console = Console::default();
+
+    let mut modifiers = ModifiersState::empty();
 
     let event_loop_closure = {
-        move |event: Event<()>,
-              window_target: &winit::event_loop::EventLoopWindowTarget<()>,
+        move |event: Event<ControlRequest>,
+              window_target: &winit::event_loop::EventLoopWindowTarget<ControlRequest>,
               control_flow: &mut winit::event_loop::ControlFlow| {
             control_flow.set_wait();
             match event {
@@ -136,7 +248,30 @@ pub fn init<'a>(scene: &'a crate::scene::Scene) -> () {
                         .make_current(&gl_window.surface)
                         .unwrap();
 
-                    renderer.get_or_insert_with(|| Renderer::new(&gl_display, scene));
+                    let (width, height): (u32, u32) = gl_window.window.inner_size().into();
+                    if renderer.is_none() {
+                        match Renderer::new(
+                            &gl_display,
+                            scene.take().unwrap_or_default(),
+                            settings,
+                            gl_config.num_samples(),
+                            width as i32,
+                            height as i32,
+                            options.shader_dir.clone(),
+                        ) {
+                            Ok(mut new_renderer) => {
+                                new_renderer.scale *= options.zoom;
+                                new_renderer.x_rotate = Some(options.orientation[0]);
+                                new_renderer.y_rotate = Some(options.orientation[1]);
+                                renderer = Some(new_renderer);
+                            }
+                            Err(error) => {
+                                eprintln!("{}", error);
+                                control_flow.set_exit();
+                                return;
+                            }
+                        }
+                    }
 
                     if let Err(res) = gl_window.surface.set_swap_interval(
                         &gl_context,
@@ -146,6 +281,37 @@ pub fn init<'a>(scene: &'a crate::scene::Scene) -> () {
                     }
 
                     assert!(state.replace((gl_context, gl_window)).is_none());
+
+                    if let Some(renderer) = renderer.as_mut() {
+                        if let Some(path) = options.session.take() {
+                            let restored = Session::load(&path)
+                                .and_then(|session| session.restore(&mut interpreter, renderer));
+                            if let Err(error) = restored {
+                                eprintln!("{}", error);
+                            }
+                        }
+
+                        if let Some(path) = options.script.take() {
+                            if let Err(error) = interpreter.run_file(renderer, &path) {
+                                eprintln!("{}", error);
+                            }
+                        }
+
+                        if let Some(path) = options.output.take() {
+                            match renderer.save_png(&path) {
+                                Ok(()) => println!("Saved {}", path.display()),
+                                Err(error) => {
+                                    eprintln!("Failed to save {}: {}", path.display(), error)
+                                }
+                            }
+                        }
+
+                        if (options.headless && options.control.is_none())
+                            || interpreter.quit_requested
+                        {
+                            control_flow.set_exit();
+                        }
+                    }
                 }
                 Event::Suspended => {
                     let (gl_context, _) = state.take().unwrap();
@@ -154,43 +320,117 @@ pub fn init<'a>(scene: &'a crate::scene::Scene) -> () {
                         .is_none());
                 }
                 Event::WindowEvent { event, .. } => match event {
-                    WindowEvent::Resized(size) => {
-                        if size.width != 0 && size.height != 0 {
-                            if let Some((gl_context, gl_window)) = &state {
-                                gl_window.surface.resize(
-                                    gl_context,
-                                    NonZeroU32::new(size.width).unwrap(),
-                                    NonZeroU32::new(size.height).unwrap(),
-                                );
-                                let renderer = renderer.as_ref().unwrap();
-                                renderer.resize(size.width as i32, size.height as i32);
-                            }
+                    WindowEvent::Resized(size) if size.width != 0 && size.height != 0 => {
+                        if let Some((gl_context, gl_window)) = &state {
+                            gl_window.surface.resize(
+                                gl_context,
+                                NonZeroU32::new(size.width).unwrap(),
+                                NonZeroU32::new(size.height).unwrap(),
+                            );
+                            let renderer = renderer.as_mut().unwrap();
+                            renderer.resize(size.width as i32, size.height as i32);
                         }
                     }
                     WindowEvent::CloseRequested => {
                         control_flow.set_exit();
                     }
-                    WindowEvent::MouseWheel { delta, .. } => match delta {
-                        winit::event::MouseScrollDelta::LineDelta(_, dirn) => {
-                            if dirn < 0.0 {
-                                let current_scale = renderer.as_ref().unwrap().scale;
-                                renderer.as_mut().unwrap().scale = current_scale - 0.002;
-                            } else {
-                                let current_scale = renderer.as_ref().unwrap().scale;
-                                renderer.as_mut().unwrap().scale = current_scale + 0.002;
+                    WindowEvent::MouseWheel {
+                        delta: winit::event::MouseScrollDelta::LineDelta(_, dirn),
+                        ..
+                    } => {
+                        let renderer = renderer.as_mut().unwrap();
+                        // Over the sequence bar the wheel scrolls along the residues, or
+                        // through the chains with Shift
+                        let cursor = [prev_x as f32, prev_y as f32];
+                        if let Some(bar) =
+                            renderer.sequence_bar().filter(|bar| bar.contains(cursor))
+                        {
+                            let panel = renderer.sequences.as_mut().unwrap();
+                            match modifiers.shift() {
+                                true => panel.scroll_by(&bar, 0.0, -dirn.signum() as isize),
+                                false => panel.scroll_by(&bar, -dirn * 5.0 * CELL_WIDTH, 0),
                             }
+                        } else if dirn < 0.0 {
+                            renderer.scale -= 0.002;
+                        } else {
+                            renderer.scale += 0.002;
+                        }
+                    }
+                    WindowEvent::ModifiersChanged(state) => {
+                        modifiers = state;
+                    }
+                    WindowEvent::KeyboardInput {
+                        input:
+                            KeyboardInput {
+                                state: ElementState::Pressed,
+                                virtual_keycode: Some(key),
+                                ..
+                            },
+                        ..
+                    } => {
+                        let Some(renderer) = renderer.as_mut() else {
+                            return;
+                        };
+
+                        if !console.is_open() {
+                            renderer.handle_key(key, modifiers);
+                            return;
                         }
-                        _ => {}
-                    },
-                    WindowEvent::CursorMoved { position, .. } => {
-                        if mouse_hold {
-                            x_diff += prev_x - position.x;
 
-                            renderer.as_mut().unwrap().x_rotate = Some(x_diff as f32 / 200.0);
+                        match key {
+                            VirtualKeyCode::Return | VirtualKeyCode::NumpadEnter => {
+                                let line = console.submit().unwrap_or_default();
+                                println!(":{}", line);
+                                if let Err(error) = interpreter.run_line(renderer, &line) {
+                                    eprintln!("{}", error);
+                                }
+                                if interpreter.quit_requested {
+                                    control_flow.set_exit();
+                                }
+                            }
+                            VirtualKeyCode::Back => console.backspace(),
+                            VirtualKeyCode::Escape => console.close(),
+                            _ => (),
+                        }
 
-                            y_diff += prev_y - position.y;
+                        if let Some((_, gl_window)) = &state {
+                            gl_window.window.set_title(&console.title());
+                        }
+                    }
+                    WindowEvent::ReceivedCharacter(character) => {
+                        if console.is_open() && !character.is_control() {
+                            console.push(character);
+                        } else if character == ':' {
+                            console.open();
+                        } else {
+                            return;
+                        }
 
-                            renderer.as_mut().unwrap().y_rotate = Some(y_diff as f32 / 200.0);
+                        if let Some((_, gl_window)) = &state {
+                            gl_window.window.set_title(&console.title());
+                        }
+                    }
+                    WindowEvent::CursorMoved { position, .. } => {
+                        if let (Some((index, start, end)), Some(renderer)) =
+                            (sequence_drag, renderer.as_mut())
+                        {
+                            let bar = renderer.sequence_bar();
+                            let reached = bar
+                                .zip(renderer.sequences.as_ref())
+                                .map(|(bar, panel)| panel.drag(&bar, index, position.x as f32));
+                            if let Some(reached) = reached.filter(|&reached| reached != end) {
+                                sequence_drag = Some((index, start, reached));
+                                interpreter.select_sequence(
+                                    renderer,
+                                    index,
+                                    start.min(reached)..=start.max(reached),
+                                );
+                            }
+                        } else if mouse_hold {
+                            renderer.as_mut().unwrap().rotate(
+                                (prev_x - position.x) as f32 / 200.0,
+                                (prev_y - position.y) as f32 / 200.0,
+                            );
                         }
                         prev_x = position.x;
                         prev_y = position.y;
@@ -198,12 +438,70 @@ pub fn init<'a>(scene: &'a crate::scene::Scene) -> () {
 
                     WindowEvent::MouseInput { state, .. } => {
                         mouse_hold = match state {
-                            winit::event::ElementState::Pressed => true,
-                            winit::event::ElementState::Released => false,
+                            ElementState::Pressed => true,
+                            ElementState::Released => false,
+                        };
+
+                        // Clicks on the Ramachandran plot or the sequence bar pick residues
+                        // instead of rotating
+                        let Some(renderer) = renderer.as_mut() else {
+                            return;
+                        };
+                        let cursor = [prev_x as f32, prev_y as f32];
+                        sequence_drag = None;
+                        match press.take() {
+                            _ if mouse_hold => press = Some((prev_x, prev_y)),
+                            Some((x, y)) if (x - prev_x).hypot(y - prev_y) < CLICK_DISTANCE => {
+                                if let Some(index) = renderer.pick(cursor) {
+                                    interpreter.pick(renderer, index);
+                                }
+                            }
+                            _ => (),
+                        }
+                        let bar = renderer.sequence_bar().filter(|bar| bar.contains(cursor));
+                        if let (Some(bar), Some(panel), true) =
+                            (bar, renderer.sequences.as_ref(), mouse_hold)
+                        {
+                            mouse_hold = false;
+                            press = None;
+                            if let Some((index, residue)) = panel.pick(&bar, cursor) {
+                                sequence_drag = Some((index, residue, residue));
+                                interpreter.select_sequence(renderer, index, residue..=residue);
+                            }
+                        }
+                        let panel = renderer.plot_panel();
+                        if let Some(plot) = renderer.plot.as_ref().filter(|_| mouse_hold) {
+                            if panel.contains(cursor) {
+                                mouse_hold = false;
+                                press = None;
+                                if let Some(index) = panel.pick(&plot.points, cursor) {
+                                    interpreter.focus(renderer, index);
+                                }
+                            }
                         }
                     }
                     _ => (),
                 },
+                Event::UserEvent(request) => {
+                    let Some(renderer) = renderer.as_mut() else {
+                        request.respond(Err(control::RpcError::new(
+                            control::RpcError::FAILED,
+                            "The viewer is not ready",
+                        )));
+                        return;
+                    };
+
+                    let result = control::dispatch(
+                        &mut interpreter,
+                        renderer,
+                        &request.method,
+                        &request.params,
+                    );
+                    request.respond(result);
+                    if interpreter.quit_requested {
+                        control_flow.set_exit();
+                    }
+                }
                 Event::RedrawEventsCleared => {
                     if let Some((gl_context, gl_window)) = &state {
                         let renderer = renderer.as_mut().unwrap();
@@ -247,7 +545,7 @@ impl GlWindow {
     }
 }
 
-pub struct Renderer<'a> {
+pub struct Renderer {
     pub vao: gl::types::GLuint,
     pub vbo: gl::types::GLuint,
     pub ibo: gl::types::GLuint,
@@ -256,11 +554,53 @@ pub struct Renderer<'a> {
     pub scale: f32,
     pub x_rotate: Option<f32>,
     pub y_rotate: Option<f32>,
-    pub scene: &'a crate::scene::Scene,
+    pub scene: Scene,
+    pub settings: RenderSettings,
+    /// MSAA samples the window surface actually got.
+    pub samples: u8,
+    pub width: i32,
+    pub height: i32,
+    /// Framebuffer the finished frame ends up in, 0 for the window.
+    pub output_fbo: gl::types::GLuint,
+    /// Ramachandran plot drawn over the bottom right corner, when shown.
+    pub plot: Option<Plot>,
+    /// Sequence bar drawn along the top, when shown.
+    pub sequences: Option<SequencePanel>,
+
+    lighting_program: ShaderProgram,
+    normal_program: ShaderProgram,
+    ssao_program: ShaderProgram,
+    blur_program: ShaderProgram,
+    outline_program: ShaderProgram,
+    shadow_program: ShaderProgram,
+    oit_composite_program: ShaderProgram,
+    fxaa_program: ShaderProgram,
+    overlay_program: ShaderProgram,
+    gbuffer: Framebuffer,
+    color_target: Framebuffer,
+    shadow_map: ShadowMap,
+    ssao: Ssao,
+    oit: Oit,
+    quad: ScreenQuad,
+    /// The plot image, reuploaded when the plot changes or the panel is resized.
+    plot_image: Framebuffer,
+    plot_image_size: i32,
+    /// The sequence bar image, reuploaded like the plot's.
+    sequence_image: Framebuffer,
+    sequence_image_size: (i32, i32),
+    shader_watcher: Option<ShaderWatcher>,
 }
 
-impl<'a> Renderer<'a> {
-    pub fn new<D>(gl_display: &D, scene: &'a crate::scene::Scene) -> Self
+impl Renderer {
+    pub fn new<D>(
+        gl_display: &D,
+        scene: Scene,
+        settings: RenderSettings,
+        samples: u8,
+        width: i32,
+        height: i32,
+        shader_dir: Option<PathBuf>,
+    ) -> Result<Self, ShaderError>
     where
         D: GlDisplay,
     {
@@ -273,66 +613,718 @@ impl<'a> Renderer<'a> {
             if let Some(renderer) = get_gl_string(&gl, gl::RENDERER) {
                 println!("Running on {}", renderer.to_string_lossy());
             }
+            let mut target = GlslTarget::Core330;
             if let Some(version) = get_gl_string(&gl, gl::VERSION) {
                 println!("OpenGL Version {}", version.to_string_lossy());
+                target = GlslTarget::from_version(&version.to_string_lossy());
             }
 
             if let Some(shaders_version) = get_gl_string(&gl, gl::SHADING_LANGUAGE_VERSION) {
                 println!("Shaders version on {}", shaders_version.to_string_lossy());
             }
 
-            Self {
-                vao: std::mem::zeroed(),
-                vbo: std::mem::zeroed(),
-                ibo: std::mem::zeroed(),
+            let dir = shader_dir.as_deref();
+            let lighting_program =
+                ShaderProgram::new(&gl, target, shader::VERTEX, shader::FRAGMENT, dir)?;
+            let normal_program =
+                ShaderProgram::new(&gl, target, shader::VERTEX, shader::NORMAL_FRAGMENT, dir)?;
+            let ssao_program =
+                ShaderProgram::new(&gl, target, shader::QUAD_VERTEX, shader::SSAO_FRAGMENT, dir)?;
+            let blur_program =
+                ShaderProgram::new(&gl, target, shader::QUAD_VERTEX, shader::BLUR_FRAGMENT, dir)?;
+            let outline_program = ShaderProgram::new(
+                &gl,
+                target,
+                shader::QUAD_VERTEX,
+                shader::OUTLINE_FRAGMENT,
+                dir,
+            )?;
+            let shadow_program =
+                ShaderProgram::new(&gl, target, shader::VERTEX, shader::SHADOW_FRAGMENT, dir)?;
+            let oit_composite_program = ShaderProgram::new(
+                &gl,
+                target,
+                shader::QUAD_VERTEX,
+                shader::OIT_COMPOSITE_FRAGMENT,
+                dir,
+            )?;
+            let fxaa_program =
+                ShaderProgram::new(&gl, target, shader::QUAD_VERTEX, shader::FXAA_FRAGMENT, dir)?;
+            let overlay_program = ShaderProgram::new(
+                &gl,
+                target,
+                shader::QUAD_VERTEX,
+                shader::OVERLAY_FRAGMENT,
+                dir,
+            )?;
+
+            println!(
+                "MSAA: {} samples requested, {} available",
+                settings.samples, samples
+            );
+
+            let mut vao = 0;
+            let mut vbo = 0;
+            let mut ibo = 0;
+            gl.GenVertexArrays(1, &mut vao);
+            gl.GenBuffers(1, &mut vbo);
+            gl.GenBuffers(1, &mut ibo);
+
+            let gbuffer = Framebuffer::new(&gl, width, height, TextureFormat::RGBA16F, true);
+            let color_target = Framebuffer::new(&gl, width, height, TextureFormat::RGBA8, true);
+            let shadow_map = ShadowMap::new(&gl, settings.light.shadow_resolution);
+            let ssao = Ssao::new(&gl, width, height);
+            let oit = Oit::new(&gl, width, height);
+            let quad = ScreenQuad::new(&gl);
+            let plot_image = Framebuffer::new(&gl, 1, 1, TextureFormat::RGBA8, false);
+            let sequence_image = Framebuffer::new(&gl, 1, 1, TextureFormat::RGBA8, false);
+
+            Ok(Self {
+                vao,
+                vbo,
+                ibo,
                 program: None,
                 gl,
                 scale: 0.1,
                 x_rotate: None,
                 y_rotate: None,
                 scene,
-            }
+                settings,
+                samples,
+                width,
+                height,
+                output_fbo: 0,
+                plot: None,
+                sequences: None,
+                lighting_program,
+                normal_program,
+                ssao_program,
+                blur_program,
+                outline_program,
+                shadow_program,
+                gbuffer,
+                oit_composite_program,
+                fxaa_program,
+                overlay_program,
+                color_target,
+                shadow_map,
+                ssao,
+                oit,
+                quad,
+                plot_image,
+                plot_image_size: 0,
+                sequence_image,
+                sequence_image_size: (0, 0),
+                shader_watcher: shader_dir.map(ShaderWatcher::new),
+            })
         }
     }
 
     pub fn draw(&mut self) {
+        if self
+            .shader_watcher
+            .as_mut()
+            .is_some_and(|watcher| watcher.changed())
+        {
+            self.reload_shaders();
+        }
+
         unsafe {
-            let vertex_shader = create_shader(&self.gl, gl::VERTEX_SHADER, &VERTEX_SHADER);
-            let fragment_shader = create_shader(&self.gl, gl::FRAGMENT_SHADER, &FRAGMENT_SHADER);
+            self.gl.Enable(gl::DEPTH_TEST);
+            self.gl.DepthFunc(gl::LESS);
+
+            if self.settings.light.shadows {
+                self.shadow_pass();
+            }
+
+            if self.settings.ssao.enabled || self.settings.outline.enabled {
+                self.normal_depth_pass();
+            }
+
+            if self.settings.ssao.enabled {
+                self.ambient_occlusion_pass();
+            }
 
-            self.program = Some(self.gl.CreateProgram());
+            if self.samples > 0 {
+                self.gl.Enable(gl::MULTISAMPLE);
+            }
 
-            self.gl.AttachShader(self.program.unwrap(), vertex_shader);
+            let [red, green, blue] = self.settings.background;
+            self.bind_output();
+            self.gl.ClearColor(red, green, blue, 1.0);
+            self.gl.Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+
+            self.gl.UseProgram(self.lighting_program.id);
+            self.set_uniform_1i(
+                self.lighting_program.id,
+                c"ssao_enabled",
+                self.settings.ssao.enabled as i32,
+            );
+            self.set_uniform_2f(
+                self.lighting_program.id,
+                c"viewport",
+                self.width as f32,
+                self.height as f32,
+            );
+            self.bind_texture(
+                self.lighting_program.id,
+                c"ambient_occlusion",
+                0,
+                self.ssao.blurred.texture,
+            );
+
+            let fog = self.settings.fog;
+            self.set_uniform_1i(self.lighting_program.id, c"fog_enabled", fog.enabled as i32);
+            self.set_uniform_1f(self.lighting_program.id, c"fog_start", fog.start);
+            self.set_uniform_1f(self.lighting_program.id, c"fog_end", fog.end);
+            self.set_uniform_3f(
+                self.lighting_program.id,
+                c"background",
+                self.settings.background,
+            );
+
+            let light = self.settings.light;
+            self.set_uniform_3f(
+                self.lighting_program.id,
+                c"light_direction",
+                normalize(light.direction),
+            );
+            self.set_uniform_3f(self.lighting_program.id, c"light_color", light.color);
+            self.set_uniform_1i(
+                self.lighting_program.id,
+                c"shadows_enabled",
+                light.shadows as i32,
+            );
+            self.bind_texture(
+                self.lighting_program.id,
+                c"shadow_map",
+                1,
+                self.shadow_map.texture,
+            );
+            self.set_uniform_1f(
+                self.lighting_program.id,
+                c"shadow_texel_size",
+                1.0 / self.shadow_map.resolution as f32,
+            );
+            self.set_uniform_1i(self.lighting_program.id, c"shadow_softness", light.softness);
+
+            self.set_uniform_1i(self.lighting_program.id, c"oit_pass", 0);
+
+            self.render_scene(self.lighting_program.id, &IDENTITY, RenderLayer::Opaque);
+
+            if self.scene.has_transparent() {
+                self.transparency_pass();
+            }
 
-            self.gl.AttachShader(self.program.unwrap(), fragment_shader);
+            if self.settings.outline.enabled {
+                self.outline_pass();
+            }
 
-            self.gl.LinkProgram(self.program.unwrap());
+            if self.fxaa_active() {
+                self.fxaa_pass();
+            }
 
-            self.gl.UseProgram(self.program.unwrap());
+            if self.sequences.is_some() {
+                self.sequence_pass();
+            }
+            if self.plot.is_some() {
+                self.plot_pass();
+            }
+        }
+    }
 
-            self.gl.GenVertexArrays(1, &mut self.vao);
-            self.gl.BindVertexArray(self.vao);
+    fn programs_mut(&mut self) -> [&mut ShaderProgram; 9] {
+        [
+            &mut self.lighting_program,
+            &mut self.normal_program,
+            &mut self.ssao_program,
+            &mut self.blur_program,
+            &mut self.outline_program,
+            &mut self.shadow_program,
+            &mut self.oit_composite_program,
+            &mut self.fxaa_program,
+            &mut self.overlay_program,
+        ]
+    }
 
-            self.gl.GenBuffers(1, &mut self.vbo);
-            self.gl.BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+    /// Rebuilds every program from the watched shader directory. Programs that fail to build
+    /// report why and keep running their previous version.
+    pub fn reload_shaders(&mut self) {
+        let Some(dir) = self
+            .shader_watcher
+            .as_ref()
+            .map(|watcher| watcher.dir.clone())
+        else {
+            return;
+        };
 
-            self.gl.GenBuffers(1, &mut self.ibo);
-            self.gl.BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.ibo);
+        let gl = self.gl.clone();
+        let mut failed = false;
+        for program in self.programs_mut() {
+            if let Err(error) = program.reload(&gl, Some(&dir)) {
+                eprintln!("{}", error);
+                failed = true;
+            }
+        }
 
-            self.gl.Enable(gl::DEPTH_TEST);
-            self.gl.DepthFunc(gl::LESS);
+        if !failed {
+            println!("Reloaded shaders from {}", dir.display());
+        }
+    }
 
-            self.gl.Clear(gl::COLOR_BUFFER_BIT);
-            self.gl.Clear(gl::DEPTH_BUFFER_BIT);
-            self.gl.ClearColor(0.1, 0.1, 0.1, 1.0);
+    /// FXAA stands in for multisampling the surface could not provide.
+    pub fn fxaa_active(&self) -> bool {
+        self.settings.fxaa && self.samples < self.settings.samples
+    }
 
-            self.scene.render(self);
+    /// Binds the framebuffer the lit scene is drawn into, the window's unless FXAA needs an
+    /// offscreen copy to filter.
+    unsafe fn bind_output(&self) {
+        if self.fxaa_active() {
+            self.color_target.bind(&self.gl);
+        } else {
+            self.gl.BindFramebuffer(gl::FRAMEBUFFER, self.output_fbo);
         }
     }
 
-    pub fn resize(&self, width: i32, height: i32) {
+    /// Filters the offscreen color target onto the window.
+    unsafe fn fxaa_pass(&mut self) {
+        self.gl.BindFramebuffer(gl::FRAMEBUFFER, self.output_fbo);
+        self.gl.Disable(gl::DEPTH_TEST);
+
+        self.gl.UseProgram(self.fxaa_program.id);
+        self.bind_texture(self.fxaa_program.id, c"color", 0, self.color_target.texture);
+        self.set_uniform_2f(
+            self.fxaa_program.id,
+            c"texel_size",
+            1.0 / self.width as f32,
+            1.0 / self.height as f32,
+        );
+        self.quad.draw(&self.gl, self.fxaa_program.id);
+
+        self.gl.Enable(gl::DEPTH_TEST);
+    }
+
+    /// Where the Ramachandran plot sits in the window.
+    pub fn plot_panel(&self) -> Panel {
+        Panel::place(self.width as f32, self.height as f32)
+    }
+
+    /// Draws the Ramachandran plot over the finished frame, redrawing its image first if the
+    /// plot or the panel size changed.
+    unsafe fn plot_pass(&mut self) {
+        let panel = self.plot_panel();
+        let size = panel.size as i32;
+        let Some(plot) = self.plot.as_mut() else {
+            return;
+        };
+
+        if plot.changed || size != self.plot_image_size {
+            let pixels = ramachandran::image(&plot.points, size as u32, plot.highlight);
+            plot.changed = false;
+            self.plot_image_size = size;
+            upload_image(&self.gl, &self.plot_image, size, size, &pixels);
+        }
+
+        self.overlay_pass(
+            self.plot_image.texture,
+            [panel.x as i32, panel.y as i32, size, size],
+        );
+    }
+
+    /// The atom under `cursor`, in window pixels, if one is shown there.
+    pub fn pick(&self, cursor: [f32; 2]) -> Option<usize> {
+        let size = (self.width as f32, self.height as f32);
+        self.scene
+            .structure
+            .as_ref()?
+            .pick(&Camera::of(self), size, cursor)
+    }
+
+    /// Where the sequence bar sits in the window, when shown.
+    pub fn sequence_bar(&self) -> Option<Bar> {
+        self.sequences
+            .as_ref()
+            .map(|panel| panel.place(self.width as f32))
+    }
+
+    /// Draws the sequence bar along the top of the finished frame, redrawing its image first
+    /// if the selection, the scrolling or the bar size changed.
+    unsafe fn sequence_pass(&mut self) {
+        let Some(bar) = self.sequence_bar() else {
+            return;
+        };
+        let size = (bar.width as i32, bar.height as i32);
+        let Some(panel) = self.sequences.as_mut() else {
+            return;
+        };
+
+        if panel.changed || size != self.sequence_image_size {
+            let pixels = panel.image(&bar);
+            panel.changed = false;
+            self.sequence_image_size = size;
+            upload_image(&self.gl, &self.sequence_image, size.0, size.1, &pixels);
+        }
+
+        self.overlay_pass(
+            self.sequence_image.texture,
+            [bar.x as i32, bar.y as i32, size.0, size.1],
+        );
+    }
+
+    /// Draws `texture` into the rectangle at `x`, `y` from the top left of the window.
+    unsafe fn overlay_pass(&self, texture: gl::types::GLuint, [x, y, width, height]: [i32; 4]) {
+        // Panels are placed from the top left, GL viewports from the bottom left
+        self.gl.BindFramebuffer(gl::FRAMEBUFFER, self.output_fbo);
+        self.gl.Disable(gl::DEPTH_TEST);
+        self.gl
+            .Viewport(x, self.height - (y + height), width, height);
+
+        self.gl.UseProgram(self.overlay_program.id);
+        self.bind_texture(self.overlay_program.id, c"image", 0, texture);
+        self.quad.draw(&self.gl, self.overlay_program.id);
+
+        self.gl.Viewport(0, 0, self.width, self.height);
+        self.gl.Enable(gl::DEPTH_TEST);
+    }
+
+    /// Turns and tilts the view by the given angles in radians, like dragging with the mouse.
+    pub fn rotate(&mut self, turn: f32, tilt: f32) {
+        self.x_rotate = Some(self.x_rotate.unwrap_or(0.0) + turn);
+        self.y_rotate = Some(self.y_rotate.unwrap_or(0.0) + tilt);
+    }
+
+    /// Draws one frame offscreen at the current size and writes it to `path` as an RGBA PNG.
+    /// The offscreen target has no multisampling, so FXAA stands in when enabled.
+    pub fn save_png(&mut self, path: &Path) -> std::io::Result<()> {
+        let target = Framebuffer::new(
+            &self.gl,
+            self.width,
+            self.height,
+            TextureFormat::RGBA8,
+            true,
+        );
+
+        let (output_fbo, samples) = (self.output_fbo, self.samples);
+        self.output_fbo = target.fbo;
+        self.samples = 0;
+        self.draw();
+        self.output_fbo = output_fbo;
+        self.samples = samples;
+
+        let (width, height) = (self.width as usize, self.height as usize);
+        let mut pixels = vec![0u8; width * height * 4];
+        unsafe {
+            self.gl.BindFramebuffer(gl::FRAMEBUFFER, target.fbo);
+            self.gl.PixelStorei(gl::PACK_ALIGNMENT, 1);
+            self.gl.ReadPixels(
+                0,
+                0,
+                self.width,
+                self.height,
+                gl::RGBA,
+                gl::UNSIGNED_BYTE,
+                pixels.as_mut_ptr().cast(),
+            );
+            self.gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+        target.delete(&self.gl);
+
+        // GL rows start at the bottom, PNG rows at the top
+        let flipped = pixels
+            .chunks_exact(width * 4)
+            .rev()
+            .flatten()
+            .copied()
+            .collect::<Vec<u8>>();
+
+        let file = std::io::BufWriter::new(std::fs::File::create(path)?);
+        let mut encoder = png::Encoder::new(file, width as u32, height as u32);
+        encoder.set_color(png::ColorType::Rgba);
+        encoder.set_depth(png::BitDepth::Eight);
+        encoder.write_header()?.write_image_data(&flipped)?;
+
+        Ok(())
+    }
+
+    /// Toggles the optional passes: `O` for SSAO (`Shift+O` cycles its quality), `E` for
+    /// outlines, `F` for fog and `S` for shadows, and `R` for the Ramachandran plot.
+    pub fn handle_key(&mut self, key: VirtualKeyCode, modifiers: ModifiersState) {
+        let on_off = |enabled: bool| if enabled { "on" } else { "off" };
+
+        match key {
+            VirtualKeyCode::O if modifiers.shift() => {
+                let ssao = &mut self.settings.ssao;
+                ssao.quality = ssao.quality.next();
+                println!("SSAO quality: {:?}", ssao.quality);
+            }
+            VirtualKeyCode::O => {
+                let ssao = &mut self.settings.ssao;
+                ssao.enabled = !ssao.enabled;
+                println!("SSAO {}", on_off(ssao.enabled));
+            }
+            VirtualKeyCode::E => {
+                let outline = &mut self.settings.outline;
+                outline.enabled = !outline.enabled;
+                println!("Outlines {}", on_off(outline.enabled));
+            }
+            VirtualKeyCode::F => {
+                let fog = &mut self.settings.fog;
+                fog.enabled = !fog.enabled;
+                println!("Fog {}", on_off(fog.enabled));
+            }
+            VirtualKeyCode::S => {
+                let light = &mut self.settings.light;
+                light.shadows = !light.shadows;
+                println!("Shadows {}", on_off(light.shadows));
+            }
+            VirtualKeyCode::R => {
+                self.plot = match self.plot {
+                    Some(_) => None,
+                    None => Some(Plot::new(self.scene.structure.as_ref())),
+                };
+                println!("Ramachandran plot {}", on_off(self.plot.is_some()));
+            }
+            _ => (),
+        }
+    }
+
+    /// Renders the scene depth from the key light into the shadow map.
+    unsafe fn shadow_pass(&mut self) {
+        let resolution = self.settings.light.shadow_resolution;
+        if resolution != self.shadow_map.resolution {
+            self.shadow_map.resize(&self.gl, resolution);
+        }
+
+        self.shadow_map.bind(&self.gl);
+        self.gl.Clear(gl::DEPTH_BUFFER_BIT);
+
+        self.gl.UseProgram(self.shadow_program.id);
+        let projection = light_matrix(self.settings.light.direction, SHADOW_RADIUS);
+        self.render_scene(self.shadow_program.id, &projection, RenderLayer::Opaque);
+
+        self.gl.Viewport(0, 0, self.width, self.height);
+    }
+
+    /// Renders view space normals and depth offscreen, the input of the SSAO and outline passes.
+    unsafe fn normal_depth_pass(&mut self) {
+        self.gbuffer.bind(&self.gl);
+        self.gl.ClearColor(0.5, 0.5, 0.5, 2.0);
+        self.gl.Clear(gl::COLOR_BUFFER_BIT | gl::DEPTH_BUFFER_BIT);
+        self.gl.UseProgram(self.normal_program.id);
+        self.render_scene(self.normal_program.id, &IDENTITY, RenderLayer::Opaque);
+    }
+
+    /// Accumulates the transparent objects, occluded by the opaque ones but in no particular
+    /// order, then composites their weighted average over the lit scene.
+    unsafe fn transparency_pass(&mut self) {
+        self.oit.begin(&self.gl);
+
+        // Opaque depth only, so hidden transparent fragments are rejected
+        self.gl
+            .ColorMask(gl::FALSE, gl::FALSE, gl::FALSE, gl::FALSE);
+        self.gl.UseProgram(self.shadow_program.id);
+        self.render_scene(self.shadow_program.id, &IDENTITY, RenderLayer::Opaque);
+        self.gl.ColorMask(gl::TRUE, gl::TRUE, gl::TRUE, gl::TRUE);
+
+        self.gl.DepthMask(gl::FALSE);
+        self.gl.Enable(gl::BLEND);
+        self.gl
+            .BlendFuncSeparate(gl::ONE, gl::ONE, gl::ZERO, gl::ONE_MINUS_SRC_ALPHA);
+
+        self.gl.UseProgram(self.lighting_program.id);
+        self.set_uniform_1i(self.lighting_program.id, c"oit_pass", 1);
+        self.render_scene(
+            self.lighting_program.id,
+            &IDENTITY,
+            RenderLayer::Transparent,
+        );
+        self.set_uniform_1i(self.lighting_program.id, c"oit_pass", 0);
+
+        self.gl.DepthMask(gl::TRUE);
+
+        self.bind_output();
+        self.gl.Disable(gl::DEPTH_TEST);
+        self.gl.BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+
+        self.gl.UseProgram(self.oit_composite_program.id);
+        self.bind_texture(
+            self.oit_composite_program.id,
+            c"accumulation",
+            0,
+            self.oit.accumulation.texture,
+        );
+        self.bind_texture(
+            self.oit_composite_program.id,
+            c"weights",
+            1,
+            self.oit.weights.texture,
+        );
+        self.quad.draw(&self.gl, self.oit_composite_program.id);
+
+        self.gl.Disable(gl::BLEND);
+        self.gl.Enable(gl::DEPTH_TEST);
+    }
+
+    /// Estimates how occluded each pixel is from the normal/depth prepass and blurs the result
+    /// into `ssao.blurred` for the lighting pass.
+    unsafe fn ambient_occlusion_pass(&mut self) {
+        let quality = self.settings.ssao.quality;
+
+        self.gl.Disable(gl::DEPTH_TEST);
+
+        self.ssao.occlusion.bind(&self.gl);
+        self.gl.UseProgram(self.ssao_program.id);
+        self.bind_texture(self.ssao_program.id, c"gbuffer", 0, self.gbuffer.texture);
+        self.gl.Uniform3fv(
+            self.gl
+                .GetUniformLocation(self.ssao_program.id, c"samples".as_ptr()),
+            crate::ssao::MAX_KERNEL_SIZE as i32,
+            self.ssao.kernel.as_ptr(),
+        );
+        self.set_uniform_1i(
+            self.ssao_program.id,
+            c"kernel_size",
+            quality.kernel_size() as i32,
+        );
+        self.set_uniform_1f(self.ssao_program.id, c"radius", self.settings.ssao.radius);
+        self.quad.draw(&self.gl, self.ssao_program.id);
+
+        self.ssao.blurred.bind(&self.gl);
+        self.gl.UseProgram(self.blur_program.id);
+        self.bind_texture(
+            self.blur_program.id,
+            c"occlusion",
+            0,
+            self.ssao.occlusion.texture,
+        );
+        self.set_uniform_2f(
+            self.blur_program.id,
+            c"texel_size",
+            1.0 / self.width as f32,
+            1.0 / self.height as f32,
+        );
+        self.set_uniform_1i(self.blur_program.id, c"blur_radius", quality.blur_radius());
+        self.quad.draw(&self.gl, self.blur_program.id);
+
+        self.gl.Enable(gl::DEPTH_TEST);
+    }
+
+    /// Blends silhouette and crease lines, found on the normal/depth prepass, over the lit scene.
+    unsafe fn outline_pass(&mut self) {
+        let outline = self.settings.outline;
+
+        self.gl.Disable(gl::DEPTH_TEST);
+        self.gl.Enable(gl::BLEND);
+        self.gl.BlendFunc(gl::SRC_ALPHA, gl::ONE_MINUS_SRC_ALPHA);
+
+        self.gl.UseProgram(self.outline_program.id);
+        self.bind_texture(self.outline_program.id, c"gbuffer", 0, self.gbuffer.texture);
+        self.set_uniform_2f(
+            self.outline_program.id,
+            c"texel_size",
+            1.0 / self.width as f32,
+            1.0 / self.height as f32,
+        );
+        self.set_uniform_1f(self.outline_program.id, c"thickness", outline.thickness);
+        self.set_uniform_3f(self.outline_program.id, c"outline_color", outline.color);
+        self.set_uniform_1f(
+            self.outline_program.id,
+            c"depth_threshold",
+            outline.depth_threshold,
+        );
+        self.set_uniform_1f(
+            self.outline_program.id,
+            c"normal_threshold",
+            outline.normal_threshold,
+        );
+        self.quad.draw(&self.gl, self.outline_program.id);
+
+        self.gl.Disable(gl::BLEND);
+        self.gl.Enable(gl::DEPTH_TEST);
+    }
+
+    /// Draws the scene objects of `layer` with `program`, which must already be in use,
+    /// transforming the rotated scene by `projection`.
+    unsafe fn render_scene(
+        &mut self,
+        program: gl::types::GLuint,
+        projection: &[f32; 16],
+        layer: RenderLayer,
+    ) {
+        self.program = Some(program);
+
+        let light_matrix = light_matrix(self.settings.light.direction, SHADOW_RADIUS);
+        self.set_uniform_matrix(program, c"projection", projection);
+        self.set_uniform_matrix(program, c"light_matrix", &light_matrix);
+
+        self.gl.BindVertexArray(self.vao);
+        self.gl.BindBuffer(gl::ARRAY_BUFFER, self.vbo);
+        self.gl.BindBuffer(gl::ELEMENT_ARRAY_BUFFER, self.ibo);
+
+        self.scene.render(self, layer);
+    }
+
+    unsafe fn bind_texture(
+        &self,
+        program: gl::types::GLuint,
+        name: &CStr,
+        unit: u32,
+        texture: gl::types::GLuint,
+    ) {
+        self.gl.ActiveTexture(gl::TEXTURE0 + unit);
+        self.gl.BindTexture(gl::TEXTURE_2D, texture);
+        self.set_uniform_1i(program, name, unit as i32);
+    }
+
+    unsafe fn set_uniform_1i(&self, program: gl::types::GLuint, name: &CStr, value: i32) {
+        self.gl
+            .Uniform1i(self.gl.GetUniformLocation(program, name.as_ptr()), value);
+    }
+
+    unsafe fn set_uniform_1f(&self, program: gl::types::GLuint, name: &CStr, value: f32) {
+        self.gl
+            .Uniform1f(self.gl.GetUniformLocation(program, name.as_ptr()), value);
+    }
+
+    unsafe fn set_uniform_3f(&self, program: gl::types::GLuint, name: &CStr, value: [f32; 3]) {
+        self.gl.Uniform3f(
+            self.gl.GetUniformLocation(program, name.as_ptr()),
+            value[0],
+            value[1],
+            value[2],
+        );
+    }
+
+    unsafe fn set_uniform_matrix(
+        &self,
+        program: gl::types::GLuint,
+        name: &CStr,
+        value: &[f32; 16],
+    ) {
+        self.gl.UniformMatrix4fv(
+            self.gl.GetUniformLocation(program, name.as_ptr()),
+            1,
+            gl::FALSE,
+            value.as_ptr(),
+        );
+    }
+
+    unsafe fn set_uniform_2f(&self, program: gl::types::GLuint, name: &CStr, x: f32, y: f32) {
+        self.gl
+            .Uniform2f(self.gl.GetUniformLocation(program, name.as_ptr()), x, y);
+    }
+
+    pub fn resize(&mut self, width: i32, height: i32) {
+        self.width = width;
+        self.height = height;
+
         unsafe {
             self.gl.Viewport(0, 0, width, height);
+            self.gbuffer.resize(&self.gl, width, height);
+            self.color_target.resize(&self.gl, width, height);
+            self.ssao.resize(&self.gl, width, height);
+            self.oit.resize(&self.gl, width, height);
         }
     }
 
@@ -343,7 +1335,7 @@ impl<'a> Renderer<'a> {
     }
 }
 
-impl Deref for Renderer<'_> {
+impl Deref for Renderer {
     type Target = gl::Gl;
 
     fn deref(&self) -> &Self::Target {
@@ -351,12 +1343,21 @@ impl Deref for Renderer<'_> {
     }
 }
 
-impl Drop for Renderer<'_> {
+impl Drop for Renderer {
     fn drop(&mut self) {
         unsafe {
-            if let Some(program) = self.program {
-                self.gl.DeleteProgram(program);
+            let gl = self.gl.clone();
+            for program in self.programs_mut() {
+                program.delete(&gl);
             }
+            self.gbuffer.delete(&self.gl);
+            self.color_target.delete(&self.gl);
+            self.shadow_map.delete(&self.gl);
+            self.oit.delete(&self.gl);
+            self.ssao.delete(&self.gl);
+            self.quad.delete(&self.gl);
+            self.plot_image.delete(&self.gl);
+            self.sequence_image.delete(&self.gl);
             self.gl.DeleteBuffers(1, &self.vbo);
             self.gl.DeleteBuffers(1, &self.ibo);
             self.gl.DeleteVertexArrays(1, &self.vao);
@@ -364,24 +1365,29 @@ impl Drop for Renderer<'_> {
     }
 }
 
+/// Resizes `target` to `width` by `height` and fills its texture with RGBA `pixels`, rows from
+/// the top.
+unsafe fn upload_image(gl: &gl::Gl, target: &Framebuffer, width: i32, height: i32, pixels: &[u8]) {
+    target.resize(gl, width, height);
+    gl.BindTexture(gl::TEXTURE_2D, target.texture);
+    gl.PixelStorei(gl::UNPACK_ALIGNMENT, 1);
+    gl.TexSubImage2D(
+        gl::TEXTURE_2D,
+        0,
+        0,
+        0,
+        width,
+        height,
+        gl::RGBA,
+        gl::UNSIGNED_BYTE,
+        pixels.as_ptr().cast(),
+    );
+    gl.BindTexture(gl::TEXTURE_2D, 0);
+}
+
 fn get_gl_string(gl: &gl::Gl, variant: gl::types::GLenum) -> Option<&'static CStr> {
     unsafe {
         let s = gl.GetString(variant);
         (!s.is_null()).then(|| CStr::from_ptr(s.cast()))
     }
 }
-pub unsafe fn create_shader(
-    gl: &gl::Gl,
-    shader: gl::types::GLenum,
-    source: &[u8],
-) -> gl::types::GLuint {
-    let shader = gl.CreateShader(shader);
-    gl.ShaderSource(
-        shader,
-        1,
-        [source.as_ptr().cast()].as_ptr(),
-        std::ptr::null(),
-    );
-    gl.CompileShader(shader);
-    shader
-}
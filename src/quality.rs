@@ -0,0 +1,103 @@
+use std::str::FromStr;
+
+use crate::opengl::RenderSettings;
+use crate::ssao::SsaoQuality;
+
+/// Bundles the settings that trade speed for image quality, so a view renders the same on
+/// every machine.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum QualityPreset {
+    Draft,
+    #[default]
+    Normal,
+    Publication,
+}
+
+impl QualityPreset {
+    /// Requested MSAA samples per pixel, 0 disables multisampling.
+    pub fn samples(self) -> u8 {
+        match self {
+            QualityPreset::Draft => 0,
+            QualityPreset::Normal => 4,
+            QualityPreset::Publication => 8,
+        }
+    }
+
+    /// Sector and stack count of every atom sphere.
+    pub fn sphere_detail(self) -> (u32, u32) {
+        match self {
+            QualityPreset::Draft => (8, 6),
+            QualityPreset::Normal => (16, 12),
+            QualityPreset::Publication => (32, 24),
+        }
+    }
+
+    /// `None` leaves SSAO off.
+    pub fn ssao(self) -> Option<SsaoQuality> {
+        match self {
+            QualityPreset::Draft => None,
+            QualityPreset::Normal => Some(SsaoQuality::Medium),
+            QualityPreset::Publication => Some(SsaoQuality::High),
+        }
+    }
+
+    pub fn shadow_resolution(self) -> i32 {
+        match self {
+            QualityPreset::Draft => 1024,
+            QualityPreset::Normal => 2048,
+            QualityPreset::Publication => 4096,
+        }
+    }
+
+    /// Overwrites the quality related fields of `settings`, leaving the rest untouched.
+    pub fn apply(self, settings: &mut RenderSettings) {
+        settings.samples = self.samples();
+        settings.fxaa = self.samples() > 0;
+        settings.sphere_detail = self.sphere_detail();
+        settings.light.shadow_resolution = self.shadow_resolution();
+
+        match self.ssao() {
+            Some(quality) => {
+                settings.ssao.enabled = true;
+                settings.ssao.quality = quality;
+            }
+            None => settings.ssao.enabled = false,
+        }
+    }
+}
+
+impl FromStr for QualityPreset {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "draft" => Ok(QualityPreset::Draft),
+            "normal" => Ok(QualityPreset::Normal),
+            "publication" => Ok(QualityPreset::Publication),
+            _ => Err(format!(
+                "Unknown quality preset '{}', expected draft, normal or publication",
+                name
+            )),
+        }
+    }
+}
+
+impl From<QualityPreset> for RenderSettings {
+    fn from(preset: QualityPreset) -> Self {
+        let mut settings = RenderSettings::default();
+        preset.apply(&mut settings);
+        settings
+    }
+}
+
+#[test]
+fn presets_scale_together() {
+    let draft = RenderSettings::from(QualityPreset::Draft);
+    let publication = RenderSettings::from(QualityPreset::Publication);
+
+    assert!(!draft.ssao.enabled && !draft.fxaa);
+    assert!(publication.samples > draft.samples);
+    assert!(publication.sphere_detail.0 > draft.sphere_detail.0);
+    assert!(publication.light.shadow_resolution > draft.light.shadow_resolution);
+    assert_eq!("Publication".parse(), Ok(QualityPreset::Publication));
+}
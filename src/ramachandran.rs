@@ -0,0 +1,400 @@
+use std::fs::File;
+use std::io::BufWriter;
+use std::path::Path;
+
+use crate::json::Json;
+use crate::math::{cross, distance, dot};
+use crate::scene::Structure;
+
+/// Longest C–N distance still counted as a peptide bond.
+const MAX_PEPTIDE_BOND: f32 = 2.0;
+/// Furthest a click may land from a point and still pick it, in pixels.
+const PICK_DISTANCE: f32 = 6.0;
+
+/// Simplified favoured and allowed regions of the general case, as (phi, psi) boxes in degrees
+/// approximating the Lovell et al. contours.
+const FAVOURED: &[[f32; 4]] = &[
+    // β sheet, wrapping around psi = ±180
+    [-170.0, -50.0, 100.0, 180.0],
+    [-170.0, -50.0, -180.0, -170.0],
+    // Right-handed helix
+    [-110.0, -40.0, -75.0, -5.0],
+    // Left-handed helix
+    [45.0, 75.0, 20.0, 70.0],
+];
+const ALLOWED: &[[f32; 4]] = &[
+    [-180.0, -35.0, 60.0, 180.0],
+    [-180.0, -35.0, -180.0, -150.0],
+    [-180.0, -35.0, -120.0, 60.0],
+    [30.0, 100.0, -20.0, 100.0],
+];
+
+const BACKGROUND: [u8; 3] = [255, 255, 255];
+const ALLOWED_COLOR: [u8; 3] = [205, 225, 255];
+const FAVOURED_COLOR: [u8; 3] = [140, 180, 240];
+const AXIS_COLOR: [u8; 3] = [150, 150, 150];
+const POINT_COLOR: [u8; 3] = [20, 20, 20];
+const OUTLIER_COLOR: [u8; 3] = [220, 30, 30];
+const HIGHLIGHT_COLOR: [u8; 3] = [255, 140, 0];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Region {
+    Favoured,
+    Allowed,
+    Outlier,
+}
+
+impl Region {
+    pub fn name(self) -> &'static str {
+        match self {
+            Region::Favoured => "favoured",
+            Region::Allowed => "allowed",
+            Region::Outlier => "outlier",
+        }
+    }
+
+    /// Where a residue's backbone angles fall. Glycine, having no side chain, may also take the
+    /// mirror image of every region.
+    pub fn of(residue_name: &str, phi: f32, psi: f32) -> Region {
+        let inside = |boxes: &[[f32; 4]], phi: f32, psi: f32| {
+            boxes.iter().any(|&[phi_min, phi_max, psi_min, psi_max]| {
+                (phi_min..=phi_max).contains(&phi) && (psi_min..=psi_max).contains(&psi)
+            })
+        };
+        let glycine = residue_name == "GLY";
+        let within =
+            |boxes: &[[f32; 4]]| inside(boxes, phi, psi) || (glycine && inside(boxes, -phi, -psi));
+
+        if within(FAVOURED) {
+            Region::Favoured
+        } else if within(ALLOWED) {
+            Region::Allowed
+        } else {
+            Region::Outlier
+        }
+    }
+}
+
+/// Backbone dihedrals of one residue.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Point {
+    /// Index into `Structure::atoms` of the residue's alpha carbon.
+    pub atom: usize,
+    /// In degrees, -180 to 180.
+    pub phi: f32,
+    pub psi: f32,
+    pub region: Region,
+}
+
+/// Phi and psi of every amino acid bonded to a residue on both sides.
+pub fn points(structure: &Structure) -> Vec<Point> {
+    let atoms = &structure.atoms;
+    // N, CA and C of each residue in structure order
+    let mut backbone = Vec::<(usize, [Option<usize>; 3])>::new();
+    for (index, atom) in atoms.iter().enumerate() {
+        if backbone
+            .last()
+            .is_none_or(|(residue, _)| *residue != atom.residue)
+        {
+            backbone.push((atom.residue, [None; 3]));
+        }
+        let slot = match atom.name.as_str() {
+            "N" => 0,
+            "CA" => 1,
+            "C" => 2,
+            _ => continue,
+        };
+        backbone.last_mut().unwrap().1[slot] = Some(index);
+    }
+
+    let bonded = |c: usize, n: usize| {
+        atoms[c].chain == atoms[n].chain
+            && distance(atoms[c].position, atoms[n].position) <= MAX_PEPTIDE_BOND
+    };
+    let position = |index: usize| atoms[index].position;
+
+    backbone
+        .windows(3)
+        .filter_map(|window| {
+            let [(_, previous), (_, current), (_, next)] = window else {
+                return None;
+            };
+            let [n, ca, c] = [current[0]?, current[1]?, current[2]?];
+            let (previous_c, next_n) = (previous[2]?, next[0]?);
+            if !bonded(previous_c, n) || !bonded(c, next_n) {
+                return None;
+            }
+
+            let phi = dihedral([previous_c, n, ca, c].map(position));
+            let psi = dihedral([n, ca, c, next_n].map(position));
+            Some(Point {
+                atom: ca,
+                phi,
+                psi,
+                region: Region::of(&atoms[ca].residue_name, phi, psi),
+            })
+        })
+        .collect()
+}
+
+/// Torsion angle about the b–c bond, in degrees.
+pub fn dihedral([a, b, c, d]: [[f32; 3]; 4]) -> f32 {
+    let subtract = |u: [f32; 3], v: [f32; 3]| [u[0] - v[0], u[1] - v[1], u[2] - v[2]];
+    let (ab, bc, cd) = (subtract(b, a), subtract(c, b), subtract(d, c));
+    let (n1, n2) = (cross(ab, bc), cross(bc, cd));
+    let length = dot(bc, bc).sqrt();
+
+    (length * dot(ab, n2)).atan2(dot(n1, n2)).to_degrees()
+}
+
+/// Counts of favoured, allowed and outlier points, and the outliers themselves.
+pub fn summary(structure: &Structure, points: &[Point]) -> String {
+    let count = |region| points.iter().filter(|point| point.region == region).count();
+    let percent = |region| 100.0 * count(region) as f32 / points.len().max(1) as f32;
+    let mut summary = format!(
+        "{} residues: {:.1}% favoured, {:.1}% allowed, {} outliers",
+        points.len(),
+        percent(Region::Favoured),
+        percent(Region::Allowed),
+        count(Region::Outlier)
+    );
+    for point in points
+        .iter()
+        .filter(|point| point.region == Region::Outlier)
+    {
+        summary.push_str(&format!(
+            "\n  {} phi {:.0} psi {:.0}",
+            structure.atoms[point.atom].describe(),
+            point.phi,
+            point.psi
+        ));
+    }
+
+    summary
+}
+
+pub fn to_json(structure: &Structure, points: &[Point]) -> Json {
+    Json::Array(
+        points
+            .iter()
+            .map(|point| {
+                let atom = &structure.atoms[point.atom];
+                Json::object([
+                    ("chain", Json::from(atom.chain_id.as_str())),
+                    ("residue", Json::from(atom.residue_name.as_str())),
+                    ("number", Json::Number(atom.residue_number as f64)),
+                    ("phi", Json::from(point.phi)),
+                    ("psi", Json::from(point.psi)),
+                    ("region", Json::from(point.region.name())),
+                ])
+            })
+            .collect(),
+    )
+}
+
+/// RGBA pixels of a `size` square plot, rows from the top: phi runs left to right and psi
+/// bottom to top, both from -180 to 180 degrees. The `highlight`ed point is ringed.
+pub fn image(points: &[Point], size: u32, highlight: Option<usize>) -> Vec<u8> {
+    let size = size.max(1) as usize;
+    let mut pixels = vec![255u8; size * size * 4];
+    let mut put = |x: isize, y: isize, color: [u8; 3]| {
+        if (0..size as isize).contains(&x) && (0..size as isize).contains(&y) {
+            let offset = (y as usize * size + x as usize) * 4;
+            pixels[offset..offset + 3].copy_from_slice(&color);
+        }
+    };
+
+    for y in 0..size {
+        for x in 0..size {
+            let [phi, psi] = angles_at([x as f32 + 0.5, y as f32 + 0.5], size as f32);
+            let color = match Region::of("", phi, psi) {
+                Region::Favoured => FAVOURED_COLOR,
+                Region::Allowed => ALLOWED_COLOR,
+                Region::Outlier => BACKGROUND,
+            };
+            put(x as isize, y as isize, color);
+        }
+    }
+
+    // Zero lines and the frame
+    let middle = size as isize / 2;
+    let last = size as isize - 1;
+    for step in 0..size as isize {
+        put(middle, step, AXIS_COLOR);
+        put(step, middle, AXIS_COLOR);
+        for edge in [0, last] {
+            put(edge, step, AXIS_COLOR);
+            put(step, edge, AXIS_COLOR);
+        }
+    }
+
+    let radius = (size as f32 / 150.0).max(1.5);
+    let mut disc = |center: [f32; 2], radius: f32, ring: Option<f32>, color: [u8; 3]| {
+        let reach = radius.ceil() as isize + 1;
+        let [cx, cy] = center.map(|value| value as isize);
+        for y in cy - reach..=cy + reach {
+            for x in cx - reach..=cx + reach {
+                let length = ((x as f32 + 0.5 - center[0]).powi(2)
+                    + (y as f32 + 0.5 - center[1]).powi(2))
+                .sqrt();
+                if length <= radius && ring.is_none_or(|inner| length >= inner) {
+                    put(x, y, color);
+                }
+            }
+        }
+    };
+    for point in points {
+        let color = match point.region {
+            Region::Outlier => OUTLIER_COLOR,
+            _ => POINT_COLOR,
+        };
+        disc(position_of(point, size as f32), radius, None, color);
+    }
+    if let Some(point) = highlight.and_then(|index| points.get(index)) {
+        let center = position_of(point, size as f32);
+        disc(center, radius * 3.0, Some(radius * 2.0), HIGHLIGHT_COLOR);
+    }
+
+    pixels
+}
+
+/// Pixel position of a point on a `size` square plot.
+fn position_of(point: &Point, size: f32) -> [f32; 2] {
+    [
+        (point.phi + 180.0) / 360.0 * size,
+        (180.0 - point.psi) / 360.0 * size,
+    ]
+}
+
+/// Phi and psi at a pixel position of a `size` square plot.
+fn angles_at([x, y]: [f32; 2], size: f32) -> [f32; 2] {
+    [x / size * 360.0 - 180.0, 180.0 - y / size * 360.0]
+}
+
+/// Writes the plot as a PNG image.
+pub fn save(points: &[Point], size: u32, path: &Path) -> Result<(), String> {
+    let failed =
+        |error: &dyn std::fmt::Display| format!("Failed to save {}: {}", path.display(), error);
+    let file = BufWriter::new(File::create(path).map_err(|error| failed(&error))?);
+    let mut encoder = png::Encoder::new(file, size, size);
+    encoder.set_color(png::ColorType::Rgba);
+    encoder.set_depth(png::BitDepth::Eight);
+    encoder
+        .write_header()
+        .and_then(|mut writer| writer.write_image_data(&image(points, size, None)))
+        .map_err(|error| failed(&error))
+}
+
+/// Where the plot sits in the window, in pixels from the top left corner.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Panel {
+    pub x: f32,
+    pub y: f32,
+    pub size: f32,
+}
+
+impl Panel {
+    /// The bottom right corner of a `width` by `height` window, a third of its shorter side.
+    pub fn place(width: f32, height: f32) -> Panel {
+        let margin = 10.0;
+        let size = (width.min(height) / 3.0).floor().max(1.0);
+        Panel {
+            x: width - size - margin,
+            y: height - size - margin,
+            size,
+        }
+    }
+
+    pub fn contains(&self, [x, y]: [f32; 2]) -> bool {
+        (self.x..self.x + self.size).contains(&x) && (self.y..self.y + self.size).contains(&y)
+    }
+
+    /// The point under the cursor, as an index into `points`.
+    pub fn pick(&self, points: &[Point], cursor: [f32; 2]) -> Option<usize> {
+        if !self.contains(cursor) {
+            return None;
+        }
+        let local = [cursor[0] - self.x, cursor[1] - self.y];
+
+        points
+            .iter()
+            .map(|point| {
+                let [x, y] = position_of(point, self.size);
+                ((x - local[0]).powi(2) + (y - local[1]).powi(2)).sqrt()
+            })
+            .enumerate()
+            .filter(|(_, length)| *length <= PICK_DISTANCE)
+            .min_by(|a, b| a.1.total_cmp(&b.1))
+            .map(|(index, _)| index)
+    }
+}
+
+/// The plot shown over the 3D view.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Plot {
+    pub points: Vec<Point>,
+    pub highlight: Option<usize>,
+    /// Set when the image needs drawing again.
+    pub changed: bool,
+}
+
+impl Plot {
+    pub fn new(structure: Option<&Structure>) -> Plot {
+        Plot {
+            points: structure.map(points).unwrap_or_default(),
+            highlight: None,
+            changed: true,
+        }
+    }
+}
+
+#[test]
+fn classifies_backbone_angles() {
+    use crate::scene::Scene;
+    use crate::style::Style;
+
+    let square = [[1.0, 0.0, 0.0], [0.0, 0.0, 0.0], [0.0, 1.0, 0.0]];
+    assert!((dihedral([square[0], square[1], square[2], [1.0, 1.0, 0.0]])).abs() < 1e-4);
+    assert!((dihedral([square[0], square[1], square[2], [0.0, 1.0, 1.0]]) - -90.0).abs() < 1e-3);
+    assert_eq!(Region::of("ALA", -63.0, -43.0), Region::Favoured);
+    assert_eq!(Region::of("ALA", 60.0, -120.0), Region::Outlier);
+    assert_eq!(Region::of("GLY", 63.0, 43.0), Region::Favoured);
+
+    let scene = Scene::open("1d66.pdb", &Style::default()).unwrap();
+    let structure = scene.structure.as_ref().unwrap();
+    let points = points(structure);
+    // A well refined crystal structure is mostly favoured
+    let favoured = points
+        .iter()
+        .filter(|point| point.region == Region::Favoured)
+        .count();
+    assert!(favoured * 10 > points.len() * 7);
+    assert!(points
+        .iter()
+        .all(|point| structure.atoms[point.atom].name == "CA"));
+
+    let panel = Panel::place(900.0, 600.0);
+    assert_eq!(panel.size, 200.0);
+    let [x, y] = position_of(&points[5], panel.size);
+    assert_eq!(
+        panel.pick(&points, [panel.x + x, panel.y + y]),
+        points
+            .iter()
+            .position(|point| position_of(point, panel.size) == [x, y])
+    );
+    assert_eq!(panel.pick(&points, [0.0, 0.0]), None);
+
+    let pixels = image(&points, 180, Some(5));
+    assert_eq!(pixels.len(), 180 * 180 * 4);
+    assert!(pixels.chunks(4).any(|pixel| pixel[..3] == HIGHLIGHT_COLOR));
+}
+
+#[test]
+fn parses_ramachandran_commands() {
+    use crate::script::ScriptCommand;
+
+    assert_eq!(
+        ScriptCommand::parse("ramachandran off"),
+        Ok(Some(ScriptCommand::HideRamachandran))
+    );
+}
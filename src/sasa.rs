@@ -0,0 +1,462 @@
+use std::collections::{BTreeSet, HashMap};
+use std::f32::consts::PI;
+use std::fmt::Write as _;
+use std::str::FromStr;
+
+use pdbtbx::Element;
+
+use crate::json::Json;
+use crate::math::distance;
+use crate::scene::Structure;
+use crate::spatial::Grid;
+
+/// Radius of a water molecule rolled over the surface, in Å.
+pub const PROBE_RADIUS: f32 = 1.4;
+/// Test points per atom, enough for areas within a percent or so.
+pub const SPHERE_POINTS: usize = 100;
+/// Slices per atom for the Lee–Richards method.
+pub const SLICES: usize = 20;
+
+/// Van der Waals radius of `element` in Å, falling back to twice the covalent radius.
+pub fn radius(element: &Element) -> f32 {
+    let radius = element.atomic_radius();
+    radius.van_der_waals.unwrap_or(radius.covalent_single * 2.0) as f32
+}
+
+/// Largest accessible area of each amino acid in a Gly-X-Gly tripeptide, in Å² (Tien et al.
+/// 2013, theoretical).
+const MAX_AREAS: &[(&str, f32)] = &[
+    ("ALA", 129.0),
+    ("ARG", 274.0),
+    ("ASN", 195.0),
+    ("ASP", 193.0),
+    ("CYS", 167.0),
+    ("GLN", 225.0),
+    ("GLU", 223.0),
+    ("GLY", 104.0),
+    ("HIS", 224.0),
+    ("ILE", 197.0),
+    ("LEU", 201.0),
+    ("LYS", 236.0),
+    ("MET", 224.0),
+    ("PHE", 240.0),
+    ("PRO", 159.0),
+    ("SER", 155.0),
+    ("THR", 172.0),
+    ("TRP", 285.0),
+    ("TYR", 263.0),
+    ("VAL", 174.0),
+];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Method {
+    /// Test points spread over each atom's sphere.
+    #[default]
+    ShrakeRupley,
+    /// Exposed arcs of circles slicing each atom's sphere, slower but smoother.
+    LeeRichards,
+}
+
+impl FromStr for Method {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "shrake-rupley" | "sr" => Ok(Method::ShrakeRupley),
+            "lee-richards" | "lr" => Ok(Method::LeeRichards),
+            _ => Err(format!(
+                "Unknown surface method '{}', expected shrake-rupley or lee-richards",
+                name
+            )),
+        }
+    }
+}
+
+/// Solvent accessible area of each sphere in Å².
+pub fn areas(positions: &[[f32; 3]], radii: &[f32], method: Method) -> Vec<f32> {
+    match method {
+        Method::ShrakeRupley => shrake_rupley(positions, radii, SPHERE_POINTS),
+        Method::LeeRichards => lee_richards(positions, radii, SLICES),
+    }
+}
+
+/// Spheres grown by the probe radius and, for each, the other grown spheres it overlaps.
+fn overlaps(positions: &[[f32; 3]], radii: &[f32]) -> (Vec<f32>, Vec<Vec<usize>>) {
+    let grown = radii
+        .iter()
+        .map(|radius| radius + PROBE_RADIUS)
+        .collect::<Vec<_>>();
+    let largest = grown.iter().copied().fold(0.0, f32::max);
+    let grid = Grid::new(positions, 2.0 * largest);
+
+    let neighbours = (0..positions.len())
+        .map(|index| {
+            let mut neighbours = Vec::new();
+            grid.for_each_within(positions[index], grown[index] + largest, |other, length| {
+                if other != index && length < grown[index] + grown[other] {
+                    neighbours.push(other);
+                }
+            });
+            neighbours
+        })
+        .collect();
+
+    (grown, neighbours)
+}
+
+/// Solvent accessible area of each sphere in Å², by the Shrake–Rupley method: points spread
+/// evenly over each sphere grown by the probe radius count as exposed unless inside another one.
+pub fn shrake_rupley(positions: &[[f32; 3]], radii: &[f32], points: usize) -> Vec<f32> {
+    let sphere = sphere_points(points);
+    let (grown, neighbours) = overlaps(positions, radii);
+
+    (0..positions.len())
+        .map(|index| {
+            let (center, radius) = (positions[index], grown[index]);
+            let exposed = sphere
+                .iter()
+                .filter(|point| {
+                    let point = [0, 1, 2].map(|axis| center[axis] + point[axis] * radius);
+                    neighbours[index]
+                        .iter()
+                        .all(|&other| distance(point, positions[other]) >= grown[other])
+                })
+                .count();
+
+            4.0 * PI * radius * radius * exposed as f32 / sphere.len() as f32
+        })
+        .collect()
+}
+
+/// Solvent accessible area of each sphere in Å², by the Lee–Richards method: each grown sphere
+/// is cut into `slices` slabs along z, and the exposed arcs of the circles through their middles
+/// are summed. A slab of height h with exposed angle θ contributes θ·R·h.
+pub fn lee_richards(positions: &[[f32; 3]], radii: &[f32], slices: usize) -> Vec<f32> {
+    let (grown, neighbours) = overlaps(positions, radii);
+
+    (0..positions.len())
+        .map(|index| {
+            let (center, radius) = (positions[index], grown[index]);
+            let height = 2.0 * radius / slices as f32;
+
+            (0..slices)
+                .map(|slice| {
+                    let z = center[2] - radius + (slice as f32 + 0.5) * height;
+                    let circle = (radius * radius - (z - center[2]).powi(2)).sqrt();
+
+                    let mut covered = Vec::new();
+                    for &other in &neighbours[index] {
+                        let position = positions[other];
+                        let other_circle = grown[other].powi(2) - (z - position[2]).powi(2);
+                        if other_circle <= 0.0 {
+                            continue;
+                        }
+                        let other_circle = other_circle.sqrt();
+                        let (dx, dy) = (position[0] - center[0], position[1] - center[1]);
+                        let apart = (dx * dx + dy * dy).sqrt();
+
+                        if apart >= circle + other_circle || apart + other_circle <= circle {
+                            continue;
+                        }
+                        if apart + circle <= other_circle {
+                            return 0.0;
+                        }
+                        let half = ((circle * circle + apart * apart
+                            - other_circle * other_circle)
+                            / (2.0 * circle * apart))
+                            .clamp(-1.0, 1.0)
+                            .acos();
+                        let middle = dy.atan2(dx);
+                        covered.push((middle - half, middle + half));
+                    }
+
+                    exposed_angle(covered) * radius * height
+                })
+                .sum()
+        })
+        .collect()
+}
+
+/// Angle of a full turn left uncovered by the arcs, given as (start, end) in radians.
+fn exposed_angle(arcs: Vec<(f32, f32)>) -> f32 {
+    let turn = 2.0 * PI;
+    let mut pieces = Vec::new();
+    for (start, end) in arcs {
+        let length = end - start;
+        let start = start.rem_euclid(turn);
+        let end = start + length;
+        if end > turn {
+            pieces.push((start, turn));
+            pieces.push((0.0, end - turn));
+        } else {
+            pieces.push((start, end));
+        }
+    }
+    pieces.sort_by(|a, b| a.0.total_cmp(&b.0));
+
+    let mut covered = 0.0;
+    let mut reach = 0.0f32;
+    for (start, end) in pieces {
+        if end > reach {
+            covered += end - start.max(reach);
+            reach = end;
+        }
+    }
+
+    turn - covered
+}
+
+/// Atom areas in Å², aligned with `structure.atoms`. Water is left out and gets zero.
+pub fn atom_areas(structure: &Structure, method: Method) -> Vec<f32> {
+    let included = (0..structure.atoms.len())
+        .filter(|&index| !structure.atoms[index].is_water())
+        .collect::<Vec<_>>();
+    let positions = included
+        .iter()
+        .map(|&index| structure.atoms[index].position)
+        .collect::<Vec<_>>();
+    let radii = included
+        .iter()
+        .map(|&index| radius(&structure.atoms[index].element))
+        .collect::<Vec<_>>();
+
+    let mut all = vec![0.0; structure.atoms.len()];
+    for (index, area) in included.into_iter().zip(areas(&positions, &radii, method)) {
+        all[index] = area;
+    }
+    all
+}
+
+/// Accessible area of one residue.
+#[derive(Debug, Clone, PartialEq)]
+pub struct ResidueArea {
+    /// Index into `Structure::atoms` of the residue's first atom.
+    pub atom: usize,
+    pub area: f32,
+    /// Fraction of the largest area the amino acid can expose, `None` for other residues.
+    pub relative: Option<f32>,
+}
+
+/// Residue totals of `areas`, in structure order, leaving out water.
+pub fn residue_areas(structure: &Structure, areas: &[f32]) -> Vec<ResidueArea> {
+    let mut residues = Vec::<ResidueArea>::new();
+    for (index, atom) in structure.atoms.iter().enumerate() {
+        if atom.is_water() {
+            continue;
+        }
+        match residues.last_mut() {
+            Some(residue) if structure.atoms[residue.atom].residue == atom.residue => {
+                residue.area += areas[index]
+            }
+            _ => residues.push(ResidueArea {
+                atom: index,
+                area: areas[index],
+                relative: None,
+            }),
+        }
+    }
+
+    for residue in &mut residues {
+        let name = &structure.atoms[residue.atom].residue_name;
+        residue.relative = MAX_AREAS
+            .iter()
+            .find(|(other, _)| other == name)
+            .map(|(_, max)| residue.area / max);
+    }
+    residues
+}
+
+/// Chain totals of `areas`, as (chain id, area) in structure order.
+pub fn chain_areas(structure: &Structure, areas: &[f32]) -> Vec<(String, f32)> {
+    let mut chains = Vec::<(usize, String, f32)>::new();
+    for (atom, area) in structure.atoms.iter().zip(areas) {
+        match chains.last_mut() {
+            Some((chain, _, total)) if *chain == atom.chain => *total += area,
+            _ => chains.push((atom.chain, atom.chain_id.clone(), *area)),
+        }
+    }
+
+    chains.into_iter().map(|(_, id, area)| (id, area)).collect()
+}
+
+/// How exposed each atom is, from 0 to 1: its residue's relative area for amino acids and the
+/// atom's share of its own lone sphere otherwise. Aligned with `structure.atoms`.
+pub fn accessibility(structure: &Structure, areas: &[f32]) -> Vec<f32> {
+    let residues = residue_areas(structure, areas)
+        .into_iter()
+        .filter_map(|residue| Some((structure.atoms[residue.atom].residue, residue.relative?)))
+        .collect::<HashMap<_, _>>();
+
+    structure
+        .atoms
+        .iter()
+        .zip(areas)
+        .map(|(atom, area)| match residues.get(&atom.residue) {
+            Some(relative) => relative.min(1.0),
+            None => area / (4.0 * PI * (radius(&atom.element) + PROBE_RADIUS).powi(2)),
+        })
+        .collect()
+}
+
+/// Comma separated areas of the atoms in `mask` and of the residues and chains they belong to,
+/// one row each, told apart by the `level` column.
+pub fn csv(structure: &Structure, areas: &[f32], mask: &[bool]) -> String {
+    let mut csv = String::from("level,chain,residue,number,atom,area,relative\n");
+    for (index, atom) in structure.atoms.iter().enumerate() {
+        if mask[index] && !atom.is_water() {
+            let _ = writeln!(
+                csv,
+                "atom,{},{},{},{},{:.2},",
+                atom.chain_id, atom.residue_name, atom.residue_number, atom.name, areas[index]
+            );
+        }
+    }
+
+    let selected = selected_residues(structure, mask);
+    for residue in residue_areas(structure, areas) {
+        let atom = &structure.atoms[residue.atom];
+        if selected.contains(&atom.residue) {
+            let relative = residue
+                .relative
+                .map_or(String::new(), |relative| format!("{:.3}", relative));
+            let _ = writeln!(
+                csv,
+                "residue,{},{},{},,{:.2},{}",
+                atom.chain_id, atom.residue_name, atom.residue_number, residue.area, relative
+            );
+        }
+    }
+
+    let selected_chains = structure
+        .atoms
+        .iter()
+        .zip(mask)
+        .filter(|(_, &hit)| hit)
+        .map(|(atom, _)| atom.chain_id.as_str())
+        .collect::<BTreeSet<_>>();
+    for (chain, area) in chain_areas(structure, areas) {
+        if selected_chains.contains(chain.as_str()) {
+            let _ = writeln!(csv, "chain,{},,,,{:.2},", chain, area);
+        }
+    }
+
+    csv
+}
+
+fn selected_residues(structure: &Structure, mask: &[bool]) -> BTreeSet<usize> {
+    structure
+        .atoms
+        .iter()
+        .zip(mask)
+        .filter(|(_, &hit)| hit)
+        .map(|(atom, _)| atom.residue)
+        .collect()
+}
+
+/// Chain totals and residue areas of the residues with atoms in `mask`.
+pub fn to_json(structure: &Structure, areas: &[f32], mask: &[bool]) -> Json {
+    let selected = selected_residues(structure, mask);
+    let residues = residue_areas(structure, areas)
+        .into_iter()
+        .filter(|residue| selected.contains(&structure.atoms[residue.atom].residue))
+        .map(|residue| {
+            let atom = &structure.atoms[residue.atom];
+            Json::object([
+                ("chain", Json::from(atom.chain_id.as_str())),
+                ("residue", Json::from(atom.residue_name.as_str())),
+                ("number", Json::Number(atom.residue_number as f64)),
+                ("area", Json::from(residue.area)),
+                ("relative", residue.relative.map_or(Json::Null, Json::from)),
+            ])
+        })
+        .collect();
+    let chains = chain_areas(structure, areas)
+        .into_iter()
+        .map(|(chain, area)| {
+            Json::object([("chain", Json::from(chain)), ("area", Json::from(area))])
+        })
+        .collect();
+
+    Json::object([
+        ("chains", Json::Array(chains)),
+        ("residues", Json::Array(residues)),
+    ])
+}
+
+/// Points spread evenly over the unit sphere along a golden angle spiral.
+fn sphere_points(count: usize) -> Vec<[f32; 3]> {
+    let golden_angle = PI * (3.0 - 5f32.sqrt());
+
+    (0..count)
+        .map(|index| {
+            let z = 1.0 - (2 * index + 1) as f32 / count as f32;
+            let ring = (1.0 - z * z).sqrt();
+            let (sin, cos) = (golden_angle * index as f32).sin_cos();
+            [ring * cos, ring * sin, z]
+        })
+        .collect()
+}
+
+#[test]
+fn both_methods_agree_on_spheres_and_structures() {
+    use crate::scene::Scene;
+    use crate::style::Style;
+
+    let expected = 4.0 * PI * (1.6 + PROBE_RADIUS).powi(2);
+    for method in [Method::ShrakeRupley, Method::LeeRichards] {
+        let lone = areas(&[[0.0; 3]], &[1.6], method);
+        assert!((lone[0] - expected).abs() < 1e-3);
+    }
+
+    // Two overlapping spheres each lose a cap of height r - d / 2
+    let radius = 1.6 + PROBE_RADIUS;
+    let cap = 2.0 * PI * radius * (radius - 1.5);
+    let pair = [[0.0; 3], [3.0, 0.0, 0.0]];
+    let areas = shrake_rupley(&pair, &[1.6; 2], 2000)
+        .into_iter()
+        .chain(lee_richards(&pair, &[1.6; 2], 200));
+    for area in areas {
+        assert!((area - (expected - cap)).abs() / expected < 0.01);
+    }
+
+    let scene = Scene::open("1d66.pdb", &Style::default()).unwrap();
+    let structure = scene.structure.as_ref().unwrap();
+    let shrake = atom_areas(structure, Method::ShrakeRupley);
+    let lee = atom_areas(structure, Method::LeeRichards);
+    let [shrake_total, lee_total] = [&shrake, &lee].map(|areas| areas.iter().sum::<f32>());
+    assert!((shrake_total - lee_total).abs() / shrake_total < 0.02);
+
+    let residues = residue_areas(structure, &shrake);
+    let chains = chain_areas(structure, &shrake);
+    let residue_total = residues.iter().map(|residue| residue.area).sum::<f32>();
+    let chain_total = chains.iter().map(|(_, area)| area).sum::<f32>();
+    assert!((residue_total - shrake_total).abs() < 1.0);
+    assert!((chain_total - shrake_total).abs() < 1.0);
+    assert!(residues
+        .iter()
+        .filter_map(|residue| residue.relative)
+        .all(|relative| (0.0..1.3).contains(&relative)));
+
+    let everything = vec![true; structure.atoms.len()];
+    let csv = csv(structure, &shrake, &everything);
+    assert_eq!(
+        csv.lines()
+            .filter(|line| line.starts_with("residue,"))
+            .count(),
+        residues.len()
+    );
+}
+
+#[test]
+fn parses_sasa_commands() {
+    use crate::script::ScriptCommand;
+    use std::path::PathBuf;
+
+    assert_eq!(
+        ScriptCommand::parse("sasa lee-richards exposed.csv"),
+        Ok(Some(ScriptCommand::SurfaceArea(
+            Method::LeeRichards,
+            Some(PathBuf::from("exposed.csv"))
+        )))
+    );
+}
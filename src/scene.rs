@@ -1,11 +1,23 @@
 use crate::cylinder::Cylinder;
+use crate::math::{self, distance};
 use crate::object::Object;
+use crate::sasa;
+use crate::selection::Selection;
+use crate::sequence;
+use crate::session::Camera;
+use crate::spatial::{Grid, KdTree};
 use crate::sphere::Sphere;
+use crate::style::{
+    chain_color, element_color, gradient_color, residue_color, ColorScheme, Representation, Style,
+    REPRESENTATION_COUNT,
+};
 use pdbtbx;
 use pdbtbx::*;
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
 
-const SPHERE_SECTOR: u32 = 2;
-const SPHERE_STACK: u32 = 2;
+pub const SPHERE_SECTOR: u32 = 2;
+pub const SPHERE_STACK: u32 = 2;
 
 #[derive(Clone)]
 pub enum ModelTypes {
@@ -13,82 +25,720 @@ pub enum ModelTypes {
     Cylinder(super::cylinder::Cylinder),
 }
 
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RenderLayer {
+    Opaque,
+    Transparent,
+}
+
 // #[derive(Clone)]
+#[derive(Default)]
 pub struct Scene {
     pub spheres: Vec<Sphere>,
     pub cyliders: Vec<Cylinder>,
+    /// Index into `structure.atoms` of the atom each sphere was built for. Objects added by hand
+    /// have no entry.
+    pub sphere_atoms: Vec<usize>,
+    /// Like `sphere_atoms`, for the cylinders.
+    pub cylinder_atoms: Vec<usize>,
+    /// Structure the geometry was built from, `None` for scenes assembled by hand.
+    pub structure: Option<Structure>,
+    /// A second structure superposed onto `structure` to compare with it. Its geometry follows
+    /// that of `structure` and has no `sphere_atoms` or `cylinder_atoms` entries.
+    pub mobile: Option<Structure>,
+}
+
+impl From<&String> for Scene {
+    fn from(filename: &String) -> Self {
+        Scene::open(filename, &Style::default()).unwrap()
+    }
 }
 
-impl Default for Scene {
-    fn default() -> Self {
-        Self {
-            spheres: Vec::new(),
-            cyliders: Vec::new(),
+/// A loaded PDB file together with how each of its atoms is displayed.
+pub struct Structure {
+    pub pdb: PDB,
+    /// File the structure was read from, `None` when built from an in-memory PDB.
+    pub source: Option<PathBuf>,
+    /// Residue names of each chain's full sequence as the file lists them, `None` when it
+    /// lists none.
+    pub seqres: Option<HashMap<String, Vec<String>>>,
+    /// Rigid transform placing the original coordinates onto a reference structure, once the
+    /// structure has been superposed.
+    pub superposition: Option<TransformationMatrix>,
+    /// Every atom with a known element, in hierarchy order.
+    pub atoms: Vec<SceneAtom>,
+    pub sphere_detail: (u32, u32),
+    /// Non-covalent interactions found by the analyses, drawn as dashed lines.
+    pub contacts: Vec<Contact>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ContactKind {
+    HydrogenBond,
+    SaltBridge,
+    PiStacking,
+    CationPi,
+    Hydrophobic,
+    /// Atoms overlapping more than their van der Waals radii allow, drawn as a disc.
+    Clash,
+}
+
+impl ContactKind {
+    pub const ALL: [ContactKind; 6] = [
+        ContactKind::HydrogenBond,
+        ContactKind::SaltBridge,
+        ContactKind::PiStacking,
+        ContactKind::CationPi,
+        ContactKind::Hydrophobic,
+        ContactKind::Clash,
+    ];
+
+    /// Color, dash radius and dash length the contact is drawn with. Clashes are drawn as a
+    /// single disc of that radius and thickness.
+    pub fn style(&self) -> ([f32; 3], f32, f32) {
+        match self {
+            ContactKind::HydrogenBond => ([0.2, 0.8, 1.0], 0.08, 0.25),
+            ContactKind::SaltBridge => ([1.0, 0.85, 0.1], 0.1, 0.3),
+            ContactKind::PiStacking => ([0.3, 0.9, 0.3], 0.1, 0.4),
+            ContactKind::CationPi => ([1.0, 0.5, 0.0], 0.1, 0.35),
+            ContactKind::Hydrophobic => ([0.6, 0.6, 0.6], 0.06, 0.15),
+            ContactKind::Clash => ([1.0, 0.1, 0.1], 0.45, 0.08),
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ContactKind::HydrogenBond => "hydrogen bond",
+            ContactKind::SaltBridge => "salt bridge",
+            ContactKind::PiStacking => "pi stacking",
+            ContactKind::CationPi => "cation-pi",
+            ContactKind::Hydrophobic => "hydrophobic",
+            ContactKind::Clash => "clash",
         }
     }
 }
 
-impl From<&String> for Scene {
-    fn from(filename: &String) -> Self {
-        let (mut pdb, _) = pdbtbx::open_pdb(filename, StrictnessLevel::Loose).unwrap();
-
-        let scale_matrix = &pdb.scale.clone().unwrap().matrix();
-
-        let scale_x = scale_matrix[0][0] as f32;
-        let scale_y = scale_matrix[1][1] as f32;
-        let scale_z = scale_matrix[2][2] as f32;
-
-        let mut spheres = Vec::<Sphere>::new();
-        let mut centre: [f32; 3] = [0.0, 0.0, 0.0];
-
-        pdb.atoms().for_each(|atom| {
-            if !atom.hetero() {
-                if let Some(element) = atom.element() {
-                    let mut model = Sphere::new(
-                        SPHERE_SECTOR,
-                        SPHERE_STACK,
-                        element.atomic_radius().covalent_single as f32 * 50.0,
-                        select_color(element),
-                    );
-                    model.scale(scale_x, scale_y, scale_z);
-                    model.translate(atom.x() as f32, atom.y() as f32, atom.z() as f32);
-                    centre = [
-                        centre[0] + atom.x() as f32,
-                        centre[1] + atom.y() as f32,
-                        centre[2] + atom.z() as f32,
-                    ];
-                    spheres.push(model);
+/// An interaction between two points of a structure, usually atoms, in centered coordinates.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Contact {
+    pub kind: ContactKind,
+    pub start: [f32; 3],
+    pub end: [f32; 3],
+    /// Atom the dashes belong to, for exports grouping geometry by residue.
+    pub atom: usize,
+}
+
+/// Display state of one atom, in the centered coordinates the geometry is built in.
+#[derive(Debug, Clone)]
+pub struct SceneAtom {
+    pub position: [f32; 3],
+    pub element: Element,
+    pub name: String,
+    pub residue_name: String,
+    /// Residue serial number as written in the file.
+    pub residue_number: isize,
+    pub chain_id: String,
+    pub b_factor: f32,
+    /// Index of the atom's chain within the structure.
+    pub chain: usize,
+    /// Index of the atom's residue within the structure.
+    pub residue: usize,
+    pub color: [f32; 3],
+    pub opacity: f32,
+    /// Whether the atom is drawn in each representation, indexed by `Representation as usize`.
+    pub shown: [bool; REPRESENTATION_COUNT],
+    /// Labels the atom's residue in vector exports.
+    pub labeled: bool,
+    /// Drawn in `HIGHLIGHT_COLOR` instead of `color`, e.g. while picked in the sequence bar.
+    pub highlighted: bool,
+}
+
+impl SceneAtom {
+    /// Color the atom is drawn in.
+    pub fn shade(&self) -> [f32; 3] {
+        match self.highlighted {
+            true => HIGHLIGHT_COLOR,
+            false => self.color,
+        }
+    }
+
+    /// "A:ARG 12" for a residue's atom.
+    pub fn describe(&self) -> String {
+        format!(
+            "{}:{} {}",
+            self.chain_id, self.residue_name, self.residue_number
+        )
+    }
+
+    /// Whether the atom belongs to a water molecule.
+    pub fn is_water(&self) -> bool {
+        matches!(self.residue_name.as_str(), "HOH" | "WAT" | "H2O" | "DOD")
+    }
+}
+
+/// Largest distance between consecutive alpha carbons still traced as one chain.
+const MAX_CA_DISTANCE: f32 = 4.2;
+/// Slack over the sum of covalent radii within which two atoms count as bonded.
+pub const BOND_TOLERANCE: f32 = 0.45;
+const BOND_RADIUS: f32 = 0.15;
+const TRACE_RADIUS: f32 = 0.35;
+/// Atoms nearest the cursor considered when picking, and how far from it they may be in pixels.
+const PICK_CANDIDATES: usize = 16;
+const PICK_RADIUS: f32 = 8.0;
+const HIGHLIGHT_COLOR: [f32; 3] = [1.0, 0.85, 0.0];
+
+impl Scene {
+    /// Loads a PDB or mmCIF file and builds the geometry `style` asks for.
+    pub fn open(filename: &str, style: &Style) -> Result<Self, String> {
+        let structure = Structure::open(filename, style)?;
+        Ok(Scene::from_structure(structure))
+    }
+
+    pub fn build(pdb: PDB, style: &Style) -> Self {
+        Scene::from_structure(Structure::new(pdb, style))
+    }
+
+    /// Centers `structure` on its shown atoms and builds its geometry.
+    fn from_structure(mut structure: Structure) -> Self {
+        let shown = structure
+            .atoms
+            .iter()
+            .map(|atom| atom.shown.contains(&true))
+            .collect::<Vec<_>>();
+        structure.center(&shown);
+
+        let mut scene = Scene {
+            structure: Some(structure),
+            ..Scene::default()
+        };
+        scene.rebuild();
+
+        scene
+    }
+
+    /// Regenerates every sphere and cylinder from the structures' display state.
+    pub fn rebuild(&mut self) {
+        let Some(structure) = &self.structure else {
+            return;
+        };
+
+        let (spheres, sphere_atoms, cylinders) = geometry(structure);
+        self.spheres = spheres;
+        self.sphere_atoms = sphere_atoms;
+        self.cyliders = cylinders.models;
+        self.cylinder_atoms = cylinders.atoms;
+
+        if let Some(mobile) = &self.mobile {
+            let (spheres, _, cylinders) = geometry(mobile);
+            self.spheres.extend(spheres);
+            self.cyliders.extend(cylinders.models);
+        }
+    }
+
+    /// Atoms matching `selection`, aligned with `structure.atoms`. Empty without a structure.
+    pub fn select(&self, selection: &Selection) -> Vec<bool> {
+        self.structure
+            .as_ref()
+            .map(|structure| structure.select(selection))
+            .unwrap_or_default()
+    }
+
+    /// Draws the atoms in `mask` with `representation` as well as their current ones.
+    pub fn show(&mut self, mask: &[bool], representation: Representation) {
+        if let Some(structure) = &mut self.structure {
+            structure.show(mask, representation);
+        }
+        self.rebuild();
+    }
+
+    /// Stops drawing the atoms in `mask` with `representation`, or at all when `None`.
+    pub fn hide(&mut self, mask: &[bool], representation: Option<Representation>) {
+        if let Some(structure) = &mut self.structure {
+            for (atom, _) in structure.atoms.iter_mut().zip(mask).filter(|(_, &hit)| hit) {
+                match representation {
+                    Some(representation) => atom.shown[representation as usize] = false,
+                    None => atom.shown = [false; REPRESENTATION_COUNT],
                 }
             }
+        }
+        self.rebuild();
+    }
+
+    pub fn color(&mut self, mask: &[bool], scheme: ColorScheme) {
+        if let Some(structure) = &mut self.structure {
+            structure.color(mask, scheme);
+        }
+        self.rebuild();
+    }
+
+    /// Replaces the contacts of `kind` with `contacts`, e.g. freshly found hydrogen bonds.
+    pub fn set_contacts(&mut self, kind: ContactKind, contacts: Vec<Contact>) {
+        if let Some(structure) = &mut self.structure {
+            structure.contacts.retain(|contact| contact.kind != kind);
+            structure.contacts.extend(contacts);
+        }
+        self.rebuild();
+    }
+
+    /// Moves the view's center of rotation to the atoms in `mask`, or to the whole structure if
+    /// none are.
+    pub fn center_on(&mut self, mask: &[bool]) {
+        if let Some(structure) = &mut self.structure {
+            let shift = structure.center(mask);
+            if let Some(mobile) = &mut self.mobile {
+                mobile.translate(shift);
+            }
+        }
+        self.rebuild();
+    }
+
+    /// Highlights exactly the atoms in `mask`, rebuilding only if that changes anything.
+    pub fn highlight(&mut self, mask: &[bool]) {
+        let Some(structure) = &mut self.structure else {
+            return;
+        };
+        let mut changed = false;
+        for (index, atom) in structure.atoms.iter_mut().enumerate() {
+            let highlighted = mask.get(index).copied().unwrap_or(false);
+            changed |= atom.highlighted != highlighted;
+            atom.highlighted = highlighted;
+        }
+        if changed {
+            self.rebuild();
+        }
+    }
+
+    /// Adds or removes the residue labels of the atoms in `mask`.
+    pub fn label(&mut self, mask: &[bool], labeled: bool) {
+        if let Some(structure) = &mut self.structure {
+            for (atom, _) in structure.atoms.iter_mut().zip(mask).filter(|(_, &hit)| hit) {
+                atom.labeled = labeled;
+            }
+        }
+    }
+}
+
+impl Structure {
+    /// Loads a PDB or mmCIF file, shown and colored as `style` asks, in the file's coordinates.
+    pub fn open(filename: &str, style: &Style) -> Result<Self, String> {
+        let (pdb, _) = pdbtbx::open(filename, StrictnessLevel::Loose).map_err(|errors| {
+            let errors = errors
+                .iter()
+                .map(|error| error.to_string())
+                .collect::<Vec<_>>();
+            format!("Failed to open {}:\n{}", filename, errors.join("\n"))
+        })?;
+
+        let mut structure = Structure::new(pdb, style);
+        structure.source = Some(PathBuf::from(filename));
+        structure.seqres = sequence::read_seqres(Path::new(filename));
+        Ok(structure)
+    }
+
+    pub fn new(pdb: PDB, style: &Style) -> Self {
+        let mut structure = Structure {
+            atoms: structure_atoms(&pdb),
+            pdb,
+            source: None,
+            seqres: None,
+            superposition: None,
+            contacts: Vec::new(),
+            sphere_detail: style.sphere_detail,
+        };
+
+        let shown = match &style.selection {
+            Some(selection) => structure.select(selection),
+            None => structure.atoms.iter().map(|_| true).collect(),
+        };
+        let shown = structure
+            .hierarchy()
+            .zip(shown)
+            .map(|((_, _, atom), shown)| shown && (style.selection.is_some() || !atom.hetero()))
+            .collect::<Vec<_>>();
+
+        structure.show(&shown, style.representation);
+        structure.color(&vec![true; shown.len()], style.color_scheme);
+
+        structure
+    }
+
+    /// Every atom of `atoms` with its chain and residue, in the same order.
+    pub fn hierarchy(&self) -> impl Iterator<Item = (&Chain, &Residue, &Atom)> + '_ {
+        self.pdb.chains().flat_map(|chain| {
+            chain.residues().flat_map(move |residue| {
+                residue
+                    .atoms()
+                    .filter(|atom| atom.element().is_some())
+                    .map(move |atom| (chain, residue, atom))
+            })
+        })
+    }
+
+    pub fn select(&self, selection: &Selection) -> Vec<bool> {
+        let combine = |left: &Selection, right: &Selection, both: fn(bool, bool) -> bool| {
+            self.select(left)
+                .into_iter()
+                .zip(self.select(right))
+                .map(|(left, right)| both(left, right))
+                .collect()
+        };
+
+        match selection {
+            Selection::Within(distance, inner) => self.within(*distance, &self.select(inner)),
+            Selection::Not(inner) if inner.is_spatial() => {
+                self.select(inner).into_iter().map(|hit| !hit).collect()
+            }
+            Selection::And(left, right) if selection.is_spatial() => {
+                combine(left, right, |left, right| left && right)
+            }
+            Selection::Or(left, right) if selection.is_spatial() => {
+                combine(left, right, |left, right| left || right)
+            }
+            _ => self
+                .hierarchy()
+                .map(|(chain, residue, atom)| selection.matches(chain, residue, atom))
+                .collect(),
+        }
+    }
+
+    /// The shown atom drawn nearest the viewer close to `cursor`, in a view of `size` pixels.
+    pub fn pick(&self, camera: &Camera, size: (f32, f32), cursor: [f32; 2]) -> Option<usize> {
+        let shown = (0..self.atoms.len())
+            .filter(|&index| self.atoms[index].shown.contains(&true))
+            .collect::<Vec<_>>();
+        let projected = shown
+            .iter()
+            .map(|&index| {
+                let view = (camera.turn, camera.tilt, camera.scale);
+                math::project(self.atoms[index].position, view, size)
+            })
+            .collect::<Vec<_>>();
+        let flat = projected
+            .iter()
+            .map(|&([x, y], _)| [x, y, 0.0])
+            .collect::<Vec<_>>();
+
+        KdTree::new(&flat)
+            .nearest([cursor[0], cursor[1], 0.0], PICK_CANDIDATES)
+            .into_iter()
+            .filter(|&(_, length)| length <= PICK_RADIUS)
+            .min_by(|a, b| projected[a.0].1.total_cmp(&projected[b.0].1))
+            .map(|(index, _)| shown[index])
+    }
+
+    /// Atoms within `distance` of any atom in `mask`, those included.
+    fn within(&self, distance: f32, mask: &[bool]) -> Vec<bool> {
+        let positions = self
+            .atoms
+            .iter()
+            .map(|atom| atom.position)
+            .collect::<Vec<_>>();
+        let grid = Grid::new(&positions, distance);
+
+        let mut near = vec![false; positions.len()];
+        for (&position, _) in positions.iter().zip(mask).filter(|(_, &hit)| hit) {
+            grid.for_each_within(position, distance, |index, _| near[index] = true);
+        }
+
+        near
+    }
+
+    fn show(&mut self, mask: &[bool], representation: Representation) {
+        for (atom, _) in self.atoms.iter_mut().zip(mask).filter(|(_, &hit)| hit) {
+            atom.shown[representation as usize] = true;
+        }
+    }
+
+    fn color(&mut self, mask: &[bool], scheme: ColorScheme) {
+        let (min_b, max_b) = self
+            .atoms
+            .iter()
+            .fold((f32::MAX, f32::MIN), |(min, max), atom| {
+                (min.min(atom.b_factor), max.max(atom.b_factor))
+            });
+        let accessibility = if scheme == ColorScheme::Accessibility {
+            sasa::accessibility(self, &sasa::atom_areas(self, sasa::Method::default()))
+        } else {
+            Vec::new()
+        };
+
+        for (index, atom) in self.atoms.iter_mut().enumerate() {
+            if !mask[index] {
+                continue;
+            }
+            atom.color = match scheme {
+                ColorScheme::Element => element_color(&atom.element),
+                ColorScheme::Chain => chain_color(atom.chain),
+                ColorScheme::Residue => residue_color(&atom.residue_name),
+                ColorScheme::BFactor => {
+                    gradient_color((atom.b_factor - min_b) / (max_b - min_b).max(f32::EPSILON))
+                }
+                ColorScheme::Accessibility => gradient_color(accessibility[index]),
+                ColorScheme::Uniform(color) => color,
+            };
+        }
+    }
+
+    /// Moves the mean position of the atoms in `mask`, or of all atoms if none are, to the
+    /// origin, returning how far everything moved.
+    fn center(&mut self, mask: &[bool]) -> [f32; 3] {
+        let mut centered = self
+            .atoms
+            .iter()
+            .zip(mask)
+            .filter(|(_, &hit)| hit)
+            .map(|(atom, _)| atom.position)
+            .collect::<Vec<_>>();
+        if centered.is_empty() {
+            centered = self.atoms.iter().map(|atom| atom.position).collect();
+        }
+
+        let count = centered.len().max(1) as f32;
+        let centre = centered.iter().fold([0.0; 3], |sum, position| {
+            [
+                sum[0] + position[0] / count,
+                sum[1] + position[1] / count,
+                sum[2] + position[2] / count,
+            ]
         });
 
-        centre = [
-            centre[0] / spheres.len() as f32,
-            centre[1] / spheres.len() as f32,
-            centre[2] / spheres.len() as f32,
+        let shift = centre.map(|value| -value);
+        self.translate(shift);
+        shift
+    }
+
+    /// Moves the atoms and contacts by `shift` in view coordinates.
+    pub fn translate(&mut self, shift: [f32; 3]) {
+        let add = |position: &mut [f32; 3]| {
+            *position = [0, 1, 2].map(|axis| position[axis] + shift[axis]);
+        };
+        for atom in self.atoms.iter_mut() {
+            add(&mut atom.position);
+        }
+        for contact in self.contacts.iter_mut() {
+            add(&mut contact.start);
+            add(&mut contact.end);
+        }
+    }
+}
+
+/// Atoms of `pdb` with a known element, hidden and uncolored.
+fn structure_atoms(pdb: &PDB) -> Vec<SceneAtom> {
+    let mut atoms = Vec::new();
+    let mut residue_index = 0;
+    for (chain_index, chain) in pdb.chains().enumerate() {
+        for residue in chain.residues() {
+            residue_index += 1;
+
+            for atom in residue.atoms() {
+                let Some(element) = atom.element() else {
+                    continue;
+                };
+
+                atoms.push(SceneAtom {
+                    position: [atom.x() as f32, atom.y() as f32, atom.z() as f32],
+                    element: *element,
+                    name: atom.name().to_string(),
+                    residue_name: residue.name().unwrap_or_default().to_string(),
+                    residue_number: residue.serial_number(),
+                    chain_id: chain.id().to_string(),
+                    b_factor: atom.b_factor() as f32,
+                    chain: chain_index,
+                    residue: residue_index,
+                    color: [1.0; 3],
+                    opacity: 1.0,
+                    shown: [false; REPRESENTATION_COUNT],
+                    labeled: false,
+                    highlighted: false,
+                });
+            }
+        }
+    }
+
+    atoms
+}
+
+/// Spheres and cylinders showing `structure`, with the atom each sphere was built for.
+fn geometry(structure: &Structure) -> (Vec<Sphere>, Vec<usize>, Cylinders) {
+    let (sectors, stacks) = structure.sphere_detail;
+    let shown_in = |representation: Representation| {
+        structure
+            .atoms
+            .iter()
+            .enumerate()
+            .filter(move |(_, atom)| atom.shown[representation as usize])
+    };
+
+    let mut spheres = Vec::new();
+    let mut sphere_atoms = Vec::new();
+    let mut cylinders = Cylinders::default();
+    let mut add_sphere = |(index, atom): (usize, &SceneAtom), radius: f32, scale: [f32; 3]| {
+        let mut model = Sphere::new(sectors, stacks, radius, atom.shade());
+        model.scale(scale[0], scale[1], scale[2]);
+        model.translate(atom.position[0], atom.position[1], atom.position[2]);
+        model.set_opacity(atom.opacity);
+        spheres.push(model);
+        sphere_atoms.push(index);
+    };
+
+    // Spacefill radii follow the unit cell SCALE record, plain covalent radii without one
+    let scale = structure.pdb.scale.as_ref().map_or([0.02; 3], |scale| {
+        let matrix = scale.matrix();
+        [matrix[0][0], matrix[1][1], matrix[2][2]].map(|value| value as f32)
+    });
+    for atom in shown_in(Representation::Spacefill) {
+        let radius = atom.1.element.atomic_radius().covalent_single as f32 * 50.0;
+        add_sphere(atom, radius, scale);
+    }
+
+    for atom in shown_in(Representation::Surface) {
+        let radius = atom.1.element.atomic_radius();
+        let radius = radius.van_der_waals.unwrap_or(radius.covalent_single * 2.0);
+        add_sphere(atom, radius as f32, [1.0; 3]);
+    }
+
+    let sticks = shown_in(Representation::BallAndStick).collect::<Vec<_>>();
+    for &atom in &sticks {
+        let radius = atom.1.element.atomic_radius().covalent_single as f32 * 0.4;
+        add_sphere(atom, radius, [1.0; 3]);
+    }
+    let stick_atoms = sticks.iter().map(|(_, atom)| *atom).collect::<Vec<_>>();
+    for (a, b) in bonds(&stick_atoms) {
+        cylinders.add_stick(sticks[a], sticks[b], BOND_RADIUS, sectors);
+    }
+
+    let trace = shown_in(Representation::Cartoon)
+        .filter(|(_, atom)| atom.name == "CA")
+        .collect::<Vec<_>>();
+    for &atom in &trace {
+        add_sphere(atom, TRACE_RADIUS, [1.0; 3]);
+    }
+    for pair in trace.windows(2) {
+        let (a, b) = (pair[0].1, pair[1].1);
+        if a.chain == b.chain && distance(a.position, b.position) < MAX_CA_DISTANCE {
+            cylinders.add_stick(pair[0], pair[1], TRACE_RADIUS, sectors);
+        }
+    }
+
+    for contact in &structure.contacts {
+        match contact.kind {
+            ContactKind::Clash => cylinders.add_disc(contact, sectors),
+            _ => cylinders.add_dashes(contact, sectors),
+        }
+    }
+
+    (spheres, sphere_atoms, cylinders)
+}
+
+/// Cylinders built by `rebuild`, with the atom each one belongs to.
+#[derive(Default)]
+struct Cylinders {
+    models: Vec<Cylinder>,
+    atoms: Vec<usize>,
+}
+
+impl Cylinders {
+    /// Joins two atoms with a cylinder, each half in its atom's color.
+    fn add_stick(
+        &mut self,
+        (a_index, a): (usize, &SceneAtom),
+        (b_index, b): (usize, &SceneAtom),
+        radius: f32,
+        sectors: u32,
+    ) {
+        let middle = [
+            (a.position[0] + b.position[0]) / 2.0,
+            (a.position[1] + b.position[1]) / 2.0,
+            (a.position[2] + b.position[2]) / 2.0,
         ];
 
-        for atom in spheres.iter_mut() {
-            atom.translate(-centre[0], -centre[1], -centre[2]);
+        for (start, end, index, atom) in [
+            (a.position, middle, a_index, a),
+            (middle, b.position, b_index, b),
+        ] {
+            let mut model = Cylinder::between(start, end, radius, sectors, atom.shade());
+            model.set_opacity(atom.opacity);
+            self.models.push(model);
+            self.atoms.push(index);
         }
+    }
 
-        return Self {
-            spheres,
-            cyliders: vec![],
+    /// Draws a contact as a flat cylinder across the middle of its two ends.
+    fn add_disc(&mut self, contact: &Contact, sectors: u32) {
+        let (color, radius, thickness) = contact.kind.style();
+        let length = distance(contact.start, contact.end).max(f32::EPSILON);
+        let point = |offset: f32| {
+            [0, 1, 2].map(|axis| {
+                let middle = (contact.start[axis] + contact.end[axis]) / 2.0;
+                middle + (contact.end[axis] - contact.start[axis]) * offset / length
+            })
         };
+
+        self.models.push(Cylinder::between(
+            point(-thickness / 2.0),
+            point(thickness / 2.0),
+            radius,
+            sectors,
+            color,
+        ));
+        self.atoms.push(contact.atom);
     }
-}
 
-fn select_color(element: &Element) -> [f32; 3] {
-    match element {
-        Element::O => [1.0, 0.0, 1.0],
-        Element::C => [0.0, 1.0, 0.0],
-        Element::N => [0.0, 0.0, 1.0],
-        Element::H => [1.0, 0.0, 0.0],
-        _ => [1.0, 1.0, 1.0],
+    /// Draws a contact as a row of short cylinders with gaps as long as the dashes.
+    fn add_dashes(&mut self, contact: &Contact, sectors: u32) {
+        let (color, radius, dash) = contact.kind.style();
+        let length = distance(contact.start, contact.end);
+        let count = ((length / (2.0 * dash)).round() as usize).max(1);
+        let point = |t: f32| {
+            [0, 1, 2]
+                .map(|axis| contact.start[axis] + (contact.end[axis] - contact.start[axis]) * t)
+        };
+
+        for index in 0..count {
+            let start = (2 * index) as f32 / (2 * count - 1) as f32;
+            let end = (2 * index + 1) as f32 / (2 * count - 1) as f32;
+            self.models.push(Cylinder::between(
+                point(start),
+                point(end),
+                radius,
+                sectors,
+                color,
+            ));
+            self.atoms.push(contact.atom);
+        }
     }
 }
 
+/// Covalent bonds inferred from distances, within residues and along the peptide backbone.
+fn bonds(atoms: &[&SceneAtom]) -> Vec<(usize, usize)> {
+    let limit = |a: &SceneAtom, b: &SceneAtom| {
+        (a.element.atomic_radius().covalent_single + b.element.atomic_radius().covalent_single)
+            as f32
+            + BOND_TOLERANCE
+    };
+    let longest = atoms
+        .iter()
+        .map(|atom| limit(atom, atom))
+        .fold(0.0, f32::max);
+
+    let positions = atoms.iter().map(|atom| atom.position).collect::<Vec<_>>();
+    Grid::new(&positions, longest)
+        .pairs(longest)
+        .into_iter()
+        .filter(|&(i, j, distance)| {
+            let (a, b) = (atoms[i], atoms[j]);
+            // Within a residue, or the peptide bond to the next one
+            let linked = a.residue == b.residue
+                || (a.chain == b.chain
+                    && a.residue + 1 == b.residue
+                    && a.name == "C"
+                    && b.name == "N");
+            linked && distance > 0.4 && distance < limit(a, b)
+        })
+        .map(|(i, j, _)| (i, j))
+        .collect()
+}
+
 impl Scene {
     pub fn add(&mut self, object: ModelTypes) {
         match object {
@@ -97,18 +747,147 @@ impl Scene {
         }
     }
 
-    pub fn render(&self, renderer: &mut crate::opengl::Renderer) {
+    /// Draws the objects belonging to `layer`, opaque ones or those with an opacity below 1.0.
+    pub fn render(&self, renderer: &crate::opengl::Renderer, layer: RenderLayer) {
+        let in_layer =
+            |model: &dyn Object| (model.opacity() < 1.0) == (layer == RenderLayer::Transparent);
+
         for model in &self.spheres {
-            unsafe {
-                model.drawer(renderer);
+            if in_layer(model) {
+                unsafe {
+                    model.drawer(renderer);
+                }
+            }
+        }
+
+        for model in &self.cyliders {
+            if in_layer(model) {
+                unsafe {
+                    model.drawer(renderer);
+                }
+            }
+        }
+    }
+
+    pub fn has_transparent(&self) -> bool {
+        self.spheres.iter().any(|model| model.opacity() < 1.0)
+            || self.cyliders.iter().any(|model| model.opacity() < 1.0)
+    }
+
+    /// Sets the opacity of every object, e.g. to ghost the whole structure.
+    pub fn set_opacity(&mut self, opacity: f32) {
+        if let Some(structure) = &mut self.structure {
+            for atom in structure.atoms.iter_mut() {
+                atom.opacity = opacity;
             }
         }
+
+        for model in self.spheres.iter_mut() {
+            model.set_opacity(opacity);
+        }
+
+        for model in self.cyliders.iter_mut() {
+            model.set_opacity(opacity);
+        }
     }
 }
 
 #[test]
 fn testpdb() {
-    let test = Scene::from("1k8h.pdb".to_string());
+    let test = Scene::from(&"1d66.pdb".to_string());
 
     println!("{:?}", test.spheres.len());
 }
+
+#[test]
+fn ghosting_moves_objects_to_transparent_layer() {
+    let mut scene = Scene::default();
+    scene.add(ModelTypes::Sphere(Sphere::new(4, 4, 1.0, [1.0, 1.0, 1.0])));
+
+    assert!(!scene.has_transparent());
+    scene.set_opacity(0.3);
+    assert!(scene.has_transparent());
+}
+
+#[test]
+fn representations_build_expected_geometry() {
+    let (pdb, _) = pdbtbx::open_pdb("1d66.pdb", StrictnessLevel::Loose).unwrap();
+
+    let spacefill = Scene::build(pdb.clone(), &Style::default());
+    assert!(!spacefill.spheres.is_empty() && spacefill.cyliders.is_empty());
+
+    let sticks = Scene::build(
+        pdb.clone(),
+        &Style {
+            representation: Representation::BallAndStick,
+            ..Style::default()
+        },
+    );
+    assert_eq!(sticks.spheres.len(), spacefill.spheres.len());
+    assert!(!sticks.cyliders.is_empty());
+
+    let chain_a = Scene::build(
+        pdb,
+        &Style {
+            selection: Some("chain A".parse().unwrap()),
+            ..Style::default()
+        },
+    );
+    assert!(chain_a.spheres.len() < spacefill.spheres.len());
+}
+
+#[test]
+fn hiding_a_selection_removes_its_geometry() {
+    let mut scene = Scene::from(&"1d66.pdb".to_string());
+    let count = scene.spheres.len();
+
+    let chain_a = scene.select(&"chain A and not hetero".parse().unwrap());
+    scene.hide(&chain_a, None);
+    assert!(scene.spheres.len() < count);
+
+    scene.show(&chain_a, Representation::Spacefill);
+    assert_eq!(scene.spheres.len(), count);
+}
+
+#[test]
+fn selects_and_picks_by_position() {
+    let scene = Scene::open("1d66.pdb", &Style::default()).unwrap();
+    let structure = scene.structure.as_ref().unwrap();
+
+    let residue = structure.select(&"chain A and resi 20".parse().unwrap());
+    let near = structure.select(&"within 4 of (chain A and resi 20)".parse().unwrap());
+    let expected = structure
+        .atoms
+        .iter()
+        .map(|atom| {
+            structure
+                .atoms
+                .iter()
+                .zip(&residue)
+                .any(|(other, &hit)| hit && distance(atom.position, other.position) <= 4.0)
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(near, expected);
+    assert!(near.iter().filter(|&&hit| hit).count() > residue.iter().filter(|&&hit| hit).count());
+    let outside = structure.select(&"not within 4 of (chain A and resi 20)".parse().unwrap());
+    assert!(outside.iter().zip(&near).all(|(&out, &hit)| out != hit));
+
+    let camera = Camera {
+        turn: 0.3,
+        tilt: -0.2,
+        scale: 0.03,
+    };
+    let size = (800.0, 600.0);
+    let view = (camera.turn, camera.tilt, camera.scale);
+    let target = structure
+        .atoms
+        .iter()
+        .position(|atom| atom.shown.contains(&true))
+        .unwrap();
+    let (cursor, depth) = math::project(structure.atoms[target].position, view, size);
+    let picked = structure.pick(&camera, size, cursor).unwrap();
+    let (position, picked_depth) = math::project(structure.atoms[picked].position, view, size);
+    assert!(picked_depth <= depth);
+    assert!(distance([position[0], position[1], 0.0], [cursor[0], cursor[1], 0.0]) <= PICK_RADIUS);
+    assert_eq!(structure.pick(&camera, size, [-100.0, -100.0]), None);
+}
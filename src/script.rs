@@ -0,0 +1,985 @@
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::{Path, PathBuf};
+
+use crate::align::{self, Alignment, Mode};
+use crate::clash::{self, Clash};
+use crate::coordinates;
+use crate::gltf;
+use crate::hbond;
+use crate::interaction::{self, Interaction};
+use crate::interface::{self, Interface};
+use crate::mesh;
+use crate::opengl::Renderer;
+use crate::ramachandran::{self, Plot, Point};
+use crate::sasa;
+use crate::scene::{ContactKind, Scene, SceneAtom, Structure};
+use crate::selection::Selection;
+use crate::sequence::{self, Sequence, SequencePanel};
+use crate::session::{Camera, Session};
+use crate::style::{parse_color, ColorScheme, Representation, Style};
+use crate::superpose::{self, Fit, Superposition};
+use crate::svg;
+
+/// One line of a `.bpx` script or of the in-app console.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptCommand {
+    Load(PathBuf),
+    /// Sets the atoms the following `color`, `show` and `hide` commands act on.
+    Select(Selection),
+    Color(ColorScheme),
+    Show(Representation),
+    /// Hides one representation, or everything when `None`.
+    Hide(Option<Representation>),
+    /// Adds or removes residue labels, drawn in vector exports.
+    Label(bool),
+    /// Absolute turn and tilt in degrees.
+    Orient(f32, f32),
+    /// Relative turn and tilt in degrees.
+    Rotate(f32, f32),
+    /// Multiplies the current magnification.
+    Zoom(f32),
+    Background([f32; 3]),
+    Png(PathBuf),
+    /// Writes the scene geometry in the format named by the file extension.
+    Export(PathBuf),
+    /// Writes the selected atoms as PDB or mmCIF, with the superposition transform applied when
+    /// the flag is set.
+    Write(PathBuf, bool),
+    /// Writes the current view to a session file.
+    Save(PathBuf),
+    /// Brings back a view written by `Save`.
+    Restore(PathBuf),
+    /// Finds hydrogen bonds of the selection and draws them, optionally writing them as a table.
+    HydrogenBonds(Option<PathBuf>),
+    /// Finds salt bridges, π-stacking, cation-π and hydrophobic contacts of the selection and
+    /// draws them, optionally writing a text or JSON report.
+    Interactions(Option<PathBuf>),
+    /// Finds the residues by which the selected chains touch within a cutoff in Å, shows them
+    /// as sticks and selects them.
+    Interface(f32),
+    /// Computes solvent accessible areas, printing chain totals for the selection and
+    /// optionally writing per atom, residue and chain areas as CSV.
+    SurfaceArea(sasa::Method, Option<PathBuf>),
+    /// Finds atoms of the selection overlapping by more than a tolerance in Å, marks them with
+    /// discs and prints the clash score, optionally writing a text or JSON report. With
+    /// `torsions`, the ends of a torsion (1–4 pairs) are checked too.
+    Clashes(f32, bool, Option<PathBuf>),
+    /// Removes the drawn contacts of these kinds.
+    ClearContacts(Vec<ContactKind>),
+    /// Loads a second structure if given and fits it onto the first over the matched atoms of
+    /// the selection, printing the RMSD. Residues are paired by a global sequence alignment,
+    /// or by chain and residue number when `numbered`.
+    Superpose(Option<PathBuf>, Fit, bool),
+    /// Loads a second structure if given, aligns the sequences of the selected chains to its
+    /// chains and fits it onto the first over the aligned residues, printing the alignments
+    /// and the RMSD and optionally writing them as FASTA or Clustal.
+    Align(Option<PathBuf>, Mode, Fit, Option<PathBuf>),
+    /// Moves the center of rotation to the selection.
+    Center,
+    /// Shows the Ramachandran plot over the view and prints a summary, optionally writing the
+    /// plot as a PNG image.
+    Ramachandran(Option<PathBuf>),
+    HideRamachandran,
+    /// Shows the chain sequences in a bar above the view and prints them, with residues of
+    /// the selection highlighted in both.
+    Sequence,
+    HideSequence,
+    Quit,
+}
+
+impl ScriptCommand {
+    /// Parses a line, returning `None` for blank lines and `#` comments.
+    pub fn parse(line: &str) -> Result<Option<ScriptCommand>, String> {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            return Ok(None);
+        }
+
+        let (name, rest) = line
+            .split_once(char::is_whitespace)
+            .map_or((line, ""), |(name, rest)| (name, rest.trim()));
+        let required = |what: &str| {
+            if rest.is_empty() {
+                Err(format!("{} needs {}", name, what))
+            } else {
+                Ok(rest)
+            }
+        };
+        let angles = || {
+            let values = rest
+                .split_whitespace()
+                .map(|value| value.parse::<f32>())
+                .collect::<Result<Vec<_>, _>>();
+            match values.as_deref() {
+                Ok([turn, tilt]) => Ok((*turn, *tilt)),
+                _ => Err(format!("{} needs <turn> <tilt> in degrees", name)),
+            }
+        };
+
+        let command = match name.to_ascii_lowercase().as_str() {
+            "load" => ScriptCommand::Load(PathBuf::from(required("a file")?)),
+            "select" => ScriptCommand::Select(required("a selection")?.parse()?),
+            "color" | "colour" => ScriptCommand::Color(required("a scheme or color")?.parse()?),
+            "show" => ScriptCommand::Show(required("a representation")?.parse()?),
+            "hide" => match rest {
+                "" | "all" | "everything" => ScriptCommand::Hide(None),
+                representation => ScriptCommand::Hide(Some(representation.parse()?)),
+            },
+            "label" => ScriptCommand::Label(true),
+            "unlabel" => ScriptCommand::Label(false),
+            "orient" => {
+                let (turn, tilt) = angles()?;
+                ScriptCommand::Orient(turn, tilt)
+            }
+            "rotate" => {
+                let (turn, tilt) = angles()?;
+                ScriptCommand::Rotate(turn, tilt)
+            }
+            "zoom" => ScriptCommand::Zoom(
+                rest.parse()
+                    .ok()
+                    .filter(|zoom: &f32| *zoom > 0.0)
+                    .ok_or_else(|| "zoom needs a positive factor".to_string())?,
+            ),
+            "background" => ScriptCommand::Background(parse_color(required("a color")?)?),
+            "png" => ScriptCommand::Png(PathBuf::from(required("a file")?)),
+            "export" => ScriptCommand::Export(PathBuf::from(required("a file")?)),
+            "write" => {
+                let (file, transformed) = match required("a .pdb or .cif file")?.rsplit_once(' ') {
+                    Some((file, "transformed")) => (file.trim(), true),
+                    Some((file, "original")) => (file.trim(), false),
+                    _ => (rest, false),
+                };
+                ScriptCommand::Write(PathBuf::from(file), transformed)
+            }
+            "save" => ScriptCommand::Save(PathBuf::from(required("a session file")?)),
+            "restore" => ScriptCommand::Restore(PathBuf::from(required("a session file")?)),
+            "hbonds" => match rest {
+                "off" => ScriptCommand::ClearContacts(vec![ContactKind::HydrogenBond]),
+                "" => ScriptCommand::HydrogenBonds(None),
+                table => ScriptCommand::HydrogenBonds(Some(PathBuf::from(table))),
+            },
+            "interactions" => match rest {
+                "off" => ScriptCommand::ClearContacts(interaction::KINDS.to_vec()),
+                "" => ScriptCommand::Interactions(None),
+                report => ScriptCommand::Interactions(Some(PathBuf::from(report))),
+            },
+            "interface" => ScriptCommand::Interface(match rest {
+                "" => interface::DEFAULT_CUTOFF,
+                cutoff => cutoff
+                    .parse()
+                    .ok()
+                    .filter(|cutoff: &f32| *cutoff > 0.0)
+                    .ok_or_else(|| "interface needs a positive cutoff in Å".to_string())?,
+            }),
+            "sasa" => {
+                let (first, table) = rest
+                    .split_once(char::is_whitespace)
+                    .map_or((rest, ""), |(first, table)| (first, table.trim()));
+                match first.parse::<sasa::Method>() {
+                    Ok(method) => ScriptCommand::SurfaceArea(method, non_empty(table)),
+                    Err(_) => ScriptCommand::SurfaceArea(sasa::Method::default(), non_empty(rest)),
+                }
+            }
+            "clashes" => {
+                let (rest, torsions) = match rest.rsplit_once(' ') {
+                    Some((rest, "torsions")) => (rest.trim(), true),
+                    _ if rest == "torsions" => ("", true),
+                    _ => (rest, false),
+                };
+                let (first, report) = rest
+                    .split_once(char::is_whitespace)
+                    .map_or((rest, ""), |(first, report)| (first, report.trim()));
+                match (first, first.parse::<f32>()) {
+                    ("off", _) => ScriptCommand::ClearContacts(vec![ContactKind::Clash]),
+                    (_, Ok(tolerance)) if tolerance >= 0.0 => {
+                        ScriptCommand::Clashes(tolerance, torsions, non_empty(report))
+                    }
+                    (_, Ok(_)) => return Err("clashes needs a tolerance of 0 Å or more".into()),
+                    _ => {
+                        ScriptCommand::Clashes(clash::DEFAULT_TOLERANCE, torsions, non_empty(rest))
+                    }
+                }
+            }
+            "superpose" => {
+                let (rest, numbered) = match rest.rsplit_once(char::is_whitespace) {
+                    Some((rest, "numbered")) => (rest.trim(), true),
+                    _ if rest == "numbered" => ("", true),
+                    _ => (rest, false),
+                };
+                let (file, fit) = match rest.rsplit_once(char::is_whitespace) {
+                    Some((file, fit)) if fit.parse::<Fit>().is_ok() => (file.trim(), fit.parse()?),
+                    _ => match rest.parse::<Fit>() {
+                        Ok(fit) => ("", fit),
+                        Err(_) => (rest, Fit::default()),
+                    },
+                };
+                ScriptCommand::Superpose(non_empty(file), fit, numbered)
+            }
+            "align" => {
+                let (mut file, mut mode, mut fit, mut export) =
+                    (None, Mode::default(), Fit::default(), None);
+                for word in rest.split_whitespace() {
+                    if let Ok(parsed) = word.parse::<Mode>() {
+                        mode = parsed;
+                    } else if let Ok(parsed) = word.parse::<Fit>() {
+                        fit = parsed;
+                    } else if is_alignment_file(word) {
+                        export = Some(PathBuf::from(word));
+                    } else if file.is_none() {
+                        file = Some(PathBuf::from(word));
+                    } else {
+                        return Err(format!("Unexpected '{}' after align", word));
+                    }
+                }
+                ScriptCommand::Align(file, mode, fit, export)
+            }
+            "center" | "centre" => ScriptCommand::Center,
+            "ramachandran" => match rest {
+                "off" => ScriptCommand::HideRamachandran,
+                "" | "on" => ScriptCommand::Ramachandran(None),
+                image => ScriptCommand::Ramachandran(Some(PathBuf::from(image))),
+            },
+            "sequence" | "sequences" => match rest {
+                "off" => ScriptCommand::HideSequence,
+                "" | "on" => ScriptCommand::Sequence,
+                _ => return Err("sequence takes 'on' or 'off'".into()),
+            },
+            "quit" | "exit" => ScriptCommand::Quit,
+            _ => return Err(format!("Unknown command '{}'", name)),
+        };
+
+        Ok(Some(command))
+    }
+}
+
+fn non_empty(path: &str) -> Option<PathBuf> {
+    (!path.is_empty()).then(|| PathBuf::from(path))
+}
+
+/// Whether `path` names a FASTA or Clustal file rather than a structure.
+fn is_alignment_file(path: &str) -> bool {
+    Path::new(path)
+        .extension()
+        .map(|extension| extension.to_string_lossy().to_ascii_lowercase())
+        .is_some_and(|extension| {
+            matches!(
+                extension.as_str(),
+                "fasta" | "fa" | "fas" | "faa" | "fna" | "aln" | "clustal" | "clw"
+            )
+        })
+}
+
+/// Prints the RMSD of a fit and colors the two structures apart.
+fn show_superposition(scene: &mut Scene, superposition: &Superposition) {
+    println!(
+        "RMSD {:.3} Å over {} atoms",
+        superposition.rmsd,
+        superposition.pairs.len()
+    );
+
+    if let Some(mobile) = &mut scene.mobile {
+        for atom in mobile.atoms.iter_mut() {
+            atom.color = superpose::MOBILE_COLOR;
+        }
+    }
+    let everything = scene.select(&Selection::All);
+    scene.color(
+        &everything,
+        ColorScheme::Uniform(superpose::REFERENCE_COLOR),
+    );
+}
+
+/// Width and height of Ramachandran plots written by `ramachandran <file.png>`, in pixels.
+const PLOT_IMAGE_SIZE: u32 = 512;
+
+/// Runs script commands against a renderer and the scene it draws.
+pub struct Interpreter {
+    /// Style structures are loaded with.
+    pub style: Style,
+    pub selection: Selection,
+    /// Relative paths in commands are resolved against this directory.
+    pub base_dir: PathBuf,
+    pub quit_requested: bool,
+}
+
+impl Interpreter {
+    pub fn new(style: Style) -> Interpreter {
+        Interpreter {
+            style,
+            selection: Selection::All,
+            base_dir: PathBuf::from("."),
+            quit_requested: false,
+        }
+    }
+
+    pub fn execute(
+        &mut self,
+        renderer: &mut Renderer,
+        command: ScriptCommand,
+    ) -> Result<(), String> {
+        match command {
+            ScriptCommand::Load(path) => {
+                let path = self.base_dir.join(path);
+                renderer.scene = Scene::open(&path.to_string_lossy(), &self.style)?;
+                self.selection = Selection::All;
+                self.rebuild_panels(renderer);
+            }
+            ScriptCommand::Select(selection) => {
+                self.selection = selection;
+                self.highlight_selection(renderer);
+            }
+            ScriptCommand::Color(scheme) => {
+                let mask = renderer.scene.select(&self.selection);
+                renderer.scene.color(&mask, scheme);
+            }
+            ScriptCommand::Show(representation) => {
+                let mask = renderer.scene.select(&self.selection);
+                renderer.scene.show(&mask, representation);
+            }
+            ScriptCommand::Hide(representation) => {
+                let mask = renderer.scene.select(&self.selection);
+                renderer.scene.hide(&mask, representation);
+            }
+            ScriptCommand::Label(labeled) => {
+                let mask = renderer.scene.select(&self.selection);
+                renderer.scene.label(&mask, labeled);
+            }
+            ScriptCommand::Orient(turn, tilt) => {
+                renderer.x_rotate = Some(turn.to_radians());
+                renderer.y_rotate = Some(tilt.to_radians());
+            }
+            ScriptCommand::Rotate(turn, tilt) => {
+                renderer.rotate(turn.to_radians(), tilt.to_radians());
+            }
+            ScriptCommand::Zoom(factor) => renderer.scale *= factor,
+            ScriptCommand::Background(color) => renderer.settings.background = color,
+            ScriptCommand::Png(path) => {
+                let path = self.base_dir.join(path);
+                renderer
+                    .save_png(&path)
+                    .map_err(|error| format!("Failed to save {}: {}", path.display(), error))?;
+                println!("Saved {}", path.display());
+            }
+            ScriptCommand::Export(path) => {
+                let path = self.base_dir.join(path);
+                let extension = path
+                    .extension()
+                    .map(|extension| extension.to_string_lossy().to_ascii_lowercase());
+                match extension.as_deref() {
+                    Some("gltf" | "glb") => gltf::save(&renderer.scene, &path)?,
+                    Some("obj" | "ply" | "stl") => mesh::save(&renderer.scene, &path)?,
+                    Some("svg") => svg::save(
+                        &renderer.scene,
+                        &Camera::of(renderer),
+                        (renderer.width as u32, renderer.height as u32),
+                        renderer.settings.background,
+                        &path,
+                    )?,
+                    _ => {
+                        return Err(format!(
+                            "Cannot export {}, expected .gltf, .glb, .obj, .ply, .stl or .svg",
+                            path.display()
+                        ))
+                    }
+                }
+                println!("Exported {}", path.display());
+            }
+            ScriptCommand::Write(path, transformed) => {
+                let path = self.base_dir.join(path);
+                let structure = renderer
+                    .scene
+                    .structure
+                    .as_ref()
+                    .ok_or_else(|| "No structure is loaded".to_string())?;
+                let mask = structure.select(&self.selection);
+                coordinates::save(structure, &mask, transformed, &path)?;
+                println!("Saved {}", path.display());
+            }
+            ScriptCommand::Save(path) => {
+                let path = self.base_dir.join(path);
+                Session::capture(self, renderer).save(&path)?;
+                println!("Saved {}", path.display());
+            }
+            ScriptCommand::Restore(path) => {
+                Session::load(&self.base_dir.join(path))?.restore(self, renderer)?;
+            }
+            ScriptCommand::HydrogenBonds(table) => {
+                self.hydrogen_bonds(renderer, table)?;
+            }
+            ScriptCommand::Interactions(report) => {
+                self.interactions(renderer, report)?;
+            }
+            ScriptCommand::Interface(cutoff) => {
+                self.interfaces(renderer, cutoff)?;
+            }
+            ScriptCommand::SurfaceArea(method, table) => {
+                self.surface_areas(renderer, method, table)?;
+            }
+            ScriptCommand::Clashes(tolerance, torsions, report) => {
+                self.clashes(renderer, tolerance, torsions, report)?;
+            }
+            ScriptCommand::ClearContacts(kinds) => {
+                for kind in kinds {
+                    renderer.scene.set_contacts(kind, Vec::new());
+                }
+            }
+            ScriptCommand::Superpose(file, fit, numbered) => {
+                self.superpose(renderer, file, fit, numbered)?;
+            }
+            ScriptCommand::Align(file, mode, fit, export) => {
+                self.align(renderer, file, mode, fit, export)?;
+            }
+            ScriptCommand::Center => {
+                let mask = renderer.scene.select(&self.selection);
+                renderer.scene.center_on(&mask);
+            }
+            ScriptCommand::Ramachandran(image) => {
+                self.ramachandran(renderer, image)?;
+            }
+            ScriptCommand::HideRamachandran => renderer.plot = None,
+            ScriptCommand::Sequence => {
+                self.sequences(renderer)?;
+            }
+            ScriptCommand::HideSequence => {
+                renderer.sequences = None;
+                renderer.scene.highlight(&[]);
+            }
+            ScriptCommand::Quit => self.quit_requested = true,
+        }
+
+        Ok(())
+    }
+
+    /// Finds and draws the hydrogen bonds of the selection, writing them to `table` if given.
+    pub fn hydrogen_bonds(
+        &self,
+        renderer: &mut Renderer,
+        table: Option<PathBuf>,
+    ) -> Result<Vec<hbond::HydrogenBond>, String> {
+        let structure = renderer
+            .scene
+            .structure
+            .as_ref()
+            .ok_or_else(|| "No structure is loaded".to_string())?;
+        let mask = structure.select(&self.selection);
+        let bonds = hbond::find(structure, &mask);
+        let contacts = hbond::contacts(structure, &bonds);
+        println!("Found {} hydrogen bonds", bonds.len());
+
+        if let Some(path) = table {
+            let path = self.base_dir.join(path);
+            fs::write(&path, hbond::table(structure, &bonds))
+                .map_err(|error| format!("Failed to save {}: {}", path.display(), error))?;
+            println!("Saved {}", path.display());
+        }
+
+        renderer
+            .scene
+            .set_contacts(ContactKind::HydrogenBond, contacts);
+        Ok(bonds)
+    }
+
+    /// Finds and draws the non-covalent interactions of the selection, writing a report to
+    /// `report` if given, as JSON when it ends in `.json`.
+    pub fn interactions(
+        &self,
+        renderer: &mut Renderer,
+        report: Option<PathBuf>,
+    ) -> Result<Vec<Interaction>, String> {
+        let structure = renderer
+            .scene
+            .structure
+            .as_ref()
+            .ok_or_else(|| "No structure is loaded".to_string())?;
+        let mask = structure.select(&self.selection);
+        let interactions = interaction::find(structure, &mask);
+        let contacts = interaction::contacts(&interactions);
+        for kind in interaction::KINDS {
+            let count = contacts
+                .iter()
+                .filter(|contact| contact.kind == kind)
+                .count();
+            println!("Found {} {} contacts", count, kind.name());
+        }
+
+        if let Some(path) = report {
+            let path = self.base_dir.join(path);
+            let report = if path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                interaction::to_json(structure, &interactions).to_string()
+            } else {
+                interaction::report(structure, &interactions)
+            };
+            fs::write(&path, report)
+                .map_err(|error| format!("Failed to save {}: {}", path.display(), error))?;
+            println!("Saved {}", path.display());
+        }
+
+        for kind in interaction::KINDS {
+            let contacts = contacts
+                .iter()
+                .filter(|contact| contact.kind == kind)
+                .cloned()
+                .collect();
+            renderer.scene.set_contacts(kind, contacts);
+        }
+        Ok(interactions)
+    }
+
+    /// Finds the interfaces between the selected chains, printing a summary, showing their
+    /// residues as sticks and making them the current selection.
+    pub fn interfaces(
+        &mut self,
+        renderer: &mut Renderer,
+        cutoff: f32,
+    ) -> Result<Vec<Interface>, String> {
+        let structure = renderer
+            .scene
+            .structure
+            .as_ref()
+            .ok_or_else(|| "No structure is loaded".to_string())?;
+        let mask = structure.select(&self.selection);
+        let interfaces = interface::find(structure, &mask, cutoff);
+        if interfaces.is_empty() {
+            println!("No chains touch within {} Å", cutoff);
+            return Ok(interfaces);
+        }
+        print!("{}", interface::report(structure, &interfaces));
+
+        let mut highlight = vec![false; structure.atoms.len()];
+        for interface in &interfaces {
+            for (hit, inside) in highlight.iter_mut().zip(interface.mask(structure)) {
+                *hit |= inside;
+            }
+        }
+        self.selection = interfaces
+            .iter()
+            .map(|interface| interface.selection(structure))
+            .reduce(|all, next| Selection::Or(Box::new(all), Box::new(next)))
+            .unwrap_or(Selection::All);
+        renderer
+            .scene
+            .show(&highlight, Representation::BallAndStick);
+        self.highlight_selection(renderer);
+        Ok(interfaces)
+    }
+
+    /// Solvent accessible area of every atom, printing the totals of the selected chains and
+    /// writing the areas of the selection to `table` if given.
+    pub fn surface_areas(
+        &self,
+        renderer: &Renderer,
+        method: sasa::Method,
+        table: Option<PathBuf>,
+    ) -> Result<Vec<f32>, String> {
+        let structure = renderer
+            .scene
+            .structure
+            .as_ref()
+            .ok_or_else(|| "No structure is loaded".to_string())?;
+        let mask = structure.select(&self.selection);
+        let areas = sasa::atom_areas(structure, method);
+
+        let selected = areas
+            .iter()
+            .zip(&mask)
+            .filter(|(_, &hit)| hit)
+            .map(|(area, _)| area)
+            .sum::<f32>();
+        for (chain, area) in sasa::chain_areas(structure, &areas) {
+            println!("Chain {}: {:.1} Å²", chain, area);
+        }
+        println!("Selection: {:.1} Å²", selected);
+
+        if let Some(path) = table {
+            let path = self.base_dir.join(path);
+            fs::write(&path, sasa::csv(structure, &areas, &mask))
+                .map_err(|error| format!("Failed to save {}: {}", path.display(), error))?;
+            println!("Saved {}", path.display());
+        }
+        Ok(areas)
+    }
+
+    /// Finds and marks the clashes of the selection, printing the clash score and writing a
+    /// report to `report` if given, as JSON when it ends in `.json`.
+    pub fn clashes(
+        &self,
+        renderer: &mut Renderer,
+        tolerance: f32,
+        torsions: bool,
+        report: Option<PathBuf>,
+    ) -> Result<(Vec<Clash>, usize), String> {
+        let structure = renderer
+            .scene
+            .structure
+            .as_ref()
+            .ok_or_else(|| "No structure is loaded".to_string())?;
+        let mask = structure.select(&self.selection);
+        let checked = mask.iter().filter(|&&hit| hit).count();
+        let clashes = clash::find(structure, &mask, tolerance, torsions);
+        println!(
+            "Found {} clashes among {} atoms, clash score {:.1}",
+            clashes.len(),
+            checked,
+            clash::score(&clashes, checked)
+        );
+
+        if let Some(path) = report {
+            let path = self.base_dir.join(path);
+            let report = if path
+                .extension()
+                .is_some_and(|extension| extension == "json")
+            {
+                clash::to_json(structure, &clashes, checked).to_string()
+            } else {
+                clash::report(structure, &clashes, checked)
+            };
+            fs::write(&path, report)
+                .map_err(|error| format!("Failed to save {}: {}", path.display(), error))?;
+            println!("Saved {}", path.display());
+        }
+
+        let contacts = clash::contacts(structure, &clashes);
+        renderer.scene.set_contacts(ContactKind::Clash, contacts);
+        Ok((clashes, checked))
+    }
+
+    /// Fits the second structure, loaded from `file` if given, onto the first over the `fit`
+    /// atoms of the selection matched between them, and colors the two apart. Residues are
+    /// matched through a global alignment of the chains' sequences, so that differently
+    /// numbered homologues fit, or by their numbering alone when `numbered`.
+    pub fn superpose(
+        &self,
+        renderer: &mut Renderer,
+        file: Option<PathBuf>,
+        fit: Fit,
+        numbered: bool,
+    ) -> Result<Superposition, String> {
+        if let Some(file) = file {
+            let path = self.base_dir.join(file);
+            renderer.scene.mobile = Some(Structure::open(&path.to_string_lossy(), &self.style)?);
+        }
+        let scene = &mut renderer.scene;
+        let reference = scene
+            .structure
+            .as_ref()
+            .ok_or_else(|| "No structure is loaded".to_string())?;
+        let mobile = scene.mobile.as_mut().ok_or_else(|| {
+            "superpose needs a file to load the second structure from".to_string()
+        })?;
+
+        let pairs = if numbered {
+            superpose::match_atoms(reference, mobile, &self.selection, fit)
+        } else {
+            let mask = reference.select(&self.selection);
+            let residues = align::align_chains(reference, mobile, &mask, Mode::Global)
+                .iter()
+                .flat_map(Alignment::residue_pairs)
+                .collect::<Vec<_>>();
+            superpose::match_residues(reference, mobile, &self.selection, fit, &residues)
+        };
+        let superposition = superpose::superpose(reference, mobile, pairs)?;
+        show_superposition(scene, &superposition);
+        Ok(superposition)
+    }
+
+    /// Aligns the sequences of the selected chains to those of the second structure, loading it
+    /// from `file` if given, and fits it onto the first over the aligned residues. The aligned
+    /// sequences replace the sequence bar and are written to `export` if given.
+    pub fn align(
+        &self,
+        renderer: &mut Renderer,
+        file: Option<PathBuf>,
+        mode: Mode,
+        fit: Fit,
+        export: Option<PathBuf>,
+    ) -> Result<(Vec<Alignment>, Superposition), String> {
+        if let Some(file) = file {
+            let path = self.base_dir.join(file);
+            renderer.scene.mobile = Some(Structure::open(&path.to_string_lossy(), &self.style)?);
+        }
+        let scene = &mut renderer.scene;
+        let reference = scene
+            .structure
+            .as_ref()
+            .ok_or_else(|| "No structure is loaded".to_string())?;
+        let mobile = scene
+            .mobile
+            .as_mut()
+            .ok_or_else(|| "align needs a file to load the second structure from".to_string())?;
+
+        let mask = reference.select(&self.selection);
+        let alignments = align::align_chains(reference, mobile, &mask, mode);
+        if alignments.is_empty() {
+            return Err("No chains of the selection align to the second structure".to_string());
+        }
+        println!("{}", align::summary(&alignments));
+
+        if let Some(path) = export {
+            let path = self.base_dir.join(path);
+            let names = [
+                align::name(reference, "reference"),
+                align::name(mobile, "mobile"),
+            ];
+            let entries = align::entries(&alignments, [&names[0], &names[1]]);
+            align::save(&entries, &path)?;
+            println!("Saved {}", path.display());
+        }
+
+        let residues = alignments
+            .iter()
+            .flat_map(Alignment::residue_pairs)
+            .collect::<Vec<_>>();
+        let pairs = superpose::match_residues(reference, mobile, &self.selection, fit, &residues);
+        let superposition = superpose::superpose(reference, mobile, pairs)?;
+        show_superposition(scene, &superposition);
+
+        renderer.sequences = Some(SequencePanel::aligned(&alignments));
+        self.highlight_selection(renderer);
+        Ok((alignments, superposition))
+    }
+
+    /// Shows the Ramachandran plot and prints how many residues fall in each region, writing
+    /// the plot to `image` if given.
+    pub fn ramachandran(
+        &self,
+        renderer: &mut Renderer,
+        image: Option<PathBuf>,
+    ) -> Result<Vec<Point>, String> {
+        let structure = renderer
+            .scene
+            .structure
+            .as_ref()
+            .ok_or_else(|| "No structure is loaded".to_string())?;
+        let plot = Plot::new(Some(structure));
+        println!("{}", ramachandran::summary(structure, &plot.points));
+
+        if let Some(path) = image {
+            let path = self.base_dir.join(path);
+            ramachandran::save(&plot.points, PLOT_IMAGE_SIZE, &path)?;
+            println!("Saved {}", path.display());
+        }
+
+        let points = plot.points.clone();
+        renderer.plot = Some(plot);
+        Ok(points)
+    }
+
+    /// Selects the residue of the plot's `index`th point, shows it as sticks, centers the view
+    /// on it and rings it in the plot.
+    pub fn focus(&mut self, renderer: &mut Renderer, index: usize) {
+        let (Some(structure), Some(plot)) = (&renderer.scene.structure, &mut renderer.plot) else {
+            return;
+        };
+        let Some(point) = plot.points.get(index) else {
+            return;
+        };
+        let atom = &structure.atoms[point.atom];
+        println!(
+            "{} phi {:.0} psi {:.0}",
+            atom.describe(),
+            point.phi,
+            point.psi
+        );
+
+        plot.highlight = Some(index);
+        plot.changed = true;
+        self.selection = residue_selection(atom);
+        let mask = renderer.scene.select(&self.selection);
+        renderer.scene.show(&mask, Representation::BallAndStick);
+        renderer.scene.center_on(&mask);
+        self.highlight_selection(renderer);
+    }
+
+    /// Selects the residue of the atom clicked in the view.
+    pub fn pick(&mut self, renderer: &mut Renderer, index: usize) {
+        let Some(structure) = &renderer.scene.structure else {
+            return;
+        };
+        let atom = &structure.atoms[index];
+        println!("Picked {} {}", atom.describe(), atom.name);
+
+        self.selection = residue_selection(atom);
+        self.highlight_selection(renderer);
+    }
+
+    /// Shows the sequence bar and prints the sequence of every chain.
+    pub fn sequences(&self, renderer: &mut Renderer) -> Result<Vec<Sequence>, String> {
+        let structure = renderer
+            .scene
+            .structure
+            .as_ref()
+            .ok_or_else(|| "No structure is loaded".to_string())?;
+        let panel = SequencePanel::new(Some(structure));
+        println!("{}", sequence::summary(&panel.sequences));
+
+        let sequences = panel.sequences.clone();
+        renderer.sequences = Some(panel);
+        self.highlight_selection(renderer);
+        Ok(sequences)
+    }
+
+    /// Selects the residues in the `range` of columns of the bar's `index`th row, as when
+    /// dragging over them.
+    pub fn select_sequence(
+        &mut self,
+        renderer: &mut Renderer,
+        index: usize,
+        range: RangeInclusive<usize>,
+    ) {
+        let Some(panel) = renderer
+            .sequences
+            .as_ref()
+            .filter(|panel| index < panel.sequences.len())
+        else {
+            return;
+        };
+        self.selection = panel.selection(index, range);
+        println!("select {}", self.selection);
+        self.highlight_selection(renderer);
+    }
+
+    /// Rebuilds the open Ramachandran plot and sequence bar for a newly loaded structure, as
+    /// both keep indices into its atoms.
+    pub fn rebuild_panels(&self, renderer: &mut Renderer) {
+        if renderer.plot.is_some() {
+            renderer.plot = Some(Plot::new(renderer.scene.structure.as_ref()));
+        }
+        if renderer.sequences.is_some() {
+            renderer.sequences = Some(SequencePanel::new(renderer.scene.structure.as_ref()));
+        }
+        self.highlight_selection(renderer);
+    }
+
+    /// Highlights the selection in the 3D view, and in the sequence bar while the bar is shown.
+    /// Selecting everything highlights nothing.
+    pub fn highlight_selection(&self, renderer: &mut Renderer) {
+        let Some(structure) = &renderer.scene.structure else {
+            return;
+        };
+        let mask = match self.selection {
+            Selection::All => vec![false; structure.atoms.len()],
+            _ => structure.select(&self.selection),
+        };
+        if let Some(panel) = &mut renderer.sequences {
+            panel.select(structure, &mask);
+        }
+        renderer.scene.highlight(&mask);
+    }
+
+    pub fn run_line(&mut self, renderer: &mut Renderer, line: &str) -> Result<(), String> {
+        match ScriptCommand::parse(line)? {
+            Some(command) => self.execute(renderer, command),
+            None => Ok(()),
+        }
+    }
+
+    /// Runs every line of a script, stopping at the first failing one. Paths inside the script
+    /// are relative to the script's directory.
+    pub fn run_file(&mut self, renderer: &mut Renderer, path: &Path) -> Result<(), String> {
+        let source = fs::read_to_string(path)
+            .map_err(|error| format!("Failed to read {}: {}", path.display(), error))?;
+
+        let base_dir = std::mem::replace(
+            &mut self.base_dir,
+            path.parent().map(Path::to_path_buf).unwrap_or_default(),
+        );
+
+        let mut result = Ok(());
+        for (number, line) in source.lines().enumerate() {
+            result = self
+                .run_line(renderer, line)
+                .map_err(|error| format!("{}:{}: {}", path.display(), number + 1, error));
+            if result.is_err() || self.quit_requested {
+                break;
+            }
+        }
+
+        self.base_dir = base_dir;
+        result
+    }
+}
+
+/// Single line command prompt typed into the viewer window and echoed in its title.
+#[derive(Debug, Default)]
+pub struct Console {
+    input: Option<String>,
+}
+
+impl Console {
+    pub fn is_open(&self) -> bool {
+        self.input.is_some()
+    }
+
+    pub fn open(&mut self) {
+        self.input = Some(String::new());
+    }
+
+    pub fn close(&mut self) {
+        self.input = None;
+    }
+
+    pub fn push(&mut self, character: char) {
+        if let Some(input) = &mut self.input {
+            input.push(character);
+        }
+    }
+
+    pub fn backspace(&mut self) {
+        if let Some(input) = &mut self.input {
+            input.pop();
+        }
+    }
+
+    /// Closes the console, returning the typed line.
+    pub fn submit(&mut self) -> Option<String> {
+        self.input.take()
+    }
+
+    pub fn title(&self) -> String {
+        match &self.input {
+            Some(input) => format!("Biopix :{}_", input),
+            None => "Biopix".to_string(),
+        }
+    }
+}
+
+/// The residue `atom` belongs to.
+fn residue_selection(atom: &SceneAtom) -> Selection {
+    Selection::And(
+        Box::new(Selection::Chain(vec![atom.chain_id.clone()])),
+        Box::new(Selection::ResidueRange(vec![(
+            atom.residue_number,
+            atom.residue_number,
+        )])),
+    )
+}
+
+#[test]
+fn parses_script_lines() {
+    let script = "# figure 2\nload 1d66.pdb\nselect chain A and resi 10-20\ncolor #ff8000\n\
+        show cartoon\nhide\norient 90 -30\npng figure.png\n";
+    let commands = script
+        .lines()
+        .filter_map(|line| ScriptCommand::parse(line).unwrap())
+        .collect::<Vec<_>>();
+
+    assert_eq!(commands.len(), 7);
+    assert_eq!(commands[0], ScriptCommand::Load(PathBuf::from("1d66.pdb")));
+    assert_eq!(
+        commands[2],
+        ScriptCommand::Color(ColorScheme::Uniform([1.0, 128.0 / 255.0, 0.0]))
+    );
+    assert_eq!(commands[4], ScriptCommand::Hide(None));
+    assert_eq!(commands[5], ScriptCommand::Orient(90.0, -30.0));
+    assert_eq!(
+        ScriptCommand::parse("save views/figure.bps"),
+        Ok(Some(ScriptCommand::Save(PathBuf::from("views/figure.bps"))))
+    );
+    assert!(ScriptCommand::parse("zoom -1").is_err());
+    assert!(ScriptCommand::parse("spin 10").is_err());
+}
@@ -0,0 +1,309 @@
+use std::fmt;
+use std::str::FromStr;
+
+use pdbtbx::{Atom, Chain, Residue};
+
+/// Atom filter parsed from a small query language, e.g. `chain A and resi 10-50`,
+/// `not (hetero or water)` or `within 5 of resn HEM`. Terms are combined with `and`, `or`,
+/// `not` and parentheses; lists of values are comma separated.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Selection {
+    All,
+    Chain(Vec<String>),
+    /// Inclusive residue serial number ranges.
+    ResidueRange(Vec<(isize, isize)>),
+    ResidueName(Vec<String>),
+    AtomName(Vec<String>),
+    Element(Vec<String>),
+    Hetero,
+    Backbone,
+    Protein,
+    Water,
+    /// Atoms within a distance in Å of any atom of the inner selection, including those atoms.
+    Within(f32, Box<Selection>),
+    Not(Box<Selection>),
+    And(Box<Selection>, Box<Selection>),
+    Or(Box<Selection>, Box<Selection>),
+}
+
+impl Selection {
+    /// True when the selection has a `within` term, which depends on the positions of other
+    /// atoms and so cannot be decided by `matches` alone.
+    pub fn is_spatial(&self) -> bool {
+        match self {
+            Selection::Within(..) => true,
+            Selection::Not(inner) => inner.is_spatial(),
+            Selection::And(left, right) | Selection::Or(left, right) => {
+                left.is_spatial() || right.is_spatial()
+            }
+            _ => false,
+        }
+    }
+
+    /// Whether one atom matches. `within` terms never match here, `Structure::select` evaluates
+    /// them over the whole structure.
+    pub fn matches(&self, chain: &Chain, residue: &Residue, atom: &Atom) -> bool {
+        let any = |values: &[String], value: &str| {
+            values
+                .iter()
+                .any(|candidate| candidate.eq_ignore_ascii_case(value.trim()))
+        };
+
+        match self {
+            Selection::All => true,
+            Selection::Chain(ids) => any(ids, chain.id()),
+            Selection::ResidueRange(ranges) => {
+                let serial = residue.serial_number();
+                ranges
+                    .iter()
+                    .any(|(start, end)| (*start..=*end).contains(&serial))
+            }
+            Selection::ResidueName(names) => residue.name().is_some_and(|name| any(names, name)),
+            Selection::AtomName(names) => any(names, atom.name()),
+            Selection::Element(symbols) => atom
+                .element()
+                .is_some_and(|element| any(symbols, element.symbol())),
+            Selection::Hetero => atom.hetero(),
+            Selection::Backbone => atom.is_backbone(),
+            Selection::Protein => residue
+                .conformers()
+                .next()
+                .is_some_and(|conformer| conformer.is_amino_acid()),
+            Selection::Water => residue
+                .name()
+                .is_some_and(|name| matches!(name, "HOH" | "WAT" | "H2O" | "DOD")),
+            Selection::Within(..) => false,
+            Selection::Not(inner) => !inner.matches(chain, residue, atom),
+            Selection::And(left, right) => {
+                left.matches(chain, residue, atom) && right.matches(chain, residue, atom)
+            }
+            Selection::Or(left, right) => {
+                left.matches(chain, residue, atom) || right.matches(chain, residue, atom)
+            }
+        }
+    }
+}
+
+impl FromStr for Selection {
+    type Err = String;
+
+    fn from_str(query: &str) -> Result<Self, Self::Err> {
+        let spaced = query.replace('(', " ( ").replace(')', " ) ");
+        let tokens = spaced.split_whitespace().collect::<Vec<_>>();
+
+        let mut parser = Parser {
+            tokens,
+            position: 0,
+        };
+        let selection = parser.or()?;
+
+        match parser.peek() {
+            None => Ok(selection),
+            Some(token) => Err(format!("Unexpected '{}' in selection '{}'", token, query)),
+        }
+    }
+}
+
+/// Writes the query back in the language `from_str` parses.
+impl fmt::Display for Selection {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Selection::All => write!(f, "all"),
+            Selection::Chain(ids) => write!(f, "chain {}", ids.join(",")),
+            Selection::ResidueRange(ranges) => {
+                let ranges = ranges
+                    .iter()
+                    .map(|(start, end)| {
+                        if start == end {
+                            start.to_string()
+                        } else {
+                            format!("{}-{}", start, end)
+                        }
+                    })
+                    .collect::<Vec<_>>();
+                write!(f, "resi {}", ranges.join(","))
+            }
+            Selection::ResidueName(names) => write!(f, "resn {}", names.join(",")),
+            Selection::AtomName(names) => write!(f, "name {}", names.join(",")),
+            Selection::Element(symbols) => write!(f, "element {}", symbols.join(",")),
+            Selection::Hetero => write!(f, "hetero"),
+            Selection::Backbone => write!(f, "backbone"),
+            Selection::Protein => write!(f, "protein"),
+            Selection::Water => write!(f, "water"),
+            Selection::Within(distance, inner) => write!(f, "within {} of ({})", distance, inner),
+            Selection::Not(inner) => write!(f, "not ({})", inner),
+            Selection::And(left, right) => write!(f, "({}) and ({})", left, right),
+            Selection::Or(left, right) => write!(f, "({}) or ({})", left, right),
+        }
+    }
+}
+
+/// Recursive descent over the whitespace separated tokens, `not` binding tighter than `and`,
+/// and `and` tighter than `or`.
+struct Parser<'a> {
+    tokens: Vec<&'a str>,
+    position: usize,
+}
+
+impl<'a> Parser<'a> {
+    fn peek(&self) -> Option<&'a str> {
+        self.tokens.get(self.position).copied()
+    }
+
+    fn next(&mut self) -> Option<&'a str> {
+        let token = self.peek();
+        self.position += 1;
+        token
+    }
+
+    fn next_is(&mut self, keyword: &str) -> bool {
+        if self
+            .peek()
+            .is_some_and(|token| token.eq_ignore_ascii_case(keyword))
+        {
+            self.position += 1;
+            return true;
+        }
+
+        false
+    }
+
+    fn or(&mut self) -> Result<Selection, String> {
+        let mut selection = self.and()?;
+        while self.next_is("or") {
+            selection = Selection::Or(Box::new(selection), Box::new(self.and()?));
+        }
+
+        Ok(selection)
+    }
+
+    fn and(&mut self) -> Result<Selection, String> {
+        let mut selection = self.not()?;
+        while self.next_is("and") {
+            selection = Selection::And(Box::new(selection), Box::new(self.not()?));
+        }
+
+        Ok(selection)
+    }
+
+    fn not(&mut self) -> Result<Selection, String> {
+        if self.next_is("not") {
+            return Ok(Selection::Not(Box::new(self.not()?)));
+        }
+
+        if self.next_is("(") {
+            let selection = self.or()?;
+            if !self.next_is(")") {
+                return Err("Missing ')' in selection".to_string());
+            }
+            return Ok(selection);
+        }
+
+        self.term()
+    }
+
+    fn term(&mut self) -> Result<Selection, String> {
+        let keyword = self
+            .next()
+            .ok_or_else(|| "Selection ended early".to_string())?;
+
+        let selection = match keyword.to_ascii_lowercase().as_str() {
+            "all" | "*" => Selection::All,
+            "hetero" => Selection::Hetero,
+            "backbone" => Selection::Backbone,
+            "protein" => Selection::Protein,
+            "water" => Selection::Water,
+            "chain" => Selection::Chain(self.values(keyword)?),
+            "resn" => Selection::ResidueName(self.values(keyword)?),
+            "name" => Selection::AtomName(self.values(keyword)?),
+            "element" => Selection::Element(self.values(keyword)?),
+            "within" => {
+                let distance = self
+                    .next()
+                    .and_then(|value| value.parse::<f32>().ok())
+                    .filter(|distance| *distance >= 0.0)
+                    .ok_or_else(|| "Missing distance after 'within'".to_string())?;
+                if !self.next_is("of") {
+                    return Err("Expected 'of' after the distance in 'within'".to_string());
+                }
+                Selection::Within(distance, Box::new(self.not()?))
+            }
+            "resi" => Selection::ResidueRange(
+                self.values(keyword)?
+                    .iter()
+                    .map(|value| parse_range(value))
+                    .collect::<Result<_, _>>()?,
+            ),
+            _ => return Err(format!("Unknown selection keyword '{}'", keyword)),
+        };
+
+        Ok(selection)
+    }
+
+    fn values(&mut self, keyword: &str) -> Result<Vec<String>, String> {
+        match self.next() {
+            Some(list) if list != "(" && list != ")" => Ok(list
+                .split(',')
+                .filter(|value| !value.is_empty())
+                .map(str::to_string)
+                .collect()),
+            _ => Err(format!("Missing value after '{}'", keyword)),
+        }
+    }
+}
+
+/// Parses `12` or `10-50`, allowing negative serial numbers such as `-3--1`.
+fn parse_range(value: &str) -> Result<(isize, isize), String> {
+    let invalid = || format!("Invalid residue range '{}'", value);
+
+    let split = value
+        .char_indices()
+        .skip(1)
+        .find(|&(_, c)| c == '-')
+        .map(|(index, _)| index);
+
+    match split {
+        Some(index) => {
+            let start = value[..index].parse().map_err(|_| invalid())?;
+            let end = value[index + 1..].parse().map_err(|_| invalid())?;
+            Ok((start, end))
+        }
+        None => {
+            let serial = value.parse().map_err(|_| invalid())?;
+            Ok((serial, serial))
+        }
+    }
+}
+
+#[test]
+fn parses_nested_queries() {
+    let selection: Selection = "chain A,B and not (resi 10-20 or water)".parse().unwrap();
+
+    assert_eq!(
+        selection,
+        Selection::And(
+            Box::new(Selection::Chain(vec!["A".to_string(), "B".to_string()])),
+            Box::new(Selection::Not(Box::new(Selection::Or(
+                Box::new(Selection::ResidueRange(vec![(10, 20)])),
+                Box::new(Selection::Water),
+            )))),
+        )
+    );
+    assert_eq!(selection.to_string().parse(), Ok(selection));
+    assert_eq!(parse_range("-3--1"), Ok((-3, -1)));
+    let within: Selection = "within 4.5 of resn HEM and not water".parse().unwrap();
+    assert_eq!(
+        within,
+        Selection::And(
+            Box::new(Selection::Within(
+                4.5,
+                Box::new(Selection::ResidueName(vec!["HEM".to_string()]))
+            )),
+            Box::new(Selection::Not(Box::new(Selection::Water))),
+        )
+    );
+    assert_eq!(within.to_string().parse(), Ok(within));
+    assert!("within of chain A".parse::<Selection>().is_err());
+    assert!("chain".parse::<Selection>().is_err());
+    assert!("resi 1 resi 2".parse::<Selection>().is_err());
+}
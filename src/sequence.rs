@@ -0,0 +1,802 @@
+use std::collections::HashMap;
+use std::fs;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use crate::align::Alignment;
+use crate::font::{self, GLYPH_HEIGHT, GLYPH_WIDTH};
+use crate::json::Json;
+use crate::scene::Structure;
+use crate::selection::Selection;
+
+/// Screen pixels per font pixel.
+const FONT_SCALE: usize = 2;
+/// Width of one residue in the bar, in pixels.
+pub const CELL_WIDTH: f32 = ((GLYPH_WIDTH + 1) * FONT_SCALE) as f32;
+/// Height of one chain's row, in pixels.
+pub const ROW_HEIGHT: f32 = ((GLYPH_HEIGHT + 3) * FONT_SCALE) as f32;
+/// Room left of the residues for the chain identifier.
+const LABEL_WIDTH: f32 = 3.0 * CELL_WIDTH;
+const PADDING: f32 = 4.0;
+const MARGIN: f32 = 10.0;
+/// Most rows shown at once; further chains are reached by scrolling.
+const MAX_ROWS: usize = 6;
+
+const BACKGROUND: [u8; 3] = [250, 250, 250];
+const LABEL_COLOR: [u8; 3] = [90, 90, 90];
+const OBSERVED_COLOR: [u8; 3] = [20, 20, 20];
+const MISSING_COLOR: [u8; 3] = [190, 190, 190];
+const TICK_COLOR: [u8; 3] = [170, 170, 170];
+const SELECTED_COLOR: [u8; 3] = [255, 215, 0];
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Kind {
+    Protein,
+    NucleicAcid,
+}
+
+impl Kind {
+    pub fn name(self) -> &'static str {
+        match self {
+            Kind::Protein => "protein",
+            Kind::NucleicAcid => "nucleic acid",
+        }
+    }
+}
+
+/// One letter code of a residue name, `None` for anything that is not a polymer residue.
+/// Modified amino acids with a common parent take the parent's letter.
+pub fn one_letter(name: &str) -> Option<(char, Kind)> {
+    let protein = |code| Some((code, Kind::Protein));
+    let nucleic = |code| Some((code, Kind::NucleicAcid));
+
+    match name.trim().to_ascii_uppercase().as_str() {
+        "ALA" => protein('A'),
+        "ARG" => protein('R'),
+        "ASN" => protein('N'),
+        "ASP" => protein('D'),
+        "CYS" => protein('C'),
+        "GLN" => protein('Q'),
+        "GLU" => protein('E'),
+        "GLY" => protein('G'),
+        "HIS" => protein('H'),
+        "ILE" => protein('I'),
+        "LEU" => protein('L'),
+        "LYS" => protein('K'),
+        "MET" | "MSE" => protein('M'),
+        "PHE" => protein('F'),
+        "PRO" => protein('P'),
+        "SER" | "SEP" => protein('S'),
+        "THR" | "TPO" => protein('T'),
+        "TRP" => protein('W'),
+        "TYR" | "PTR" => protein('Y'),
+        "VAL" => protein('V'),
+        "SEC" => protein('U'),
+        "PYL" => protein('O'),
+        "ASX" => protein('B'),
+        "GLX" => protein('Z'),
+        "UNK" => protein('X'),
+        "A" | "DA" => nucleic('A'),
+        "C" | "DC" => nucleic('C'),
+        "G" | "DG" => nucleic('G'),
+        "T" | "DT" => nucleic('T'),
+        "U" | "DU" => nucleic('U'),
+        "I" | "DI" => nucleic('I'),
+        "N" | "DN" => nucleic('N'),
+        _ => None,
+    }
+}
+
+/// A residue of a chain's sequence, modelled or only listed in SEQRES or `_entity_poly_seq`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Monomer {
+    pub name: String,
+    pub code: char,
+    /// Residue serial number, `None` when the residue has no coordinates.
+    pub number: Option<isize>,
+    /// First atom of the residue in `Structure::atoms`, `None` when it has no coordinates.
+    pub atom: Option<usize>,
+}
+
+impl Monomer {
+    pub fn observed(&self) -> bool {
+        self.atom.is_some()
+    }
+}
+
+/// The polymer sequence of one chain.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Sequence {
+    pub chain_id: String,
+    pub kind: Kind,
+    pub monomers: Vec<Monomer>,
+    /// Whether the file lists the chain's full sequence. Otherwise only the modelled residues
+    /// are known and gaps cannot be told apart from the sequence's ends.
+    pub complete: bool,
+}
+
+impl Sequence {
+    /// Every residue's one letter code.
+    pub fn letters(&self) -> String {
+        self.monomers.iter().map(|monomer| monomer.code).collect()
+    }
+
+    /// Like `letters`, with `-` for residues without coordinates.
+    pub fn observed_letters(&self) -> String {
+        self.monomers
+            .iter()
+            .map(|monomer| {
+                if monomer.observed() {
+                    monomer.code
+                } else {
+                    '-'
+                }
+            })
+            .collect()
+    }
+
+    /// The modelled residues among the given monomers, by chain and residue number.
+    pub fn selection(&self, monomers: impl IntoIterator<Item = usize>) -> Selection {
+        let mut ranges: Vec<(isize, isize)> = Vec::new();
+        for number in monomers
+            .into_iter()
+            .filter_map(|monomer| self.monomers[monomer].number)
+        {
+            match ranges.last_mut() {
+                Some((_, end)) if *end + 1 == number => *end = number,
+                _ => ranges.push((number, number)),
+            }
+        }
+
+        Selection::And(
+            Box::new(Selection::Chain(vec![self.chain_id.clone()])),
+            Box::new(Selection::ResidueRange(ranges)),
+        )
+    }
+}
+
+/// Residue names of each chain's full sequence as listed in the file at `path`, from SEQRES
+/// records of PDB files or `_entity_poly_seq` of mmCIF files. `None` when the file lists none.
+pub fn read_seqres(path: &Path) -> Option<HashMap<String, Vec<String>>> {
+    let text = fs::read_to_string(path).ok()?;
+    let chains = if path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("cif"))
+    {
+        entity_poly_seq(&text)
+    } else {
+        pdb_seqres(&text)
+    };
+
+    (!chains.is_empty()).then_some(chains)
+}
+
+fn pdb_seqres(text: &str) -> HashMap<String, Vec<String>> {
+    let mut chains: HashMap<String, Vec<String>> = HashMap::new();
+    for line in text.lines().filter(|line| line.starts_with("SEQRES")) {
+        let (Some(chain), Some(names)) = (line.get(11..12), line.get(19..)) else {
+            continue;
+        };
+        chains
+            .entry(chain.trim().to_string())
+            .or_default()
+            .extend(names.split_whitespace().map(str::to_string));
+    }
+
+    chains
+}
+
+/// Sequences of `_entity_poly_seq` handed to the author chains `_entity_poly` assigns each
+/// entity. Of the alternatives listed for a microheterogeneous position the first is kept.
+fn entity_poly_seq(text: &str) -> HashMap<String, Vec<String>> {
+    let items = cif_items(text);
+    let column = |tag: &str| items.get(tag).map_or(&[][..], Vec::as_slice);
+
+    let mut entities: HashMap<&str, Vec<String>> = HashMap::new();
+    let mut previous = None;
+    for ((entity, number), name) in column("_entity_poly_seq.entity_id")
+        .iter()
+        .zip(column("_entity_poly_seq.num"))
+        .zip(column("_entity_poly_seq.mon_id"))
+    {
+        if previous == Some((entity, number)) {
+            continue;
+        }
+        previous = Some((entity, number));
+        entities.entry(entity).or_default().push(name.clone());
+    }
+
+    let mut chains = HashMap::new();
+    for (entity, strands) in column("_entity_poly.entity_id")
+        .iter()
+        .zip(column("_entity_poly.pdbx_strand_id"))
+    {
+        let Some(names) = entities.get(entity.as_str()) else {
+            continue;
+        };
+        for chain in strands.split(',') {
+            chains.insert(chain.trim().to_string(), names.clone());
+        }
+    }
+
+    chains
+}
+
+/// Values of every data item of an mmCIF file by tag, a single one for plain items and one per
+/// row for the columns of loops. Text fields count as one value.
+fn cif_items(text: &str) -> HashMap<String, Vec<String>> {
+    let mut tokens = Vec::new();
+    let mut lines = text.lines();
+    while let Some(line) = lines.next() {
+        if let Some(first) = line.strip_prefix(';') {
+            let mut field = first.to_string();
+            for line in lines.by_ref() {
+                if line.starts_with(';') {
+                    break;
+                }
+                field.push('\n');
+                field.push_str(line);
+            }
+            tokens.push((field, true));
+            continue;
+        }
+
+        let mut rest = line.trim_start();
+        while !rest.is_empty() && !rest.starts_with('#') {
+            let quote = rest.chars().next().filter(|c| *c == '\'' || *c == '"');
+            let (token, after) = match quote {
+                // A quote only closes when followed by whitespace or the end of the line
+                Some(quote) => {
+                    let end = rest
+                        .char_indices()
+                        .skip(1)
+                        .find(|&(i, c)| {
+                            c == quote
+                                && rest[i + 1..].chars().next().is_none_or(char::is_whitespace)
+                        })
+                        .map_or(rest.len(), |(i, _)| i);
+                    (&rest[1..end], rest.get(end + 1..).unwrap_or(""))
+                }
+                None => rest.split_at(rest.find(char::is_whitespace).unwrap_or(rest.len())),
+            };
+            tokens.push((token.to_string(), quote.is_some()));
+            rest = after.trim_start();
+        }
+    }
+
+    let mut items: HashMap<String, Vec<String>> = HashMap::new();
+    let mut tokens = tokens.into_iter().peekable();
+    let is_tag = |(token, quoted): &(String, bool)| !quoted && token.starts_with('_');
+    while let Some((token, quoted)) = tokens.next() {
+        if quoted {
+            continue;
+        }
+        if token.eq_ignore_ascii_case("loop_") {
+            let mut tags = Vec::new();
+            while let Some(tag) = tokens.next_if(is_tag) {
+                tags.push(tag.0);
+            }
+            let mut values = Vec::new();
+            while let Some(value) = tokens.next_if(|token| {
+                !is_tag(token)
+                    && (token.1
+                        || !(token.0.eq_ignore_ascii_case("loop_") || token.0.starts_with("data_")))
+            }) {
+                values.push(value.0);
+            }
+            for (column, tag) in tags.iter().enumerate() {
+                let column = values.iter().skip(column).step_by(tags.len()).cloned();
+                items.entry(tag.clone()).or_default().extend(column);
+            }
+        } else if token.starts_with('_') {
+            if let Some(value) = tokens.next_if(|token| !is_tag(token)) {
+                items.insert(token, vec![value.0]);
+            }
+        }
+    }
+
+    items
+}
+
+/// Sequences of the polymer chains of `structure`, in chain order. Where the file lists a
+/// chain's full sequence, residues without coordinates are filled in between the modelled ones.
+pub fn sequences(structure: &Structure) -> Vec<Sequence> {
+    let mut sequences: Vec<Sequence> = Vec::new();
+
+    for (index, atom) in structure.atoms.iter().enumerate() {
+        if index > 0 && structure.atoms[index - 1].residue == atom.residue {
+            continue;
+        }
+        let Some((code, kind)) = one_letter(&atom.residue_name) else {
+            continue;
+        };
+        let monomer = Monomer {
+            name: atom.residue_name.clone(),
+            code,
+            number: Some(atom.residue_number),
+            atom: Some(index),
+        };
+
+        match sequences.last_mut() {
+            Some(sequence) if sequence.chain_id == atom.chain_id => sequence.monomers.push(monomer),
+            _ => sequences.push(Sequence {
+                chain_id: atom.chain_id.clone(),
+                kind,
+                monomers: vec![monomer],
+                complete: false,
+            }),
+        }
+    }
+
+    for sequence in sequences.iter_mut() {
+        let names = structure
+            .seqres
+            .as_ref()
+            .and_then(|chains| chains.get(&sequence.chain_id));
+        if let Some(names) = names {
+            let observed = std::mem::take(&mut sequence.monomers);
+            sequence.monomers = merge(names, observed);
+            sequence.complete = true;
+        }
+    }
+
+    sequences
+}
+
+/// Places the modelled residues along the SEQRES names, filling the rest in as missing. A jump
+/// in residue numbering is taken as that many missing residues when the names agree, otherwise
+/// each modelled residue takes the next SEQRES entry of the same name.
+fn merge(names: &[String], observed: Vec<Monomer>) -> Vec<Monomer> {
+    let mut monomers = Vec::new();
+    let mut next = 0;
+    let missing = |monomers: &mut Vec<Monomer>, range: std::ops::Range<usize>| {
+        monomers.extend(names[range].iter().map(|name| Monomer {
+            name: name.clone(),
+            code: one_letter(name).map_or('X', |(code, _)| code),
+            number: None,
+            atom: None,
+        }));
+    };
+
+    let mut previous: Option<isize> = None;
+    for monomer in observed {
+        if let (Some(previous), Some(number)) = (previous, monomer.number) {
+            let skip = (number - previous - 1).max(0) as usize;
+            if names.get(next + skip) == Some(&monomer.name) {
+                missing(&mut monomers, next..next + skip);
+                next += skip;
+            }
+        }
+        if let Some(offset) = names[next..].iter().position(|name| *name == monomer.name) {
+            missing(&mut monomers, next..next + offset);
+            next += offset + 1;
+        }
+
+        previous = monomer.number;
+        monomers.push(monomer);
+    }
+    missing(&mut monomers, next..names.len());
+
+    monomers
+}
+
+/// FASTA-like listing with `-` for residues without coordinates.
+pub fn summary(sequences: &[Sequence]) -> String {
+    sequences
+        .iter()
+        .map(|sequence| {
+            let observed = sequence.monomers.iter().filter(|m| m.observed()).count();
+            let residues = match sequence.complete {
+                true => format!(
+                    "{} residues, {} modelled",
+                    sequence.monomers.len(),
+                    observed
+                ),
+                false => format!("{} modelled residues, full sequence not listed", observed),
+            };
+            format!(
+                ">{} {}, {}\n{}",
+                sequence.chain_id,
+                sequence.kind.name(),
+                residues,
+                sequence.observed_letters()
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+pub fn to_json(sequences: &[Sequence]) -> Json {
+    Json::Array(
+        sequences
+            .iter()
+            .map(|sequence| {
+                Json::object([
+                    ("chain", Json::from(sequence.chain_id.as_str())),
+                    ("kind", Json::from(sequence.kind.name())),
+                    ("sequence", Json::from(sequence.letters().as_str())),
+                    ("observed", Json::from(sequence.observed_letters().as_str())),
+                    ("complete", Json::Bool(sequence.complete)),
+                ])
+            })
+            .collect(),
+    )
+}
+
+/// Where the sequence bar sits in the window, in pixels from the top left corner.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Bar {
+    pub x: f32,
+    pub y: f32,
+    pub width: f32,
+    pub height: f32,
+}
+
+impl Bar {
+    /// Along the top of a `width` wide window, one row per chain up to `MAX_ROWS`.
+    pub fn place(width: f32, rows: usize) -> Bar {
+        Bar {
+            x: MARGIN,
+            y: MARGIN,
+            width: (width - 2.0 * MARGIN).floor().max(1.0),
+            height: rows.clamp(1, MAX_ROWS) as f32 * ROW_HEIGHT + 2.0 * PADDING,
+        }
+    }
+
+    pub fn contains(&self, [x, y]: [f32; 2]) -> bool {
+        (self.x..self.x + self.width).contains(&x) && (self.y..self.y + self.height).contains(&y)
+    }
+}
+
+/// The sequence bar shown above the 3D view. Each row lays a sequence out in columns, one
+/// monomer or alignment gap per column, so aligned sequences line up.
+#[derive(Debug, Clone, PartialEq)]
+pub struct SequencePanel {
+    pub sequences: Vec<Sequence>,
+    /// The monomer of each row's sequence in each column, `None` for an alignment gap.
+    pub columns: Vec<Vec<Option<usize>>>,
+    /// Row whose residues each row picks and highlights. Rows of a second structure follow
+    /// the row they are aligned to, since selections act on the loaded structure.
+    pub links: Vec<usize>,
+    /// Whether each column of each row belongs to the current selection.
+    pub selected: Vec<Vec<bool>>,
+    /// How far the residues are scrolled left in pixels, and the first row shown.
+    pub scroll: f32,
+    pub first_row: usize,
+    /// Set when the image needs drawing again.
+    pub changed: bool,
+}
+
+impl SequencePanel {
+    pub fn new(structure: Option<&Structure>) -> SequencePanel {
+        let sequences = structure.map(sequences).unwrap_or_default();
+        let columns = sequences
+            .iter()
+            .map(|sequence| (0..sequence.monomers.len()).map(Some).collect())
+            .collect();
+        let links = (0..sequences.len()).collect();
+        SequencePanel::with_columns(sequences, columns, links)
+    }
+
+    /// Each alignment's two sequences in adjacent rows, the second structure's row picking
+    /// through the first's.
+    pub fn aligned(alignments: &[Alignment]) -> SequencePanel {
+        let mut sequences = Vec::new();
+        let mut columns = Vec::new();
+        let mut links = Vec::new();
+        for alignment in alignments {
+            links.extend([sequences.len(); 2]);
+            sequences.extend([alignment.first.clone(), alignment.second.clone()]);
+            columns.push(alignment.columns.iter().map(|&(first, _)| first).collect());
+            columns.push(
+                alignment
+                    .columns
+                    .iter()
+                    .map(|&(_, second)| second)
+                    .collect(),
+            );
+        }
+        SequencePanel::with_columns(sequences, columns, links)
+    }
+
+    /// Rows of `sequences` laid out in `columns`, picking through the rows in `links`.
+    pub fn with_columns(
+        sequences: Vec<Sequence>,
+        columns: Vec<Vec<Option<usize>>>,
+        links: Vec<usize>,
+    ) -> SequencePanel {
+        SequencePanel {
+            selected: columns.iter().map(|row| vec![false; row.len()]).collect(),
+            sequences,
+            columns,
+            links,
+            scroll: 0.0,
+            first_row: 0,
+            changed: true,
+        }
+    }
+
+    pub fn place(&self, width: f32) -> Bar {
+        Bar::place(width, self.sequences.len())
+    }
+
+    fn rows(&self) -> usize {
+        self.sequences.len().min(MAX_ROWS)
+    }
+
+    /// The monomer of the linked row in `column` of `row`.
+    fn linked(&self, row: usize, column: usize) -> Option<(&Sequence, usize)> {
+        let link = self.links[row];
+        let monomer = self.columns[link].get(column).copied().flatten()?;
+        Some((&self.sequences[link], monomer))
+    }
+
+    /// Marks the columns whose linked monomer has an atom in `mask`.
+    pub fn select(&mut self, structure: &Structure, mask: &[bool]) {
+        let mut residues = vec![false; structure.atoms.last().map_or(0, |atom| atom.residue + 1)];
+        for (atom, _) in structure.atoms.iter().zip(mask).filter(|(_, &hit)| hit) {
+            residues[atom.residue] = true;
+        }
+
+        for row in 0..self.sequences.len() {
+            for column in 0..self.columns[row].len() {
+                self.selected[row][column] = self
+                    .linked(row, column)
+                    .and_then(|(sequence, monomer)| sequence.monomers[monomer].atom)
+                    .is_some_and(|atom| residues[structure.atoms[atom].residue]);
+            }
+        }
+        self.changed = true;
+    }
+
+    /// The modelled residues linked to the `range` of columns of `row`.
+    pub fn selection(&self, row: usize, range: RangeInclusive<usize>) -> Selection {
+        let monomers = range
+            .filter_map(|column| self.linked(row, column))
+            .map(|(_, monomer)| monomer)
+            .collect::<Vec<_>>();
+        self.sequences[self.links[row]].selection(monomers)
+    }
+
+    /// Scrolls along the sequences by `along` pixels and through the chains by `rows`, keeping
+    /// within what there is to show.
+    pub fn scroll_by(&mut self, bar: &Bar, along: f32, rows: isize) {
+        let longest = self.columns.iter().map(Vec::len).max().unwrap_or(0);
+        let visible = bar.width - 2.0 * PADDING - LABEL_WIDTH;
+        let limit = (longest as f32 * CELL_WIDTH - visible).max(0.0);
+        self.scroll = (self.scroll + along).clamp(0.0, limit);
+
+        let last = self.sequences.len().saturating_sub(self.rows());
+        self.first_row = self.first_row.saturating_add_signed(rows).min(last);
+        self.changed = true;
+    }
+
+    /// Column under `x`, possibly out of range of any row.
+    fn column(&self, bar: &Bar, x: f32) -> isize {
+        ((x - bar.x - PADDING - LABEL_WIDTH + self.scroll) / CELL_WIDTH).floor() as isize
+    }
+
+    /// The row and column under the cursor.
+    pub fn pick(&self, bar: &Bar, cursor: [f32; 2]) -> Option<(usize, usize)> {
+        if !bar.contains(cursor) {
+            return None;
+        }
+        let row = ((cursor[1] - bar.y - PADDING) / ROW_HEIGHT).floor();
+        if row < 0.0 || row as usize >= self.rows() {
+            return None;
+        }
+        let row = self.first_row + row as usize;
+        let column = self.column(bar, cursor[0]);
+
+        (column >= 0 && (column as usize) < self.columns[row].len())
+            .then_some((row, column as usize))
+    }
+
+    /// The column of `row` nearest the cursor's `x`, for drags leaving the residues.
+    pub fn drag(&self, bar: &Bar, row: usize, x: f32) -> usize {
+        let last = self.columns[row].len().saturating_sub(1);
+        self.column(bar, x).clamp(0, last as isize) as usize
+    }
+
+    /// RGBA pixels of the bar, rows from the top: chain identifiers on the left, then one
+    /// letter per column, grey where the residue has no coordinates and `-` for alignment
+    /// gaps, a tick under every tenth and selected columns on a yellow ground.
+    pub fn image(&self, bar: &Bar) -> Vec<u8> {
+        let (width, height) = (bar.width as usize, bar.height as usize);
+        let mut pixels = BACKGROUND
+            .iter()
+            .copied()
+            .chain([255])
+            .cycle()
+            .take(width * height * 4)
+            .collect::<Vec<_>>();
+        let fill = |pixels: &mut [u8], [x, y, w, h]: [usize; 4], color: [u8; 3]| {
+            for row in y..(y + h).min(height) {
+                for column in x..(x + w).min(width) {
+                    let offset = (row * width + column) * 4;
+                    pixels[offset..offset + 3].copy_from_slice(&color);
+                }
+            }
+        };
+
+        let left = PADDING + LABEL_WIDTH;
+        let glyph_top = FONT_SCALE as f32;
+        for (row, sequence) in self
+            .sequences
+            .iter()
+            .enumerate()
+            .skip(self.first_row)
+            .take(self.rows())
+        {
+            let top = PADDING + (row - self.first_row) as f32 * ROW_HEIGHT;
+            font::draw_text(
+                &mut pixels,
+                width,
+                [PADDING as isize, (top + glyph_top) as isize],
+                &sequence.chain_id,
+                FONT_SCALE,
+                LABEL_COLOR,
+            );
+
+            for (index, monomer) in self.columns[row].iter().enumerate() {
+                let x = left + index as f32 * CELL_WIDTH - self.scroll;
+                if x < left || x + CELL_WIDTH > bar.width - PADDING {
+                    continue;
+                }
+                let (x, y) = (x as usize, top as usize);
+                if self.selected[row][index] {
+                    fill(
+                        &mut pixels,
+                        [x, y, CELL_WIDTH as usize, ROW_HEIGHT as usize],
+                        SELECTED_COLOR,
+                    );
+                }
+                if (index + 1).is_multiple_of(10) {
+                    let bottom = y + ROW_HEIGHT as usize - FONT_SCALE;
+                    fill(
+                        &mut pixels,
+                        [x, bottom, CELL_WIDTH as usize - FONT_SCALE, 1],
+                        TICK_COLOR,
+                    );
+                }
+                let (code, color) = match monomer.map(|monomer| &sequence.monomers[monomer]) {
+                    Some(monomer) if monomer.observed() => (monomer.code, OBSERVED_COLOR),
+                    Some(monomer) => (monomer.code, MISSING_COLOR),
+                    None => ('-', LABEL_COLOR),
+                };
+                font::draw_text(
+                    &mut pixels,
+                    width,
+                    [x as isize, (top + glyph_top) as isize],
+                    &code.to_string(),
+                    FONT_SCALE,
+                    color,
+                );
+            }
+        }
+
+        pixels
+    }
+}
+
+#[test]
+fn reads_chain_sequences() {
+    use crate::scene::Scene;
+    use crate::style::Style;
+
+    assert_eq!(one_letter("MSE"), Some(('M', Kind::Protein)));
+    assert_eq!(one_letter("DG"), Some(('G', Kind::NucleicAcid)));
+    assert_eq!(one_letter("HOH"), None);
+
+    let scene = Scene::open("1d66.pdb", &Style::default()).unwrap();
+    let structure = scene.structure.as_ref().unwrap();
+    let sequences = sequences(structure);
+    let chains = sequences
+        .iter()
+        .map(|sequence| sequence.chain_id.as_str())
+        .collect::<Vec<_>>();
+    assert_eq!(chains, ["D", "E", "A", "B"]);
+    assert_eq!(sequences[0].kind, Kind::NucleicAcid);
+    assert_eq!(sequences[0].letters(), "CCGGAGGACAGTCCTCCGG");
+
+    // The protein chains list 66 residues of which only part were modelled
+    let protein = &sequences[2];
+    assert_eq!(protein.kind, Kind::Protein);
+    assert_eq!(protein.monomers.len(), 66);
+    assert!(protein
+        .letters()
+        .starts_with("MKLLSSIEQACDICRLKKLKCSKEKPKCAKC"));
+    assert!(protein.observed_letters().starts_with("-------"));
+    for monomer in protein.monomers.iter().filter(|monomer| monomer.observed()) {
+        let atom = &structure.atoms[monomer.atom.unwrap()];
+        assert_eq!(Some(atom.residue_number), monomer.number);
+        assert_eq!(atom.residue_name, monomer.name);
+    }
+
+    // Picking residues in the bar and selecting them round trips through the atom mask
+    let mut panel = SequencePanel::new(Some(structure));
+    let bar = panel.place(800.0);
+    let cursor = [
+        bar.x + PADDING + LABEL_WIDTH + 12.5 * CELL_WIDTH,
+        bar.y + PADDING + 2.5 * ROW_HEIGHT,
+    ];
+    assert_eq!(panel.pick(&bar, cursor), Some((2, 12)));
+    assert_eq!(panel.drag(&bar, 2, -50.0), 0);
+    let selection = panel.selection(2, 12..=20);
+    let mask = structure.select(&selection);
+    panel.select(structure, &mask);
+    let selected = panel.selected[2]
+        .iter()
+        .enumerate()
+        .filter(|(_, &selected)| selected)
+        .map(|(index, _)| index)
+        .collect::<Vec<_>>();
+    let modelled = (12..=20)
+        .filter(|&index| protein.monomers[index].observed())
+        .collect::<Vec<_>>();
+    assert_eq!(selected, modelled);
+
+    panel.scroll_by(&bar, 1e6, 10);
+    assert_eq!(panel.first_row, 0);
+    assert!(panel.scroll > 0.0);
+    let pixels = panel.image(&bar);
+    assert_eq!(pixels.len(), bar.width as usize * bar.height as usize * 4);
+
+    // Without a listed sequence only the modelled residues are known, and the summary says so
+    assert!(sequences.iter().all(|sequence| sequence.complete));
+    let unlisted = Structure::new(structure.pdb.clone(), &Style::default());
+    let partial = crate::sequence::sequences(&unlisted);
+    assert!(partial.iter().all(|sequence| !sequence.complete));
+    assert!(!partial[2].observed_letters().contains('-'));
+    assert!(summary(&partial).contains("full sequence not listed"));
+}
+
+#[test]
+fn reads_entity_poly_seq() {
+    let text = "data_TEST
+_entity_poly.entity_id 1
+_entity_poly.type 'polypeptide(L)'
+_entity_poly.pdbx_seq_one_letter_code
+;MKL
+SS
+;
+_entity_poly.pdbx_strand_id A,B
+#
+loop_
+_entity_poly_seq.entity_id
+_entity_poly_seq.num
+_entity_poly_seq.mon_id
+_entity_poly_seq.hetero
+1 1 MET n
+1 2 LYS n
+1 3 LEU y
+1 3 ILE y
+1 4 SER n
+1 5 SER n
+#
+loop_
+_struct.entry_id
+_struct.title
+TEST 'the protein's # title'
+";
+    let chains = entity_poly_seq(text);
+    assert_eq!(chains.len(), 2);
+    assert_eq!(chains["A"], ["MET", "LYS", "LEU", "SER", "SER"]);
+    assert_eq!(chains["B"], chains["A"]);
+    assert_eq!(cif_items(text)["_struct.title"], ["the protein's # title"]);
+    assert_eq!(
+        cif_items(text)["_entity_poly.pdbx_seq_one_letter_code"],
+        ["MKL\nSS"]
+    );
+}
+
+#[test]
+fn parses_sequence_commands() {
+    use crate::script::ScriptCommand;
+
+    assert_eq!(
+        ScriptCommand::parse("sequence off"),
+        Ok(Some(ScriptCommand::HideSequence))
+    );
+    assert!(ScriptCommand::parse("sequence 12").is_err());
+}
@@ -0,0 +1,560 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use pdbtbx::TransformationMatrix;
+
+use crate::json::Json;
+use crate::opengl::Renderer;
+use crate::scene::{Contact, ContactKind, Scene, Structure};
+use crate::script::Interpreter;
+use crate::selection::Selection;
+use crate::style::REPRESENTATION_COUNT;
+use crate::superpose;
+
+const SESSION_VERSION: f64 = 2.0;
+
+/// Rotation in radians and magnification of the view, stored as is so it comes back exactly.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Camera {
+    pub turn: f32,
+    pub tilt: f32,
+    pub scale: f32,
+}
+
+/// How one atom is displayed, in the order of `Structure::atoms`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct AtomState {
+    pub color: [f32; 3],
+    pub opacity: f32,
+    pub shown: [bool; REPRESENTATION_COUNT],
+    pub labeled: bool,
+}
+
+/// A second structure loaded for superposition, with the transform placing it onto the first.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MobileState {
+    pub file: PathBuf,
+    pub transformation: Option<[[f64; 4]; 3]>,
+    pub atoms: Vec<AtomState>,
+}
+
+/// Everything needed to bring a view back: the structure file, how each atom is drawn, the
+/// current selection and the camera, the drawn contacts and a superposed second structure.
+/// Saved as JSON, usually with a `.bps` extension.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Session {
+    pub file: Option<PathBuf>,
+    pub selection: Selection,
+    pub camera: Camera,
+    pub background: [f32; 3],
+    pub sphere_detail: (u32, u32),
+    pub atoms: Vec<AtomState>,
+    /// View position of the first atom, so a recentered view and its contacts line up again.
+    pub origin: Option<[f32; 3]>,
+    pub contacts: Vec<Contact>,
+    pub mobile: Option<MobileState>,
+}
+
+impl Camera {
+    pub fn of(renderer: &Renderer) -> Camera {
+        Camera {
+            turn: renderer.x_rotate.unwrap_or(0.0),
+            tilt: renderer.y_rotate.unwrap_or(0.0),
+            scale: renderer.scale,
+        }
+    }
+}
+
+impl Session {
+    pub fn capture(interpreter: &Interpreter, renderer: &Renderer) -> Session {
+        Session::of_scene(
+            &renderer.scene,
+            interpreter.selection.clone(),
+            Camera::of(renderer),
+            renderer.settings.background,
+        )
+    }
+
+    pub fn of_scene(
+        scene: &Scene,
+        selection: Selection,
+        camera: Camera,
+        background: [f32; 3],
+    ) -> Session {
+        let structure = scene.structure.as_ref();
+
+        Session {
+            file: structure.and_then(|structure| structure.source.clone()),
+            selection,
+            camera,
+            background,
+            sphere_detail: structure.map_or(
+                (crate::scene::SPHERE_SECTOR, crate::scene::SPHERE_STACK),
+                |structure| structure.sphere_detail,
+            ),
+            atoms: structure.map(atom_states).unwrap_or_default(),
+            origin: structure
+                .and_then(|structure| structure.atoms.first())
+                .map(|atom| atom.position),
+            contacts: structure
+                .map(|structure| structure.contacts.clone())
+                .unwrap_or_default(),
+            mobile: scene.mobile.as_ref().and_then(|mobile| {
+                Some(MobileState {
+                    file: mobile.source.clone()?,
+                    transformation: mobile
+                        .superposition
+                        .as_ref()
+                        .map(TransformationMatrix::matrix),
+                    atoms: atom_states(mobile),
+                })
+            }),
+        }
+    }
+
+    /// Loads the session's structure and puts the view back as it was saved.
+    pub fn restore(
+        &self,
+        interpreter: &mut Interpreter,
+        renderer: &mut Renderer,
+    ) -> Result<(), String> {
+        let mut scene = match &self.file {
+            Some(file) => Scene::open(&file.to_string_lossy(), &interpreter.style)?,
+            None => Scene::default(),
+        };
+        if let Some(mobile) = &self.mobile {
+            let file = mobile.file.to_string_lossy();
+            scene.mobile = Some(Structure::open(&file, &interpreter.style)?);
+        }
+        self.apply(&mut scene)?;
+
+        renderer.scene = scene;
+        renderer.x_rotate = Some(self.camera.turn);
+        renderer.y_rotate = Some(self.camera.tilt);
+        renderer.scale = self.camera.scale;
+        renderer.settings.background = self.background;
+        interpreter.selection = self.selection.clone();
+        interpreter.rebuild_panels(renderer);
+
+        Ok(())
+    }
+
+    /// Copies the saved atom states, contacts and superposition onto `scene`, which must hold
+    /// the same structures.
+    pub fn apply(&self, scene: &mut Scene) -> Result<(), String> {
+        if let Some(structure) = &mut scene.structure {
+            apply_states(structure, &self.atoms)?;
+            structure.sphere_detail = self.sphere_detail;
+
+            if let (Some(origin), Some(first)) = (self.origin, structure.atoms.first()) {
+                let shift = [0, 1, 2].map(|axis| origin[axis] - first.position[axis]);
+                structure.translate(shift);
+            }
+            structure.contacts = self.contacts.clone();
+        }
+
+        if let (Some(state), Some(mobile)) = (&self.mobile, &mut scene.mobile) {
+            apply_states(mobile, &state.atoms)?;
+            if let (Some(matrix), Some(reference)) = (state.transformation, &scene.structure) {
+                superpose::place(
+                    reference,
+                    mobile,
+                    &TransformationMatrix::from_matrix(matrix),
+                );
+            }
+        }
+        scene.rebuild();
+
+        Ok(())
+    }
+
+    /// Writes the session, storing the structure path relative to the session file when possible
+    /// so both can be handed on together.
+    pub fn save(&self, path: &Path) -> Result<(), String> {
+        let base_dir = path.parent().unwrap_or(Path::new("."));
+
+        fs::write(path, self.to_json(base_dir).to_string())
+            .map_err(|error| format!("Failed to save {}: {}", path.display(), error))
+    }
+
+    pub fn load(path: &Path) -> Result<Session, String> {
+        let text = fs::read_to_string(path)
+            .map_err(|error| format!("Failed to read {}: {}", path.display(), error))?;
+        let json = Json::parse(&text)
+            .map_err(|error| format!("Invalid session {}: {}", path.display(), error))?;
+
+        let mut session = Session::from_json(&json)
+            .map_err(|error| format!("Invalid session {}: {}", path.display(), error))?;
+        let base_dir = path.parent().unwrap_or(Path::new("."));
+        session.file = session.file.map(|file| base_dir.join(file));
+        if let Some(mobile) = &mut session.mobile {
+            mobile.file = base_dir.join(&mobile.file);
+        }
+
+        Ok(session)
+    }
+
+    fn to_json(&self, base_dir: &Path) -> Json {
+        let path =
+            |file: &Path| Json::from(relative_path(file, base_dir).to_string_lossy().as_ref());
+        let contacts = self
+            .contacts
+            .iter()
+            .map(|contact| {
+                Json::object([
+                    ("kind", Json::from(contact.kind.name())),
+                    ("start", numbers(&contact.start)),
+                    ("end", numbers(&contact.end)),
+                    ("atom", Json::from(contact.atom)),
+                ])
+            })
+            .collect();
+        let mobile = self.mobile.as_ref().map_or(Json::Null, |mobile| {
+            let mut json = Json::object([
+                ("file", path(&mobile.file)),
+                (
+                    "transformation",
+                    mobile.transformation.map_or(Json::Null, |matrix| {
+                        Json::Array(matrix.iter().flatten().map(|&v| Json::Number(v)).collect())
+                    }),
+                ),
+            ]);
+            if let Json::Object(members) = &mut json {
+                members.extend(atom_members(&mobile.atoms));
+            }
+            json
+        });
+
+        let mut json = Json::object([
+            ("version", Json::Number(SESSION_VERSION)),
+            ("file", self.file.as_deref().map_or(Json::Null, path)),
+            ("selection", Json::from(self.selection.to_string())),
+            (
+                "camera",
+                Json::object([
+                    ("turn", Json::from(self.camera.turn)),
+                    ("tilt", Json::from(self.camera.tilt)),
+                    ("scale", Json::from(self.camera.scale)),
+                ]),
+            ),
+            ("background", numbers(&self.background)),
+            (
+                "sphere_detail",
+                Json::Array(vec![
+                    Json::from(self.sphere_detail.0 as usize),
+                    Json::from(self.sphere_detail.1 as usize),
+                ]),
+            ),
+            (
+                "origin",
+                self.origin.map_or(Json::Null, |origin| numbers(&origin)),
+            ),
+            ("contacts", Json::Array(contacts)),
+            ("mobile", mobile),
+        ]);
+        if let Json::Object(members) = &mut json {
+            members.extend(atom_members(&self.atoms));
+        }
+        json
+    }
+
+    fn from_json(json: &Json) -> Result<Session, String> {
+        let field = |key: &str| json.get(key).ok_or_else(|| format!("Missing \"{}\"", key));
+
+        let version = field("version")?.as_f64().unwrap_or(0.0);
+        if version > SESSION_VERSION {
+            return Err(format!("Unsupported session version {}", version));
+        }
+
+        let camera = field("camera")?;
+        let angle = |key: &str| {
+            camera
+                .get(key)
+                .and_then(Json::as_f64)
+                .map(|value| value as f32)
+                .ok_or_else(|| format!("Missing camera \"{}\"", key))
+        };
+        let camera = Camera {
+            turn: angle("turn")?,
+            tilt: angle("tilt")?,
+            scale: camera
+                .get("scale")
+                .and_then(Json::as_f64)
+                .ok_or_else(|| "Missing camera \"scale\"".to_string())? as f32,
+        };
+
+        let background = match parse_numbers(field("background")?, "background")?.as_slice() {
+            [red, green, blue] => [*red as f32, *green as f32, *blue as f32],
+            _ => return Err("\"background\" needs three components".to_string()),
+        };
+        let sphere_detail =
+            match parse_numbers(field("sphere_detail")?, "sphere_detail")?.as_slice() {
+                [sectors, stacks] => (*sectors as u32, *stacks as u32),
+                _ => return Err("\"sphere_detail\" needs sectors and stacks".to_string()),
+            };
+
+        // Sessions before version 2 have no origin, contacts or second structure
+        let origin = match json.get("origin").filter(|origin| **origin != Json::Null) {
+            Some(origin) => Some(point(origin, "origin")?),
+            None => None,
+        };
+        let contacts = match json.get("contacts") {
+            Some(contacts) => contacts
+                .as_array()
+                .ok_or_else(|| "\"contacts\" must be an array".to_string())?
+                .iter()
+                .map(parse_contact)
+                .collect::<Result<Vec<_>, _>>()?,
+            None => Vec::new(),
+        };
+        let mobile = match json.get("mobile").filter(|mobile| **mobile != Json::Null) {
+            Some(mobile) => Some(MobileState {
+                file: mobile
+                    .get("file")
+                    .and_then(Json::as_str)
+                    .map(PathBuf::from)
+                    .ok_or_else(|| "Missing mobile \"file\"".to_string())?,
+                transformation: match mobile.get("transformation") {
+                    Some(Json::Null) | None => None,
+                    Some(matrix) => match parse_numbers(matrix, "transformation")?.as_slice() {
+                        values if values.len() == 12 => Some(std::array::from_fn(|row| {
+                            std::array::from_fn(|column| values[row * 4 + column])
+                        })),
+                        _ => return Err("\"transformation\" needs 12 numbers".to_string()),
+                    },
+                },
+                atoms: parse_atoms(mobile)?,
+            }),
+            None => None,
+        };
+
+        Ok(Session {
+            file: field("file")?.as_str().map(PathBuf::from),
+            selection: field("selection")?
+                .as_str()
+                .ok_or_else(|| "\"selection\" must be a string".to_string())?
+                .parse()?,
+            camera,
+            background,
+            sphere_detail,
+            atoms: parse_atoms(json)?,
+            origin,
+            contacts,
+            mobile,
+        })
+    }
+}
+
+fn numbers(values: &[f32]) -> Json {
+    Json::Array(values.iter().map(|&v| Json::from(v)).collect())
+}
+
+fn parse_numbers(value: &Json, key: &str) -> Result<Vec<f64>, String> {
+    value
+        .as_array()
+        .and_then(|values| values.iter().map(Json::as_f64).collect::<Option<Vec<_>>>())
+        .ok_or_else(|| format!("\"{}\" must be an array of numbers", key))
+}
+
+fn point(value: &Json, key: &str) -> Result<[f32; 3], String> {
+    match parse_numbers(value, key)?.as_slice() {
+        [x, y, z] => Ok([*x as f32, *y as f32, *z as f32]),
+        _ => Err(format!("\"{}\" needs three coordinates", key)),
+    }
+}
+
+fn parse_contact(json: &Json) -> Result<Contact, String> {
+    let field = |key: &str| {
+        json.get(key)
+            .ok_or_else(|| format!("Missing contact \"{}\"", key))
+    };
+    let kind = field("kind")?.as_str().unwrap_or_default();
+
+    Ok(Contact {
+        kind: ContactKind::ALL
+            .into_iter()
+            .find(|candidate| candidate.name() == kind)
+            .ok_or_else(|| format!("Unknown contact kind '{}'", kind))?,
+        start: point(field("start")?, "start")?,
+        end: point(field("end")?, "end")?,
+        atom: field("atom")?
+            .as_f64()
+            .ok_or_else(|| "Contact \"atom\" must be a number".to_string())? as usize,
+    })
+}
+
+fn atom_states(structure: &Structure) -> Vec<AtomState> {
+    structure
+        .atoms
+        .iter()
+        .map(|atom| AtomState {
+            color: atom.color,
+            opacity: atom.opacity,
+            shown: atom.shown,
+            labeled: atom.labeled,
+        })
+        .collect()
+}
+
+fn apply_states(structure: &mut Structure, states: &[AtomState]) -> Result<(), String> {
+    if structure.atoms.len() != states.len() {
+        return Err(format!(
+            "The session describes {} atoms but the structure has {}",
+            states.len(),
+            structure.atoms.len()
+        ));
+    }
+
+    for (atom, state) in structure.atoms.iter_mut().zip(states) {
+        atom.color = state.color;
+        atom.opacity = state.opacity;
+        atom.shown = state.shown;
+        atom.labeled = state.labeled;
+    }
+
+    Ok(())
+}
+
+/// Colors, opacities, shown representations and labels of `atoms` as object members.
+fn atom_members(atoms: &[AtomState]) -> Vec<(String, Json)> {
+    // Per atom values are flattened to keep the file small
+    let colors = atoms.iter().flat_map(|atom| atom.color).collect::<Vec<_>>();
+    let opacities = atoms.iter().map(|atom| atom.opacity).collect::<Vec<_>>();
+    let shown = atoms
+        .iter()
+        .map(|atom| {
+            let bits = atom
+                .shown
+                .iter()
+                .enumerate()
+                .filter(|(_, &shown)| shown)
+                .fold(0, |bits, (index, _)| bits | 1 << index);
+            Json::from(bits as usize)
+        })
+        .collect();
+    let labels = atoms
+        .iter()
+        .enumerate()
+        .filter(|(_, atom)| atom.labeled)
+        .map(|(index, _)| Json::from(index))
+        .collect();
+
+    vec![
+        ("colors".to_string(), numbers(&colors)),
+        ("opacities".to_string(), numbers(&opacities)),
+        ("shown".to_string(), Json::Array(shown)),
+        ("labels".to_string(), Json::Array(labels)),
+    ]
+}
+
+/// Atom states from the members written by `atom_members`.
+fn parse_atoms(json: &Json) -> Result<Vec<AtomState>, String> {
+    let field = |key: &str| json.get(key).ok_or_else(|| format!("Missing \"{}\"", key));
+
+    let colors = parse_numbers(field("colors")?, "colors")?;
+    let opacities = parse_numbers(field("opacities")?, "opacities")?;
+    let shown = parse_numbers(field("shown")?, "shown")?;
+    if colors.len() != shown.len() * 3 || opacities.len() != shown.len() {
+        return Err("Per atom arrays differ in length".to_string());
+    }
+    let mut atoms = shown
+        .iter()
+        .zip(colors.chunks(3))
+        .zip(&opacities)
+        .map(|((&bits, color), &opacity)| AtomState {
+            color: [color[0] as f32, color[1] as f32, color[2] as f32],
+            opacity: opacity as f32,
+            shown: std::array::from_fn(|index| (bits as usize) & (1 << index) != 0),
+            labeled: false,
+        })
+        .collect::<Vec<_>>();
+    // Older sessions have no labels
+    if let Some(labels) = json.get("labels") {
+        for index in parse_numbers(labels, "labels")? {
+            atoms
+                .get_mut(index as usize)
+                .ok_or_else(|| format!("Label on missing atom {}", index))?
+                .labeled = true;
+        }
+    }
+
+    Ok(atoms)
+}
+
+/// `path` relative to `base_dir` if it lies below it, otherwise absolute.
+fn relative_path(path: &Path, base_dir: &Path) -> PathBuf {
+    let absolute = |path: &Path| fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let path = absolute(path);
+
+    match path.strip_prefix(absolute(base_dir)) {
+        Ok(relative) => relative.to_path_buf(),
+        Err(_) => path,
+    }
+}
+
+#[test]
+fn session_restores_atom_state() {
+    use crate::style::{ColorScheme, Representation, Style};
+
+    let mut scene = Scene::open("1d66.pdb", &Style::default()).unwrap();
+    let chain_a = scene.select(&"chain A".parse().unwrap());
+    scene.color(&chain_a, ColorScheme::BFactor);
+    scene.show(&chain_a, Representation::Cartoon);
+    scene.hide(&chain_a, Some(Representation::Spacefill));
+    scene.label(&scene.select(&"chain A and resi 12".parse().unwrap()), true);
+    scene.center_on(&chain_a);
+    let contact = Contact {
+        kind: ContactKind::SaltBridge,
+        start: scene.structure.as_ref().unwrap().atoms[0].position,
+        end: [1.5, -2.0, 0.25],
+        atom: 0,
+    };
+    scene.set_contacts(contact.kind, vec![contact.clone()]);
+    let mut mobile = Structure::open("1d66.pdb", &Style::default()).unwrap();
+    let mut shift = TransformationMatrix::identity();
+    shift.matrix_mut()[0][3] = 4.0;
+    superpose::place(scene.structure.as_ref().unwrap(), &mut mobile, &shift);
+    scene.mobile = Some(mobile);
+    scene.rebuild();
+
+    let camera = Camera {
+        turn: 0.5,
+        tilt: -0.25,
+        scale: 0.2,
+    };
+    let session = Session::of_scene(&scene, "chain A".parse().unwrap(), camera, [1.0; 3]);
+
+    let path = std::env::temp_dir().join(format!("biopix-test-{}.bps", std::process::id()));
+    session.save(&path).unwrap();
+    let loaded = Session::load(&path).unwrap();
+    fs::remove_file(&path).unwrap();
+
+    assert_eq!(loaded.selection, session.selection);
+    assert_eq!(loaded.atoms, session.atoms);
+    assert_eq!(loaded.camera, camera);
+    assert_eq!(
+        fs::canonicalize(loaded.file.as_ref().unwrap()).unwrap(),
+        fs::canonicalize("1d66.pdb").unwrap()
+    );
+
+    assert_eq!(loaded.contacts, vec![contact]);
+    let (mobile, saved) = (loaded.mobile.as_ref().unwrap(), session.mobile.unwrap());
+    assert_eq!(mobile.transformation, saved.transformation);
+    assert_eq!(mobile.atoms, saved.atoms);
+
+    let mut restored = Scene::open("1d66.pdb", &Style::default()).unwrap();
+    restored.mobile = Some(Structure::open("1d66.pdb", &Style::default()).unwrap());
+    loaded.apply(&mut restored).unwrap();
+    assert_eq!(restored.spheres.len(), scene.spheres.len());
+    assert_eq!(restored.cyliders.len(), scene.cyliders.len());
+    let positions = |scene: &Scene| {
+        let (structure, mobile) = (scene.structure.as_ref(), scene.mobile.as_ref());
+        let atoms = structure
+            .unwrap()
+            .atoms
+            .iter()
+            .chain(&mobile.unwrap().atoms);
+        atoms.map(|atom| atom.position).collect::<Vec<_>>()
+    };
+    assert_eq!(positions(&restored), positions(&scene));
+}
@@ -0,0 +1,455 @@
+use std::error::Error;
+use std::ffi::CString;
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+use crate::gl;
+
+/// GLSL source compiled into the binary, optionally overridden by a file of the same name in a
+/// shader directory during development.
+#[derive(Debug, Clone, Copy)]
+pub struct ShaderSource {
+    pub name: &'static str,
+    pub embedded: &'static str,
+}
+
+macro_rules! embed_shader {
+    ($name:literal) => {
+        ShaderSource {
+            name: $name,
+            embedded: include_str!(concat!("shaders/", $name)),
+        }
+    };
+}
+
+pub const VERTEX: ShaderSource = embed_shader!("vertex.glsl");
+pub const FRAGMENT: ShaderSource = embed_shader!("fragment.glsl");
+pub const NORMAL_FRAGMENT: ShaderSource = embed_shader!("normal_fragment.glsl");
+pub const QUAD_VERTEX: ShaderSource = embed_shader!("quad_vertex.glsl");
+pub const SSAO_FRAGMENT: ShaderSource = embed_shader!("ssao_fragment.glsl");
+pub const BLUR_FRAGMENT: ShaderSource = embed_shader!("blur_fragment.glsl");
+pub const OUTLINE_FRAGMENT: ShaderSource = embed_shader!("outline_fragment.glsl");
+pub const SHADOW_FRAGMENT: ShaderSource = embed_shader!("shadow_fragment.glsl");
+pub const OIT_COMPOSITE_FRAGMENT: ShaderSource = embed_shader!("oit_composite_fragment.glsl");
+pub const FXAA_FRAGMENT: ShaderSource = embed_shader!("fxaa_fragment.glsl");
+pub const OVERLAY_FRAGMENT: ShaderSource = embed_shader!("overlay_fragment.glsl");
+
+impl ShaderSource {
+    /// Reads the source from `shader_dir` when given, otherwise uses the embedded copy.
+    pub fn load(&self, shader_dir: Option<&Path>) -> Result<String, ShaderError> {
+        match shader_dir {
+            Some(dir) => {
+                let path = dir.join(self.name);
+                fs::read_to_string(&path).map_err(|error| ShaderError::Io { path, error })
+            }
+            None => Ok(self.embedded.to_string()),
+        }
+    }
+}
+
+/// GLSL dialect the sources are translated to, chosen from the context that was actually
+/// created.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum GlslTarget {
+    /// Desktop OpenGL 3.3 core profile.
+    Core330,
+    /// OpenGL ES 3.0.
+    Es300,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ShaderStage {
+    Vertex,
+    Fragment,
+}
+
+impl GlslTarget {
+    /// Picks the target from a `GL_VERSION` string, which starts with "OpenGL ES" on GLES.
+    pub fn from_version(version: &str) -> GlslTarget {
+        if version.starts_with("OpenGL ES") {
+            GlslTarget::Es300
+        } else {
+            GlslTarget::Core330
+        }
+    }
+
+    pub fn version_header(self) -> &'static str {
+        match self {
+            GlslTarget::Core330 => "#version 330 core",
+            GlslTarget::Es300 => "#version 300 es",
+        }
+    }
+
+    /// Translates GLSL ES 1.0 style source: adds the `#version` header, turns
+    /// `attribute`/`varying` into `in`/`out`, `texture2D` into `texture`, and replaces
+    /// `gl_FragColor`/`gl_FragData` with declared fragment outputs.
+    pub fn preprocess(self, source: &str, stage: ShaderStage) -> String {
+        let mut body = String::with_capacity(source.len());
+        for line in source.lines() {
+            if line.trim_start().starts_with("#version") {
+                continue;
+            }
+            body.push_str(line);
+            body.push('\n');
+        }
+
+        let body = replace_identifiers(&body, |identifier| match (identifier, stage) {
+            ("attribute", ShaderStage::Vertex) => Some("in"),
+            ("varying", ShaderStage::Vertex) => Some("out"),
+            ("varying", ShaderStage::Fragment) => Some("in"),
+            ("texture2D", _) => Some("texture"),
+            ("gl_FragColor", ShaderStage::Fragment) => Some("frag_color"),
+            ("gl_FragData", ShaderStage::Fragment) => Some("frag_data"),
+            _ => None,
+        });
+
+        let mut output = String::with_capacity(body.len() + 128);
+        output.push_str(self.version_header());
+        output.push('\n');
+
+        if stage == ShaderStage::Fragment {
+            if self == GlslTarget::Es300 && !body.contains("precision") {
+                output.push_str("precision highp float;\n");
+            }
+            if contains_identifier(source, "gl_FragColor") {
+                output.push_str("layout(location = 0) out vec4 frag_color;\n");
+            }
+            if let Some(count) = frag_data_count(source) {
+                output.push_str(&format!(
+                    "layout(location = 0) out vec4 frag_data[{}];\n",
+                    count
+                ));
+            }
+        }
+
+        output.push_str(&body);
+        output
+    }
+}
+
+/// Rewrites whole identifiers of `source` for which `map` returns a replacement.
+fn replace_identifiers<'a>(source: &str, map: impl Fn(&str) -> Option<&'a str>) -> String {
+    let mut output = String::with_capacity(source.len());
+    let mut rest = source;
+
+    while let Some(start) = rest.find(is_identifier_char) {
+        output.push_str(&rest[..start]);
+        rest = &rest[start..];
+
+        let end = rest.find(|c| !is_identifier_char(c)).unwrap_or(rest.len());
+        let identifier = &rest[..end];
+        output.push_str(map(identifier).unwrap_or(identifier));
+        rest = &rest[end..];
+    }
+
+    output.push_str(rest);
+    output
+}
+
+fn is_identifier_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_'
+}
+
+fn contains_identifier(source: &str, name: &str) -> bool {
+    source
+        .split(|c| !is_identifier_char(c))
+        .any(|identifier| identifier == name)
+}
+
+/// Number of `gl_FragData` outputs, from the highest literal index written.
+fn frag_data_count(source: &str) -> Option<usize> {
+    source
+        .match_indices("gl_FragData[")
+        .filter_map(|(index, pattern)| {
+            let rest = &source[index + pattern.len()..];
+            rest[..rest.find(']')?].trim().parse::<usize>().ok()
+        })
+        .max()
+        .map(|index| index + 1)
+}
+
+#[derive(Debug)]
+pub enum ShaderError {
+    Io {
+        path: PathBuf,
+        error: std::io::Error,
+    },
+    Compile {
+        name: &'static str,
+        log: String,
+    },
+    Link {
+        vertex: &'static str,
+        fragment: &'static str,
+        log: String,
+    },
+}
+
+impl fmt::Display for ShaderError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ShaderError::Io { path, error } => {
+                write!(f, "Failed to read shader {}: {}", path.display(), error)
+            }
+            ShaderError::Compile { name, log } => {
+                write!(f, "Failed to compile {}:\n{}", name, log.trim_end())
+            }
+            ShaderError::Link {
+                vertex,
+                fragment,
+                log,
+            } => write!(
+                f,
+                "Failed to link {} with {}:\n{}",
+                vertex,
+                fragment,
+                log.trim_end()
+            ),
+        }
+    }
+}
+
+impl Error for ShaderError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            ShaderError::Io { error, .. } => Some(error),
+            _ => None,
+        }
+    }
+}
+
+/// A linked vertex and fragment shader pair that remembers its sources, so it can be rebuilt.
+pub struct ShaderProgram {
+    pub id: gl::types::GLuint,
+    target: GlslTarget,
+    vertex: ShaderSource,
+    fragment: ShaderSource,
+}
+
+impl ShaderProgram {
+    pub fn new(
+        gl: &gl::Gl,
+        target: GlslTarget,
+        vertex: ShaderSource,
+        fragment: ShaderSource,
+        shader_dir: Option<&Path>,
+    ) -> Result<ShaderProgram, ShaderError> {
+        Ok(ShaderProgram {
+            id: build_program(gl, target, vertex, fragment, shader_dir)?,
+            target,
+            vertex,
+            fragment,
+        })
+    }
+
+    /// Rebuilds the program from its current sources. On failure the previous program stays
+    /// in use.
+    pub fn reload(&mut self, gl: &gl::Gl, shader_dir: Option<&Path>) -> Result<(), ShaderError> {
+        let id = build_program(gl, self.target, self.vertex, self.fragment, shader_dir)?;
+
+        unsafe {
+            gl.DeleteProgram(self.id);
+        }
+        self.id = id;
+
+        Ok(())
+    }
+
+    pub fn delete(&self, gl: &gl::Gl) {
+        unsafe {
+            gl.DeleteProgram(self.id);
+        }
+    }
+}
+
+fn build_program(
+    gl: &gl::Gl,
+    target: GlslTarget,
+    vertex: ShaderSource,
+    fragment: ShaderSource,
+    shader_dir: Option<&Path>,
+) -> Result<gl::types::GLuint, ShaderError> {
+    let vertex_source = target.preprocess(&vertex.load(shader_dir)?, ShaderStage::Vertex);
+    let fragment_source = target.preprocess(&fragment.load(shader_dir)?, ShaderStage::Fragment);
+
+    unsafe {
+        let vertex_shader = compile_shader(gl, gl::VERTEX_SHADER, vertex.name, &vertex_source)?;
+        let fragment_shader =
+            match compile_shader(gl, gl::FRAGMENT_SHADER, fragment.name, &fragment_source) {
+                Ok(shader) => shader,
+                Err(error) => {
+                    gl.DeleteShader(vertex_shader);
+                    return Err(error);
+                }
+            };
+
+        let program = gl.CreateProgram();
+        gl.AttachShader(program, vertex_shader);
+        gl.AttachShader(program, fragment_shader);
+        gl.LinkProgram(program);
+
+        gl.DeleteShader(vertex_shader);
+        gl.DeleteShader(fragment_shader);
+
+        let mut status = 0;
+        gl.GetProgramiv(program, gl::LINK_STATUS, &mut status);
+        if status == 0 {
+            let log = program_log(gl, program);
+            gl.DeleteProgram(program);
+            return Err(ShaderError::Link {
+                vertex: vertex.name,
+                fragment: fragment.name,
+                log,
+            });
+        }
+
+        Ok(program)
+    }
+}
+
+unsafe fn compile_shader(
+    gl: &gl::Gl,
+    kind: gl::types::GLenum,
+    name: &'static str,
+    source: &str,
+) -> Result<gl::types::GLuint, ShaderError> {
+    let source = CString::new(source).map_err(|_| ShaderError::Compile {
+        name,
+        log: "source contains a nul byte".to_string(),
+    })?;
+
+    let shader = gl.CreateShader(kind);
+    gl.ShaderSource(shader, 1, [source.as_ptr()].as_ptr(), std::ptr::null());
+    gl.CompileShader(shader);
+
+    let mut status = 0;
+    gl.GetShaderiv(shader, gl::COMPILE_STATUS, &mut status);
+    if status == 0 {
+        let log = shader_log(gl, shader);
+        gl.DeleteShader(shader);
+        return Err(ShaderError::Compile { name, log });
+    }
+
+    Ok(shader)
+}
+
+unsafe fn shader_log(gl: &gl::Gl, shader: gl::types::GLuint) -> String {
+    let mut length = 0;
+    gl.GetShaderiv(shader, gl::INFO_LOG_LENGTH, &mut length);
+
+    let mut log = vec![0u8; length.max(1) as usize];
+    gl.GetShaderInfoLog(
+        shader,
+        length,
+        std::ptr::null_mut(),
+        log.as_mut_ptr().cast(),
+    );
+
+    String::from_utf8_lossy(&log)
+        .trim_end_matches('\0')
+        .to_string()
+}
+
+unsafe fn program_log(gl: &gl::Gl, program: gl::types::GLuint) -> String {
+    let mut length = 0;
+    gl.GetProgramiv(program, gl::INFO_LOG_LENGTH, &mut length);
+
+    let mut log = vec![0u8; length.max(1) as usize];
+    gl.GetProgramInfoLog(
+        program,
+        length,
+        std::ptr::null_mut(),
+        log.as_mut_ptr().cast(),
+    );
+
+    String::from_utf8_lossy(&log)
+        .trim_end_matches('\0')
+        .to_string()
+}
+
+/// How often the shader directory is checked for edits.
+const POLL_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Watches a shader directory for edited `.glsl` files by polling their modification times.
+pub struct ShaderWatcher {
+    pub dir: PathBuf,
+    last_modified: Option<SystemTime>,
+    last_poll: Instant,
+}
+
+impl ShaderWatcher {
+    pub fn new(dir: PathBuf) -> ShaderWatcher {
+        ShaderWatcher {
+            last_modified: newest_modification(&dir),
+            dir,
+            last_poll: Instant::now(),
+        }
+    }
+
+    /// True once after any shader in the directory was modified.
+    pub fn changed(&mut self) -> bool {
+        if self.last_poll.elapsed() < POLL_INTERVAL {
+            return false;
+        }
+        self.last_poll = Instant::now();
+
+        let modified = newest_modification(&self.dir);
+        if modified > self.last_modified {
+            self.last_modified = modified;
+            return true;
+        }
+
+        false
+    }
+}
+
+fn newest_modification(dir: &Path) -> Option<SystemTime> {
+    fs::read_dir(dir)
+        .ok()?
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().extension().is_some_and(|ext| ext == "glsl"))
+        .filter_map(|entry| entry.metadata().ok()?.modified().ok())
+        .max()
+}
+
+#[test]
+fn shader_dir_overrides_embedded_source() {
+    let dir = std::env::temp_dir().join(format!("biopix-shaders-{}", std::process::id()));
+    fs::create_dir_all(&dir).unwrap();
+    fs::write(dir.join(FXAA_FRAGMENT.name), "void main() {}").unwrap();
+
+    assert_eq!(FXAA_FRAGMENT.load(Some(&dir)).unwrap(), "void main() {}");
+    assert_eq!(FXAA_FRAGMENT.load(None).unwrap(), FXAA_FRAGMENT.embedded);
+    assert!(matches!(
+        VERTEX.load(Some(&dir)),
+        Err(ShaderError::Io { .. })
+    ));
+
+    fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn preprocess_maps_es2_idioms() {
+    let fragment = "precision mediump float;\nvarying vec2 v_uv;\nuniform sampler2D color;\n\
+        void main() {\n    gl_FragData[0] = texture2D(color, v_uv);\n    gl_FragData[1] = vec4(1.0);\n}\n";
+    let output = GlslTarget::Core330.preprocess(fragment, ShaderStage::Fragment);
+
+    assert!(output.starts_with("#version 330 core\n"));
+    assert!(output.contains("layout(location = 0) out vec4 frag_data[2];"));
+    assert!(output.contains("in vec2 v_uv;"));
+    assert!(output.contains("frag_data[0] = texture(color, v_uv);"));
+    assert!(!output.contains("gl_FragData") && !output.contains("varying"));
+
+    let vertex = "attribute vec3 position;\nvarying vec3 v_position;\n";
+    let output = GlslTarget::Es300.preprocess(vertex, ShaderStage::Vertex);
+    assert_eq!(
+        output,
+        "#version 300 es\nin vec3 position;\nout vec3 v_position;\n"
+    );
+    assert_eq!(
+        GlslTarget::from_version("OpenGL ES 3.2 Mesa 23.0"),
+        GlslTarget::Es300
+    );
+}
@@ -0,0 +1,26 @@
+precision mediump float;
+
+uniform sampler2D occlusion;
+uniform vec2 texel_size;
+uniform int blur_radius;
+
+varying vec2 v_uv;
+
+void main()
+{
+    float result = 0.0;
+    float count = 0.0;
+
+    for (int x = -4; x <= 4; x++) {
+        for (int y = -4; y <= 4; y++) {
+            if (abs(x) > blur_radius || abs(y) > blur_radius) {
+                continue;
+            }
+
+            result += texture2D(occlusion, v_uv + vec2(float(x), float(y)) * texel_size).r;
+            count += 1.0;
+        }
+    }
+
+    gl_FragColor = vec4(vec3(result / count), 1.0);
+}
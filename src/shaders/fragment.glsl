@@ -4,31 +4,107 @@ precision mediump float;
 varying vec3 v_position;
 varying vec3 v_normal;
 varying vec3 v_color;
-varying mat4 light_dirn;
+varying vec4 v_shadow_coord;
+
+uniform bool ssao_enabled;
+uniform sampler2D ambient_occlusion;
+uniform vec2 viewport;
+
+uniform bool fog_enabled;
+uniform float fog_start;
+uniform float fog_end;
+uniform vec3 background;
+
+// Key light, fixed in view space
+uniform vec3 light_direction;
+uniform vec3 light_color;
+
+uniform bool shadows_enabled;
+uniform sampler2D shadow_map;
+uniform float shadow_texel_size;
+uniform int shadow_softness;
+
+// Transparent objects are accumulated into two targets instead of being blended in draw order
+uniform float opacity;
+uniform bool oit_pass;
 
-vec3 light_position = vec3(light_dirn * vec4(vec3(-100.0, -000.0, -000.0),1.0));
-vec3 light_color = vec3(0.5, 0.5, 0.5);
 vec3 ambient_color = vec3(0.0, 0.0, 0.0);
 float shininess = 0.0;
 
+// Fraction of the key light reaching this fragment, averaged over neighbouring shadow map texels
+float shadow_visibility(vec3 normal)
+{
+    vec3 coord = v_shadow_coord.xyz * 0.5 + 0.5;
+    if (coord.z > 1.0) {
+        return 1.0;
+    }
+
+    // Surfaces grazing the light need a larger bias to avoid acne
+    float bias = max(0.004 * (1.0 - dot(normal, light_direction)), 0.001);
+
+    float lit = 0.0;
+    float count = 0.0;
+    for (int x = -4; x <= 4; x++) {
+        for (int y = -4; y <= 4; y++) {
+            if (abs(x) > shadow_softness || abs(y) > shadow_softness) {
+                continue;
+            }
+
+            vec2 offset = vec2(float(x), float(y)) * shadow_texel_size;
+            float closest = texture2D(shadow_map, coord.xy + offset).r;
+            lit += coord.z - bias > closest ? 0.0 : 1.0;
+            count += 1.0;
+        }
+    }
+
+    return lit / count;
+}
+
 void main()
 {
+    vec3 normal = normalize(v_normal);
+
+    // Ambient occlusion computed by the SSAO pass, 1.0 means fully exposed
+    float occlusion = 1.0;
+    if (ssao_enabled) {
+        occlusion = texture2D(ambient_occlusion, gl_FragCoord.xy / viewport).r;
+    }
+
     // Calculate ambient lighting
-    vec3 ambient = v_color * 0.05;
+    vec3 ambient = v_color * 0.05 * occlusion;
+
+    float visibility = 1.0;
+    if (shadows_enabled) {
+        visibility = shadow_visibility(normal);
+    }
 
     // Calculate diffuse lighting
-    vec3 lightDirection = normalize(light_position - v_position);
-    float diffuse = max(dot(v_normal, lightDirection), 0.0);
-    vec3 diffuseColor = v_color * light_color * diffuse;
+    vec3 lightDirection = light_direction;
+    float diffuse = max(dot(normal, lightDirection), 0.0);
+    vec3 diffuseColor = v_color * light_color * diffuse * occlusion * visibility;
 
     // Calculate specular lighting
     vec3 viewDirection = normalize(-lightDirection);
-    vec3 reflectDirection = reflect(-lightDirection, v_normal);
+    vec3 reflectDirection = reflect(-lightDirection, normal);
     float specular = pow(max(dot(viewDirection, reflectDirection), 0.0), shininess);
-    vec3 specularColor = light_color * specular;
+    vec3 specularColor = light_color * specular * visibility;
 
     // Combine ambient, diffuse, and specular lighting
     vec3 finalColor = ambient + diffuseColor + specularColor;
 
-    gl_FragColor = vec4(finalColor, 1.0);
+    // Depth cueing, fade far fragments into the background
+    if (fog_enabled && fog_end > fog_start) {
+        float depth = v_position.z * 0.5 + 0.5;
+        float fog = clamp((depth - fog_start) / (fog_end - fog_start), 0.0, 1.0);
+        finalColor = mix(finalColor, background, fog);
+    }
+
+    if (oit_pass) {
+        // Weight nearer fragments more, so they dominate the averaged color
+        float weight = clamp(opacity * max(0.01, 3000.0 * pow(1.0 - gl_FragCoord.z, 3.0)), 0.01, 3000.0);
+        gl_FragData[0] = vec4(finalColor * opacity * weight, opacity);
+        gl_FragData[1] = vec4(opacity * weight);
+    } else {
+        gl_FragData[0] = vec4(finalColor, 1.0);
+    }
 }
@@ -0,0 +1,40 @@
+precision mediump float;
+
+uniform sampler2D color;
+uniform vec2 texel_size;
+
+varying vec2 v_uv;
+
+// Fast approximate antialiasing, used when the surface has fewer MSAA samples than requested
+float FXAA_REDUCE_MIN = 1.0 / 128.0;
+float FXAA_REDUCE_MUL = 1.0 / 8.0;
+float FXAA_SPAN_MAX = 8.0;
+
+void main()
+{
+    vec3 luma = vec3(0.299, 0.587, 0.114);
+
+    vec3 rgb_m = texture2D(color, v_uv).rgb;
+    float luma_nw = dot(texture2D(color, v_uv + vec2(-1.0, -1.0) * texel_size).rgb, luma);
+    float luma_ne = dot(texture2D(color, v_uv + vec2(1.0, -1.0) * texel_size).rgb, luma);
+    float luma_sw = dot(texture2D(color, v_uv + vec2(-1.0, 1.0) * texel_size).rgb, luma);
+    float luma_se = dot(texture2D(color, v_uv + vec2(1.0, 1.0) * texel_size).rgb, luma);
+    float luma_m = dot(rgb_m, luma);
+
+    float luma_min = min(luma_m, min(min(luma_nw, luma_ne), min(luma_sw, luma_se)));
+    float luma_max = max(luma_m, max(max(luma_nw, luma_ne), max(luma_sw, luma_se)));
+
+    // Blur along the edge, perpendicular to the luma gradient
+    vec2 direction = vec2(-((luma_nw + luma_ne) - (luma_sw + luma_se)), (luma_nw + luma_sw) - (luma_ne + luma_se));
+    float direction_reduce = max((luma_nw + luma_ne + luma_sw + luma_se) * 0.25 * FXAA_REDUCE_MUL, FXAA_REDUCE_MIN);
+    float inverse_direction_min = 1.0 / (min(abs(direction.x), abs(direction.y)) + direction_reduce);
+    direction = clamp(direction * inverse_direction_min, vec2(-FXAA_SPAN_MAX), vec2(FXAA_SPAN_MAX)) * texel_size;
+
+    vec3 rgb_a = 0.5 * (texture2D(color, v_uv + direction * (1.0 / 3.0 - 0.5)).rgb
+        + texture2D(color, v_uv + direction * (2.0 / 3.0 - 0.5)).rgb);
+    vec3 rgb_b = rgb_a * 0.5 + 0.25 * (texture2D(color, v_uv - direction * 0.5).rgb
+        + texture2D(color, v_uv + direction * 0.5).rgb);
+    float luma_b = dot(rgb_b, luma);
+
+    gl_FragColor = vec4((luma_b < luma_min || luma_b > luma_max) ? rgb_a : rgb_b, 1.0);
+}
@@ -0,0 +1,12 @@
+precision highp float;
+
+varying vec3 v_position;
+varying vec3 v_normal;
+varying vec3 v_color;
+
+void main()
+{
+    vec3 normal = normalize(v_normal);
+
+    gl_FragColor = vec4(normal * 0.5 + 0.5, v_position.z);
+}
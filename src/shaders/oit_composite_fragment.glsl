@@ -0,0 +1,22 @@
+precision mediump float;
+
+uniform sampler2D accumulation;
+uniform sampler2D weights;
+
+varying vec2 v_uv;
+
+void main()
+{
+    vec4 accumulated = texture2D(accumulation, v_uv);
+    float revealage = accumulated.a;
+
+    // Nothing transparent covers this pixel
+    if (revealage >= 1.0) {
+        discard;
+    }
+
+    float weight = texture2D(weights, v_uv).r;
+    vec3 average = accumulated.rgb / max(weight, 0.00001);
+
+    gl_FragColor = vec4(average, 1.0 - revealage);
+}
@@ -0,0 +1,43 @@
+precision highp float;
+
+uniform sampler2D gbuffer;
+uniform vec2 texel_size;
+uniform float thickness;
+uniform vec3 outline_color;
+uniform float depth_threshold;
+uniform float normal_threshold;
+
+varying vec2 v_uv;
+
+float edge(vec4 center, vec2 offset)
+{
+    vec4 neighbour = texture2D(gbuffer, v_uv + offset * texel_size * thickness);
+
+    // Background was cleared to a depth beyond the far plane
+    bool center_background = center.a > 1.0;
+    bool neighbour_background = neighbour.a > 1.0;
+    if (center_background || neighbour_background) {
+        return center_background == neighbour_background ? 0.0 : 1.0;
+    }
+
+    float depth_edge = step(depth_threshold, abs(center.a - neighbour.a));
+
+    vec3 center_normal = normalize(center.rgb * 2.0 - 1.0);
+    vec3 neighbour_normal = normalize(neighbour.rgb * 2.0 - 1.0);
+    float normal_edge = step(normal_threshold, 1.0 - dot(center_normal, neighbour_normal));
+
+    return max(depth_edge, normal_edge);
+}
+
+void main()
+{
+    vec4 center = texture2D(gbuffer, v_uv);
+
+    float result = 0.0;
+    result = max(result, edge(center, vec2(1.0, 0.0)));
+    result = max(result, edge(center, vec2(-1.0, 0.0)));
+    result = max(result, edge(center, vec2(0.0, 1.0)));
+    result = max(result, edge(center, vec2(0.0, -1.0)));
+
+    gl_FragColor = vec4(outline_color, result);
+}
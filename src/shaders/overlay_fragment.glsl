@@ -0,0 +1,11 @@
+precision mediump float;
+
+uniform sampler2D image;
+
+varying vec2 v_uv;
+
+// Overlay images are uploaded top row first
+void main()
+{
+    gl_FragColor = texture2D(image, vec2(v_uv.x, 1.0 - v_uv.y));
+}
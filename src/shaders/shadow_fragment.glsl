@@ -0,0 +1,7 @@
+precision mediump float;
+
+void main()
+{
+    // Only depth is written to the shadow map
+    gl_FragColor = vec4(1.0);
+}
@@ -0,0 +1,51 @@
+precision highp float;
+
+uniform sampler2D gbuffer;
+uniform vec3 samples[64];
+uniform int kernel_size;
+uniform float radius;
+
+varying vec2 v_uv;
+
+float bias = 0.002;
+
+float hash(vec2 point)
+{
+    return fract(sin(dot(point, vec2(12.9898, 78.233))) * 43758.5453);
+}
+
+void main()
+{
+    vec4 data = texture2D(gbuffer, v_uv);
+
+    // Background keeps the far depth it was cleared with
+    if (data.a > 1.0) {
+        gl_FragColor = vec4(1.0);
+        return;
+    }
+
+    vec3 position = vec3(v_uv * 2.0 - 1.0, data.a);
+    vec3 normal = normalize(data.rgb * 2.0 - 1.0);
+
+    // Rotate the kernel per pixel, the blur pass removes the resulting noise
+    vec3 random = vec3(hash(gl_FragCoord.xy) * 2.0 - 1.0, hash(gl_FragCoord.yx + 7.0) * 2.0 - 1.0, 0.0);
+    vec3 tangent = normalize(random - normal * dot(random, normal));
+    vec3 bitangent = cross(normal, tangent);
+    mat3 tbn = mat3(tangent, bitangent, normal);
+
+    float occlusion = 0.0;
+    for (int i = 0; i < 64; i++) {
+        if (i >= kernel_size) {
+            break;
+        }
+
+        vec3 sample_position = position + tbn * samples[i] * radius;
+        float scene_depth = texture2D(gbuffer, sample_position.xy * 0.5 + 0.5).a;
+
+        // Smaller depth is closer to the viewer, ignore surfaces far outside the radius
+        float range = smoothstep(0.0, 1.0, radius / max(abs(position.z - scene_depth), 0.0001));
+        occlusion += (scene_depth <= sample_position.z - bias ? 1.0 : 0.0) * range;
+    }
+
+    gl_FragColor = vec4(vec3(1.0 - occlusion / float(kernel_size)), 1.0);
+}
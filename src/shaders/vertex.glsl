@@ -5,33 +5,31 @@ attribute vec3 color;
 uniform float scale;
 uniform float x_rotate;
 uniform float y_rotate;
+uniform mat4 projection;
+uniform mat4 light_matrix;
 
 varying vec3 v_position;
 varying vec3 v_normal;
 varying vec3 v_color;
-varying mat4 light_dirn;
+varying vec4 v_shadow_coord;
 
-float s_x = sin(y_rotate);
-float c_x = cos(y_rotate);
-
-float s_y = sin(-x_rotate);
-float c_y = cos(-x_rotate);
-
-const float fovy = 90.0;
-float aspect = 16.0 / 18.0;
-float zNear = 0.1;
-float zFar = 100.0;
+void main() {
+  // Uniform dependent values must be computed here, GLSL ES 3.00 only allows constant
+  // global initializers
+  float s_x = sin(y_rotate);
+  float c_x = cos(y_rotate);
 
-mat4 x_mat = mat4(1, 0, 0, 0, 0, c_x, -s_x, 0, 0, s_x, c_x, 0, 0, 0, 0, 1);
+  float s_y = sin(-x_rotate);
+  float c_y = cos(-x_rotate);
 
-mat4 y_mat = mat4(c_y, 0, -s_y, 0, 0, 1, 0, 0, s_y, 0, c_y, 0, 0, 0, 0, 1);
+  mat4 x_mat = mat4(1, 0, 0, 0, 0, c_x, -s_x, 0, 0, s_x, c_x, 0, 0, 0, 0, 1);
+  mat4 y_mat = mat4(c_y, 0, -s_y, 0, 0, 1, 0, 0, s_y, 0, c_y, 0, 0, 0, 0, 1);
 
-void main() {
   vec4 final_position = y_mat * x_mat * vec4(scale * position, 1.0);
-  gl_Position = final_position;
+  gl_Position = projection * final_position;
 
   v_color = color;
-  v_normal = normal;
+  v_normal = vec3(y_mat * x_mat * vec4(normal, 0.0));
   v_position = vec3(final_position);
-  light_dirn = y_mat * x_mat;
+  v_shadow_coord = light_matrix * final_position;
 }
@@ -0,0 +1,147 @@
+use crate::gl;
+use crate::math::{cross, normalize};
+
+/// Directional key light, fixed in view space so it does not turn with the molecule.
+#[derive(Debug, Clone, Copy)]
+pub struct LightSettings {
+    /// Direction pointing towards the light, in view space (-z faces the viewer).
+    pub direction: [f32; 3],
+    pub color: [f32; 3],
+    pub shadows: bool,
+    /// Width and height of the shadow map in texels.
+    pub shadow_resolution: i32,
+    /// Half width, in texels, of the percentage closer filtering kernel.
+    pub softness: i32,
+}
+
+impl Default for LightSettings {
+    fn default() -> Self {
+        Self {
+            direction: [-0.5, 0.5, -1.0],
+            color: [0.5, 0.5, 0.5],
+            shadows: false,
+            shadow_resolution: 2048,
+            softness: 2,
+        }
+    }
+}
+
+/// Half extent of the region covered by the shadow map. The visible clip space cube fits
+/// inside, whatever the light direction.
+pub const SHADOW_RADIUS: f32 = 2.0;
+
+/// Depth only render target the scene is drawn into from the light's point of view.
+pub struct ShadowMap {
+    pub fbo: gl::types::GLuint,
+    pub texture: gl::types::GLuint,
+    pub resolution: i32,
+}
+
+impl ShadowMap {
+    pub fn new(gl: &gl::Gl, resolution: i32) -> ShadowMap {
+        let mut shadow_map = ShadowMap {
+            fbo: 0,
+            texture: 0,
+            resolution,
+        };
+
+        unsafe {
+            gl.GenFramebuffers(1, &mut shadow_map.fbo);
+            gl.GenTextures(1, &mut shadow_map.texture);
+
+            shadow_map.resize(gl, resolution);
+
+            gl.BindFramebuffer(gl::FRAMEBUFFER, shadow_map.fbo);
+            gl.FramebufferTexture2D(
+                gl::FRAMEBUFFER,
+                gl::DEPTH_ATTACHMENT,
+                gl::TEXTURE_2D,
+                shadow_map.texture,
+                0,
+            );
+            gl.DrawBuffers(1, &gl::NONE);
+            gl.ReadBuffer(gl::NONE);
+
+            if gl.CheckFramebufferStatus(gl::FRAMEBUFFER) != gl::FRAMEBUFFER_COMPLETE {
+                eprintln!("Shadow map framebuffer {} is incomplete", shadow_map.fbo);
+            }
+
+            gl.BindFramebuffer(gl::FRAMEBUFFER, 0);
+        }
+
+        shadow_map
+    }
+
+    /// Reallocates the depth texture, discarding its contents.
+    pub fn resize(&mut self, gl: &gl::Gl, resolution: i32) {
+        self.resolution = resolution;
+
+        unsafe {
+            gl.BindTexture(gl::TEXTURE_2D, self.texture);
+            gl.TexImage2D(
+                gl::TEXTURE_2D,
+                0,
+                gl::DEPTH_COMPONENT24 as i32,
+                resolution,
+                resolution,
+                0,
+                gl::DEPTH_COMPONENT,
+                gl::UNSIGNED_INT,
+                std::ptr::null(),
+            );
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MIN_FILTER, gl::NEAREST as i32);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_MAG_FILTER, gl::NEAREST as i32);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_S, gl::CLAMP_TO_EDGE as i32);
+            gl.TexParameteri(gl::TEXTURE_2D, gl::TEXTURE_WRAP_T, gl::CLAMP_TO_EDGE as i32);
+            gl.BindTexture(gl::TEXTURE_2D, 0);
+        }
+    }
+
+    pub fn bind(&self, gl: &gl::Gl) {
+        unsafe {
+            gl.BindFramebuffer(gl::FRAMEBUFFER, self.fbo);
+            gl.Viewport(0, 0, self.resolution, self.resolution);
+        }
+    }
+
+    pub fn delete(&self, gl: &gl::Gl) {
+        unsafe {
+            gl.DeleteTextures(1, &self.texture);
+            gl.DeleteFramebuffers(1, &self.fbo);
+        }
+    }
+}
+
+/// Column major orthographic view-projection of a directional light shining along
+/// `-direction`, covering a cube of half extent `radius` around the view space origin.
+/// Depth grows away from the light, like the main pass.
+pub fn light_matrix(direction: [f32; 3], radius: f32) -> [f32; 16] {
+    let forward = normalize([-direction[0], -direction[1], -direction[2]]);
+    let up = if forward[1].abs() > 0.99 {
+        [1.0, 0.0, 0.0]
+    } else {
+        [0.0, 1.0, 0.0]
+    };
+    let right = normalize(cross(up, forward));
+    let up = cross(forward, right);
+
+    let mut matrix = [0.0; 16];
+    for (row, axis) in [right, up, forward].iter().enumerate() {
+        for column in 0..3 {
+            matrix[column * 4 + row] = axis[column] / radius;
+        }
+    }
+    matrix[15] = 1.0;
+
+    matrix
+}
+
+#[test]
+fn light_matrix_puts_light_side_closest() {
+    let matrix = light_matrix([0.0, 0.0, -1.0], SHADOW_RADIUS);
+    let depth = |z: f32| matrix[10] * z + matrix[14];
+
+    // The light sits in front of the viewer, so points nearer the viewer are nearer the light
+    assert!(depth(-1.0) < depth(1.0));
+    assert!((depth(-SHADOW_RADIUS) + 1.0).abs() < 1e-6);
+}
@@ -0,0 +1,323 @@
+use crate::math::distance;
+
+/// Most cells allotted per point, so that sparse or tiny-celled grids stay small.
+const CELLS_PER_POINT: usize = 8;
+
+/// Points binned into cubic cells, a cell list. Radius queries only visit the cells overlapping
+/// the query sphere, which makes neighbour searches over a whole structure close to linear.
+#[derive(Debug, Clone)]
+pub struct Grid {
+    positions: Vec<[f32; 3]>,
+    origin: [f32; 3],
+    cell: f32,
+    dims: [usize; 3],
+    /// Where each cell's run of points starts in `order`, with the total count appended.
+    starts: Vec<usize>,
+    order: Vec<usize>,
+}
+
+impl Grid {
+    /// Bins `positions` into cells `cell` wide. Queries are fastest when the cell size is close
+    /// to the usual query radius.
+    pub fn new(positions: &[[f32; 3]], cell: f32) -> Grid {
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for position in positions {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(position[axis]);
+                max[axis] = max[axis].max(position[axis]);
+            }
+        }
+        if positions.is_empty() {
+            (min, max) = ([0.0; 3], [0.0; 3]);
+        }
+
+        let mut cell = cell.max(f32::EPSILON);
+        let dims = loop {
+            let dims = [0, 1, 2].map(|axis| ((max[axis] - min[axis]) / cell) as usize + 1);
+            if dims.iter().product::<usize>() <= CELLS_PER_POINT * positions.len().max(1) {
+                break dims;
+            }
+            cell *= 1.5;
+        };
+
+        let mut grid = Grid {
+            positions: positions.to_vec(),
+            origin: min,
+            cell,
+            dims,
+            starts: vec![0; dims.iter().product::<usize>() + 1],
+            order: vec![0; positions.len()],
+        };
+
+        // Counting sort of the points by cell
+        let cells = positions
+            .iter()
+            .map(|&position| grid.index(grid.cell_of(position).map(|value| value as usize)))
+            .collect::<Vec<_>>();
+        for &cell in &cells {
+            grid.starts[cell + 1] += 1;
+        }
+        for cell in 1..grid.starts.len() {
+            grid.starts[cell] += grid.starts[cell - 1];
+        }
+        let mut next = grid.starts.clone();
+        for (point, &cell) in cells.iter().enumerate() {
+            grid.order[next[cell]] = point;
+            next[cell] += 1;
+        }
+
+        grid
+    }
+
+    pub fn len(&self) -> usize {
+        self.positions.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.positions.is_empty()
+    }
+
+    pub fn position(&self, index: usize) -> [f32; 3] {
+        self.positions[index]
+    }
+
+    /// Cell coordinates of `point`, which lie outside the grid for points outside it.
+    fn cell_of(&self, point: [f32; 3]) -> [isize; 3] {
+        [0, 1, 2].map(|axis| {
+            let cell = ((point[axis] - self.origin[axis]) / self.cell).floor() as isize;
+            cell.min(self.dims[axis] as isize - 1)
+        })
+    }
+
+    fn index(&self, [x, y, z]: [usize; 3]) -> usize {
+        (z * self.dims[1] + y) * self.dims[0] + x
+    }
+
+    /// Calls `visit` with the index and distance of every point within `radius` of `point`.
+    pub fn for_each_within(&self, point: [f32; 3], radius: f32, mut visit: impl FnMut(usize, f32)) {
+        let low = self.cell_of(point.map(|value| value - radius));
+        let high = self.cell_of(point.map(|value| value + radius));
+        let range = |axis: usize| {
+            let start = low[axis].max(0) as usize;
+            let end = (high[axis] + 1).clamp(0, self.dims[axis] as isize) as usize;
+            start..end
+        };
+
+        for z in range(2) {
+            for y in range(1) {
+                let row = self.index([0, y, z]);
+                let cells = range(0);
+                let run =
+                    self.starts[row + cells.start]..self.starts[row + cells.end.max(cells.start)];
+                for &other in &self.order[run] {
+                    let length = distance(point, self.positions[other]);
+                    if length <= radius {
+                        visit(other, length);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Indices of the points within `radius` of `point`, in no particular order.
+    pub fn within(&self, point: [f32; 3], radius: f32) -> Vec<usize> {
+        let mut found = Vec::new();
+        self.for_each_within(point, radius, |index, _| found.push(index));
+        found
+    }
+
+    /// The `k` points nearest to `point` with their distances, nearest first.
+    pub fn nearest(&self, point: [f32; 3], k: usize) -> Vec<(usize, f32)> {
+        let k = k.min(self.len());
+        if k == 0 {
+            return Vec::new();
+        }
+
+        // Every point within the search radius is found, so once it holds k points it holds
+        // the k nearest
+        let span = (0..3)
+            .map(|axis| self.dims[axis] as f32 * self.cell)
+            .fold(0.0, f32::max);
+        let far = (0..3)
+            .map(|axis| (point[axis] - self.origin[axis]).abs())
+            .fold(0.0, f32::max);
+        let mut radius = self.cell;
+        loop {
+            let mut found = Vec::new();
+            self.for_each_within(point, radius, |index, length| found.push((index, length)));
+            if found.len() >= k || radius > 2.0 * (span + far) {
+                found.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+                found.truncate(k);
+                return found;
+            }
+            radius *= 2.0;
+        }
+    }
+
+    /// Every pair of points closer than `radius`, as `(i, j, distance)` with `i < j`, sorted.
+    pub fn pairs(&self, radius: f32) -> Vec<(usize, usize, f32)> {
+        let mut pairs = Vec::new();
+        for (index, &position) in self.positions.iter().enumerate() {
+            self.for_each_within(position, radius, |other, length| {
+                if other > index {
+                    pairs.push((index, other, length));
+                }
+            });
+        }
+        pairs.sort_unstable_by_key(|&(i, j, _)| (i, j));
+
+        pairs
+    }
+}
+
+/// Balanced k-d tree, an alternative to `Grid` for k-nearest queries on unevenly spread points,
+/// where no single cell size suits every region.
+#[derive(Debug, Clone)]
+pub struct KdTree {
+    positions: Vec<[f32; 3]>,
+    /// Point indices laid out so that each subtree's root is the median of its slice.
+    order: Vec<usize>,
+}
+
+impl KdTree {
+    pub fn new(positions: &[[f32; 3]]) -> KdTree {
+        let mut order = (0..positions.len()).collect::<Vec<_>>();
+        build(positions, &mut order, 0);
+
+        KdTree {
+            positions: positions.to_vec(),
+            order,
+        }
+    }
+
+    pub fn within(&self, point: [f32; 3], radius: f32) -> Vec<usize> {
+        let mut found = Vec::new();
+        self.search(&self.order, 0, point, &mut |index, length| {
+            if length <= radius {
+                found.push(index);
+            }
+            radius
+        });
+        found
+    }
+
+    /// The `k` points nearest to `point` with their distances, nearest first.
+    pub fn nearest(&self, point: [f32; 3], k: usize) -> Vec<(usize, f32)> {
+        let mut best = Vec::<(usize, f32)>::with_capacity(k + 1);
+        if k == 0 {
+            return best;
+        }
+        self.search(&self.order, 0, point, &mut |index, length| {
+            let slot = best.partition_point(|&(other, other_length)| {
+                other_length < length || (other_length == length && other < index)
+            });
+            if slot < k {
+                best.insert(slot, (index, length));
+                best.truncate(k);
+            }
+            if best.len() == k {
+                best[k - 1].1
+            } else {
+                f32::INFINITY
+            }
+        });
+        best
+    }
+
+    /// Visits points nearest side first; `visit` returns the current search radius, which
+    /// prunes subtrees lying entirely beyond it.
+    fn search(
+        &self,
+        slice: &[usize],
+        depth: usize,
+        point: [f32; 3],
+        visit: &mut impl FnMut(usize, f32) -> f32,
+    ) -> f32 {
+        if slice.is_empty() {
+            return f32::INFINITY;
+        }
+
+        let axis = depth % 3;
+        let middle = slice.len() / 2;
+        let root = slice[middle];
+        let mut radius = visit(root, distance(point, self.positions[root]));
+
+        let split = point[axis] - self.positions[root][axis];
+        let (near, far) = if split < 0.0 {
+            (&slice[..middle], &slice[middle + 1..])
+        } else {
+            (&slice[middle + 1..], &slice[..middle])
+        };
+        radius = radius.min(self.search(near, depth + 1, point, visit));
+        if split.abs() <= radius {
+            radius = radius.min(self.search(far, depth + 1, point, visit));
+        }
+
+        radius
+    }
+}
+
+fn build(positions: &[[f32; 3]], order: &mut [usize], depth: usize) {
+    if order.len() <= 1 {
+        return;
+    }
+
+    let axis = depth % 3;
+    let middle = order.len() / 2;
+    order.select_nth_unstable_by(middle, |&a, &b| {
+        positions[a][axis].total_cmp(&positions[b][axis])
+    });
+    let (low, high) = order.split_at_mut(middle);
+    build(positions, low, depth + 1);
+    build(positions, &mut high[1..], depth + 1);
+}
+
+#[test]
+fn grid_and_tree_match_brute_force() {
+    use crate::scene::Scene;
+    use crate::style::Style;
+
+    let scene = Scene::open("1d66.pdb", &Style::default()).unwrap();
+    let positions = scene
+        .structure
+        .as_ref()
+        .unwrap()
+        .atoms
+        .iter()
+        .map(|atom| atom.position)
+        .collect::<Vec<_>>();
+    let grid = Grid::new(&positions, 4.0);
+    let tree = KdTree::new(&positions);
+
+    for &query in positions.iter().step_by(97).chain(&[[100.0, -50.0, 3.0]]) {
+        let mut brute = (0..positions.len())
+            .filter(|&index| distance(query, positions[index]) <= 6.5)
+            .collect::<Vec<_>>();
+        let mut found = grid.within(query, 6.5);
+        let mut from_tree = tree.within(query, 6.5);
+        found.sort_unstable();
+        from_tree.sort_unstable();
+        brute.sort_unstable();
+        assert_eq!(found, brute);
+        assert_eq!(from_tree, brute);
+
+        let mut nearest = (0..positions.len())
+            .map(|index| (index, distance(query, positions[index])))
+            .collect::<Vec<_>>();
+        nearest.sort_by(|a, b| a.1.total_cmp(&b.1).then(a.0.cmp(&b.0)));
+        nearest.truncate(5);
+        assert_eq!(grid.nearest(query, 5), nearest);
+        assert_eq!(tree.nearest(query, 5), nearest);
+    }
+
+    let pairs = grid.pairs(2.0);
+    let brute = (0..positions.len())
+        .flat_map(|i| (i + 1..positions.len()).map(move |j| (i, j)))
+        .filter(|&(i, j)| distance(positions[i], positions[j]) <= 2.0)
+        .count();
+    assert_eq!(pairs.len(), brute);
+    assert!(pairs
+        .windows(2)
+        .all(|pair| (pair[0].0, pair[0].1) < (pair[1].0, pair[1].1)));
+}
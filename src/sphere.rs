@@ -9,6 +9,7 @@ pub struct Sphere {
     pub colors: Vec<f32>,
 
     color: [f32; 3],
+    opacity: f32,
     sector_count: u32,
     stack_count: u32,
     radius: f32,
@@ -24,6 +25,7 @@ impl Sphere {
             indices: vec![],
             colors: vec![],
             color,
+            opacity: 1.0,
             sector_count,
             stack_count,
             radius,
@@ -34,10 +36,10 @@ impl Sphere {
         sphere.generate_indices();
         sphere.interlaced_vertices_generator();
 
-        return sphere;
+        sphere
     }
 
-    fn generate_vertices(&mut self) -> () {
+    fn generate_vertices(&mut self) {
         let mut x;
         let mut y;
         let mut z;
@@ -77,12 +79,14 @@ impl Sphere {
         }
     }
 
-    fn generate_indices(&mut self) -> () {
+    fn generate_indices(&mut self) {
         for i in 0..self.stack_count {
-            let mut k1 = i * (self.sector_count + 1);
-            let mut k2 = k1 + self.sector_count + 1;
+            let row = i * (self.sector_count + 1);
 
             for j in 0..self.sector_count {
+                let k1 = row + j;
+                let k2 = k1 + self.sector_count + 1;
+
                 if i != 0 {
                     self.indices.push(k1);
                     self.indices.push(k2);
@@ -94,8 +98,6 @@ impl Sphere {
                     self.indices.push(k2);
                     self.indices.push(k2 + 1);
                 }
-                k1 = k1 + 1;
-                k2 = k2 + 1;
             }
         }
     }
@@ -106,7 +108,7 @@ impl Sphere {
             .chunks(3)
             .zip(self.normal_vertices().chunks(3))
             .zip(self.colors().chunks(3))
-            .flat_map(|(a, b)| a.0.into_iter().chain(a.1).chain(b))
+            .flat_map(|(a, b)| a.0.iter().chain(a.1).chain(b))
             .copied()
             .collect::<Vec<f32>>();
     }
@@ -114,30 +116,38 @@ impl Sphere {
 
 impl Object for Sphere {
     fn indices(&self) -> &Vec<u32> {
-        return &self.indices;
+        &self.indices
     }
 
     fn vertices_mut(&mut self) -> &mut Vec<f32> {
-        return &mut self.vertices;
+        &mut self.vertices
     }
 
     fn vertices(&self) -> &Vec<f32> {
-        return &self.vertices;
+        &self.vertices
     }
 
     fn colors(&self) -> &Vec<f32> {
-        return &self.colors;
+        &self.colors
     }
 
     fn normal_vertices(&self) -> &Vec<f32> {
-        return &self.normal_vertices;
+        &self.normal_vertices
     }
 
     fn interlaced_vertices(&self) -> &Vec<f32> {
-        return &self.interlaced_vertices;
+        &self.interlaced_vertices
     }
 
     fn generate_interlaced_vertices(&mut self) {
         self.interlaced_vertices_generator();
     }
+
+    fn opacity(&self) -> f32 {
+        self.opacity
+    }
+
+    fn set_opacity(&mut self, opacity: f32) {
+        self.opacity = opacity.clamp(0.0, 1.0);
+    }
 }
@@ -0,0 +1,126 @@
+use crate::framebuffer::{Framebuffer, TextureFormat};
+use crate::gl;
+
+/// Upper bound on the sample kernel, matching the `samples` array in `ssao_fragment.glsl`.
+pub const MAX_KERNEL_SIZE: usize = 64;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SsaoQuality {
+    Low,
+    Medium,
+    High,
+}
+
+impl SsaoQuality {
+    pub fn kernel_size(self) -> usize {
+        match self {
+            SsaoQuality::Low => 8,
+            SsaoQuality::Medium => 24,
+            SsaoQuality::High => MAX_KERNEL_SIZE,
+        }
+    }
+
+    /// Half width, in pixels, of the box blur applied to the raw occlusion.
+    pub fn blur_radius(self) -> i32 {
+        match self {
+            SsaoQuality::Low => 1,
+            SsaoQuality::Medium => 2,
+            SsaoQuality::High => 3,
+        }
+    }
+
+    pub fn next(self) -> SsaoQuality {
+        match self {
+            SsaoQuality::Low => SsaoQuality::Medium,
+            SsaoQuality::Medium => SsaoQuality::High,
+            SsaoQuality::High => SsaoQuality::Low,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+pub struct SsaoSettings {
+    pub enabled: bool,
+    pub quality: SsaoQuality,
+    /// Sampling radius in clip space units.
+    pub radius: f32,
+}
+
+impl Default for SsaoSettings {
+    fn default() -> Self {
+        Self {
+            enabled: false,
+            quality: SsaoQuality::Medium,
+            radius: 0.05,
+        }
+    }
+}
+
+/// GPU resources of the ambient occlusion pass: the raw occlusion target, computed from the
+/// renderer's normal/depth prepass, and its blurred copy, which the lighting pass samples.
+pub struct Ssao {
+    pub occlusion: Framebuffer,
+    pub blurred: Framebuffer,
+    pub kernel: Vec<f32>,
+}
+
+impl Ssao {
+    pub fn new(gl: &gl::Gl, width: i32, height: i32) -> Ssao {
+        Ssao {
+            occlusion: Framebuffer::new(gl, width, height, TextureFormat::R8, false),
+            blurred: Framebuffer::new(gl, width, height, TextureFormat::R8, false),
+            kernel: generate_kernel(MAX_KERNEL_SIZE)
+                .into_iter()
+                .flatten()
+                .collect(),
+        }
+    }
+
+    pub fn resize(&self, gl: &gl::Gl, width: i32, height: i32) {
+        self.occlusion.resize(gl, width, height);
+        self.blurred.resize(gl, width, height);
+    }
+
+    pub fn delete(&self, gl: &gl::Gl) {
+        self.occlusion.delete(gl);
+        self.blurred.delete(gl);
+    }
+}
+
+/// Sample offsets inside the unit hemisphere around +z, denser towards the origin so that
+/// nearby geometry contributes more occlusion than distant geometry.
+pub fn generate_kernel(size: usize) -> Vec<[f32; 3]> {
+    let mut seed: u32 = 0x9e37_79b9;
+    let mut random = move || {
+        seed ^= seed << 13;
+        seed ^= seed >> 17;
+        seed ^= seed << 5;
+        seed as f32 / u32::MAX as f32
+    };
+
+    (0..size)
+        .map(|i| {
+            let x = random() * 2.0 - 1.0;
+            let y = random() * 2.0 - 1.0;
+            let z = random();
+            let length = (x * x + y * y + z * z).sqrt().max(f32::EPSILON);
+
+            let t = i as f32 / size as f32;
+            let scale = (0.1 + 0.9 * t * t) * random();
+
+            [x / length * scale, y / length * scale, z / length * scale]
+        })
+        .collect()
+}
+
+#[test]
+fn kernel_stays_in_hemisphere() {
+    let kernel = generate_kernel(MAX_KERNEL_SIZE);
+
+    assert_eq!(kernel.len(), MAX_KERNEL_SIZE);
+    for sample in kernel {
+        let length = (sample[0].powi(2) + sample[1].powi(2) + sample[2].powi(2)).sqrt();
+        assert!(sample[2] >= 0.0);
+        assert!(length <= 1.0);
+    }
+}
@@ -0,0 +1,197 @@
+use std::str::FromStr;
+
+use pdbtbx::Element;
+
+use crate::scene::{SPHERE_SECTOR, SPHERE_STACK};
+use crate::selection::Selection;
+
+/// How the atoms of a structure are turned into geometry.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Representation {
+    /// One sphere per atom.
+    #[default]
+    Spacefill,
+    /// Small atom spheres joined by bond cylinders.
+    BallAndStick,
+    /// A tube through the alpha carbons of every chain.
+    Cartoon,
+    /// Fused van der Waals spheres, approximating the molecular surface.
+    Surface,
+}
+
+/// Number of `Representation` variants, the size of per-atom visibility arrays.
+pub const REPRESENTATION_COUNT: usize = 4;
+
+impl FromStr for Representation {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "spacefill" | "cpk" => Ok(Representation::Spacefill),
+            "ball-and-stick" | "ballandstick" | "sticks" => Ok(Representation::BallAndStick),
+            "cartoon" | "trace" => Ok(Representation::Cartoon),
+            "surface" => Ok(Representation::Surface),
+            _ => Err(format!(
+                "Unknown representation '{}', expected spacefill, ball-and-stick, cartoon or surface",
+                name
+            )),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub enum ColorScheme {
+    #[default]
+    Element,
+    Chain,
+    /// Amino acid class: hydrophobic, polar, acidic or basic.
+    Residue,
+    /// Blue for the lowest temperature factor through white to red for the highest.
+    BFactor,
+    /// Blue for buried through white to red for solvent exposed residues.
+    Accessibility,
+    Uniform([f32; 3]),
+}
+
+impl FromStr for ColorScheme {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "element" | "cpk" => Ok(ColorScheme::Element),
+            "chain" => Ok(ColorScheme::Chain),
+            "residue" => Ok(ColorScheme::Residue),
+            "bfactor" | "b-factor" => Ok(ColorScheme::BFactor),
+            "accessibility" | "sasa" => Ok(ColorScheme::Accessibility),
+            _ => parse_color(name).map(ColorScheme::Uniform).map_err(|_| {
+                format!(
+                    "Unknown color scheme '{}', expected element, chain, residue, bfactor, accessibility or a color",
+                    name
+                )
+            }),
+        }
+    }
+}
+
+/// Everything that decides the geometry and colors built for a structure.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Style {
+    pub representation: Representation,
+    pub color_scheme: ColorScheme,
+    /// Atoms to show, `None` shows every non-hetero atom.
+    pub selection: Option<Selection>,
+    /// Sector and stack count of every sphere.
+    pub sphere_detail: (u32, u32),
+}
+
+impl Default for Style {
+    fn default() -> Self {
+        Self {
+            representation: Representation::default(),
+            color_scheme: ColorScheme::default(),
+            selection: None,
+            sphere_detail: (SPHERE_SECTOR, SPHERE_STACK),
+        }
+    }
+}
+
+pub fn element_color(element: &Element) -> [f32; 3] {
+    match element {
+        Element::O => [1.0, 0.0, 1.0],
+        Element::C => [0.0, 1.0, 0.0],
+        Element::N => [0.0, 0.0, 1.0],
+        Element::H => [1.0, 0.0, 0.0],
+        _ => [1.0, 1.0, 1.0],
+    }
+}
+
+const CHAIN_PALETTE: [[f32; 3]; 8] = [
+    [0.40, 0.76, 0.65],
+    [0.99, 0.55, 0.38],
+    [0.55, 0.63, 0.80],
+    [0.91, 0.54, 0.76],
+    [0.65, 0.85, 0.33],
+    [1.00, 0.85, 0.18],
+    [0.90, 0.77, 0.58],
+    [0.70, 0.70, 0.70],
+];
+
+/// Color of the `index`th chain of a structure, cycling through a fixed palette.
+pub fn chain_color(index: usize) -> [f32; 3] {
+    CHAIN_PALETTE[index % CHAIN_PALETTE.len()]
+}
+
+pub fn residue_color(name: &str) -> [f32; 3] {
+    match name {
+        "ALA" | "VAL" | "LEU" | "ILE" | "MET" | "PHE" | "TRP" | "PRO" | "GLY" => [0.75, 0.75, 0.75],
+        "ASP" | "GLU" => [0.90, 0.20, 0.20],
+        "LYS" | "ARG" | "HIS" => [0.20, 0.35, 0.90],
+        "SER" | "THR" | "ASN" | "GLN" | "CYS" | "TYR" => [0.30, 0.80, 0.40],
+        _ => [1.0, 0.85, 0.40],
+    }
+}
+
+/// Blue-white-red ramp over `t` in 0..=1.
+pub fn gradient_color(t: f32) -> [f32; 3] {
+    let t = t.clamp(0.0, 1.0);
+    if t < 0.5 {
+        let s = t * 2.0;
+        [s, s, 1.0]
+    } else {
+        let s = (1.0 - t) * 2.0;
+        [1.0, s, s]
+    }
+}
+
+/// Parses `#rrggbb`, `r,g,b` with components in 0..=1, or one of a few color names.
+pub fn parse_color(value: &str) -> Result<[f32; 3], String> {
+    let invalid = || format!("Invalid color '{}'", value);
+
+    let named = match value.to_ascii_lowercase().as_str() {
+        "black" => Some([0.0, 0.0, 0.0]),
+        "white" => Some([1.0, 1.0, 1.0]),
+        "grey" | "gray" => Some([0.5, 0.5, 0.5]),
+        "red" => Some([1.0, 0.0, 0.0]),
+        "green" => Some([0.0, 1.0, 0.0]),
+        "blue" => Some([0.0, 0.0, 1.0]),
+        "yellow" => Some([1.0, 1.0, 0.0]),
+        "orange" => Some([1.0, 0.5, 0.0]),
+        _ => None,
+    };
+    if let Some(color) = named {
+        return Ok(color);
+    }
+
+    if let Some(hex) = value.strip_prefix('#') {
+        if hex.len() != 6 || !hex.is_ascii() {
+            return Err(invalid());
+        }
+        let channel = |index: usize| {
+            u8::from_str_radix(&hex[index..index + 2], 16)
+                .map(|channel| channel as f32 / 255.0)
+                .map_err(|_| invalid())
+        };
+        return Ok([channel(0)?, channel(2)?, channel(4)?]);
+    }
+
+    let components = value
+        .split(',')
+        .map(|component| component.trim().parse::<f32>().map_err(|_| invalid()))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    match components.as_slice() {
+        [red, green, blue] => Ok([*red, *green, *blue]),
+        _ => Err(invalid()),
+    }
+}
+
+#[test]
+fn parses_colors_and_schemes() {
+    assert_eq!(parse_color("#ff0080"), Ok([1.0, 0.0, 128.0 / 255.0]));
+    assert_eq!(parse_color("0.1, 0.2,0.3"), Ok([0.1, 0.2, 0.3]));
+    assert!(parse_color("#fff").is_err());
+    assert!(parse_color("#a€bb").is_err());
+    assert_eq!("white".parse(), Ok(ColorScheme::Uniform([1.0; 3])));
+    assert_eq!("B-Factor".parse(), Ok(ColorScheme::BFactor));
+    assert_eq!("ball-and-stick".parse(), Ok(Representation::BallAndStick));
+}
@@ -0,0 +1,401 @@
+use std::collections::HashMap;
+use std::str::FromStr;
+
+use pdbtbx::TransformationMatrix;
+
+use crate::json::Json;
+use crate::scene::Structure;
+use crate::selection::Selection;
+
+/// Colors the two structures are overlaid in.
+pub const REFERENCE_COLOR: [f32; 3] = [0.35, 0.6, 1.0];
+pub const MOBILE_COLOR: [f32; 3] = [1.0, 0.55, 0.2];
+/// Fewest matched atoms a rotation is fitted to.
+const MIN_PAIRS: usize = 3;
+
+/// Which matched atoms the fit uses.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum Fit {
+    /// Alpha carbons of the selected residues.
+    #[default]
+    Alpha,
+    /// Every selected atom.
+    Selection,
+}
+
+impl FromStr for Fit {
+    type Err = String;
+
+    fn from_str(name: &str) -> Result<Self, Self::Err> {
+        match name.to_ascii_lowercase().as_str() {
+            "ca" | "alpha" => Ok(Fit::Alpha),
+            "selection" | "atoms" => Ok(Fit::Selection),
+            _ => Err(format!("Unknown fit '{}', expected ca or selection", name)),
+        }
+    }
+}
+
+/// How a structure was placed onto a reference.
+#[derive(Debug, Clone)]
+pub struct Superposition {
+    /// Matched atoms as indices into the reference's and the mobile structure's `atoms`.
+    pub pairs: Vec<(usize, usize)>,
+    /// Root mean square deviation of the matched atoms after the fit, in Å.
+    pub rmsd: f32,
+    /// Maps the mobile structure's file coordinates onto the reference's.
+    pub transformation: TransformationMatrix,
+}
+
+/// Atoms of `structure` the fit may use: those in `mask`, only alpha carbons unless fitting
+/// the whole selection.
+fn candidates(structure: &Structure, mask: &[bool], fit: Fit) -> Vec<usize> {
+    (0..structure.atoms.len())
+        .filter(|&index| {
+            mask[index] && (fit == Fit::Selection || structure.atoms[index].name == "CA")
+        })
+        .collect()
+}
+
+/// Atoms of `selection` in both structures paired by chain, residue number and atom name,
+/// as `(reference, mobile)` indices. Residues whose names differ are left out.
+pub fn match_atoms(
+    reference: &Structure,
+    mobile: &Structure,
+    selection: &Selection,
+    fit: Fit,
+) -> Vec<(usize, usize)> {
+    let key = |structure: &Structure, index: usize| {
+        let atom = &structure.atoms[index];
+        (
+            atom.chain_id.clone(),
+            atom.residue_number,
+            atom.residue_name.clone(),
+            atom.name.clone(),
+        )
+    };
+
+    let mut mobile_atoms = HashMap::new();
+    for index in candidates(mobile, &mobile.select(selection), fit) {
+        mobile_atoms.entry(key(mobile, index)).or_insert(index);
+    }
+    candidates(reference, &reference.select(selection), fit)
+        .into_iter()
+        .filter_map(|index| Some((index, *mobile_atoms.get(&key(reference, index))?)))
+        .collect()
+}
+
+/// Like `match_atoms`, with residues paired by `residues` instead of by numbering, e.g. from a
+/// sequence alignment. Each pair holds the index of any atom of a reference residue and of
+/// the mobile residue it corresponds to; `selection` only applies to the reference.
+pub fn match_residues(
+    reference: &Structure,
+    mobile: &Structure,
+    selection: &Selection,
+    fit: Fit,
+    residues: &[(usize, usize)],
+) -> Vec<(usize, usize)> {
+    let counterparts = residues
+        .iter()
+        .map(|&(first, second)| (mobile.atoms[second].residue, reference.atoms[first].residue))
+        .collect::<HashMap<_, _>>();
+
+    let mut mobile_atoms = HashMap::new();
+    for index in candidates(mobile, &vec![true; mobile.atoms.len()], fit) {
+        let atom = &mobile.atoms[index];
+        if let Some(&residue) = counterparts.get(&atom.residue) {
+            mobile_atoms.entry((residue, &atom.name)).or_insert(index);
+        }
+    }
+    candidates(reference, &reference.select(selection), fit)
+        .into_iter()
+        .filter_map(|index| {
+            let atom = &reference.atoms[index];
+            Some((index, *mobile_atoms.get(&(atom.residue, &atom.name))?))
+        })
+        .collect()
+}
+
+/// Fits `mobile` onto `reference` over the matched `pairs`, records the transformation in
+/// `mobile.superposition` and moves its atoms over the reference's in the view.
+pub fn superpose(
+    reference: &Structure,
+    mobile: &mut Structure,
+    pairs: Vec<(usize, usize)>,
+) -> Result<Superposition, String> {
+    if pairs.len() < MIN_PAIRS {
+        return Err(format!(
+            "Only {} atoms match between the structures, at least {} are needed",
+            pairs.len(),
+            MIN_PAIRS
+        ));
+    }
+
+    let reference_file = file_positions(reference);
+    let mobile_file = file_positions(mobile);
+    let (rotation, translation) = kabsch(
+        &pairs
+            .iter()
+            .map(|&(index, _)| reference_file[index])
+            .collect::<Vec<_>>(),
+        &pairs
+            .iter()
+            .map(|&(_, index)| mobile_file[index])
+            .collect::<Vec<_>>(),
+    );
+    let transformation = TransformationMatrix::from_matrix([0, 1, 2].map(|row| {
+        [
+            rotation[row][0],
+            rotation[row][1],
+            rotation[row][2],
+            translation[row],
+        ]
+    }));
+    place(reference, mobile, &transformation);
+
+    let squared = pairs
+        .iter()
+        .map(|&(reference_index, mobile_index)| {
+            let moved = transform(&transformation, mobile_file[mobile_index]);
+            (0..3)
+                .map(|axis| (moved[axis] - reference_file[reference_index][axis]).powi(2))
+                .sum::<f64>()
+        })
+        .sum::<f64>();
+
+    Ok(Superposition {
+        rmsd: (squared / pairs.len() as f64).sqrt() as f32,
+        pairs,
+        transformation,
+    })
+}
+
+/// Moves the atoms of `mobile` by `transformation` from their file coordinates onto the
+/// reference's in the view and records it in `mobile.superposition`.
+pub fn place(reference: &Structure, mobile: &mut Structure, transformation: &TransformationMatrix) {
+    // The reference is displayed centered, shifted from its file coordinates
+    let offset = match (reference.atoms.first(), reference.hierarchy().next()) {
+        (Some(atom), Some((_, _, file))) => [
+            atom.position[0] as f64 - file.x(),
+            atom.position[1] as f64 - file.y(),
+            atom.position[2] as f64 - file.z(),
+        ],
+        _ => [0.0; 3],
+    };
+    let file = file_positions(mobile);
+    for (atom, position) in mobile.atoms.iter_mut().zip(file) {
+        let moved = transform(transformation, position);
+        atom.position = [0, 1, 2].map(|axis| (moved[axis] + offset[axis]) as f32);
+    }
+    // Contacts found before the move no longer line up with the atoms
+    mobile.contacts.clear();
+    mobile.superposition = Some(transformation.clone());
+}
+
+fn transform(transformation: &TransformationMatrix, [x, y, z]: [f64; 3]) -> [f64; 3] {
+    let (x, y, z) = transformation.apply((x, y, z));
+    [x, y, z]
+}
+
+/// Atom positions as read from the file, aligned with `structure.atoms`.
+fn file_positions(structure: &Structure) -> Vec<[f64; 3]> {
+    structure
+        .hierarchy()
+        .map(|(_, _, atom)| [atom.x(), atom.y(), atom.z()])
+        .collect()
+}
+
+/// Rotation and translation that best place `mobile` onto the paired `reference` points in
+/// the least squares sense, found through Horn's quaternion form of the Kabsch problem.
+pub fn kabsch(reference: &[[f64; 3]], mobile: &[[f64; 3]]) -> ([[f64; 3]; 3], [f64; 3]) {
+    let centroid = |points: &[[f64; 3]]| {
+        let count = points.len().max(1) as f64;
+        [0, 1, 2].map(|axis| points.iter().map(|point| point[axis]).sum::<f64>() / count)
+    };
+    let (reference_center, mobile_center) = (centroid(reference), centroid(mobile));
+
+    // Correlation of the centered point sets, s[a][b] = Σ mobile_a * reference_b
+    let mut s = [[0.0; 3]; 3];
+    for (r, m) in reference.iter().zip(mobile) {
+        for a in 0..3 {
+            for b in 0..3 {
+                s[a][b] += (m[a] - mobile_center[a]) * (r[b] - reference_center[b]);
+            }
+        }
+    }
+    let [[xx, xy, xz], [yx, yy, yz], [zx, zy, zz]] = s;
+    let n = [
+        [xx + yy + zz, yz - zy, zx - xz, xy - yx],
+        [yz - zy, xx - yy - zz, xy + yx, zx + xz],
+        [zx - xz, xy + yx, -xx + yy - zz, yz + zy],
+        [xy - yx, zx + xz, yz + zy, -xx - yy + zz],
+    ];
+
+    // The best rotation is the eigenvector of the largest eigenvalue, as a unit quaternion
+    let (values, vectors) = jacobi(n);
+    let best = (0..4)
+        .max_by(|&a, &b| values[a].total_cmp(&values[b]))
+        .unwrap_or(0);
+    let [w, x, y, z] = [0, 1, 2, 3].map(|row| vectors[row][best]);
+    let rotation = [
+        [
+            w * w + x * x - y * y - z * z,
+            2.0 * (x * y - w * z),
+            2.0 * (x * z + w * y),
+        ],
+        [
+            2.0 * (x * y + w * z),
+            w * w - x * x + y * y - z * z,
+            2.0 * (y * z - w * x),
+        ],
+        [
+            2.0 * (x * z - w * y),
+            2.0 * (y * z + w * x),
+            w * w - x * x - y * y + z * z,
+        ],
+    ];
+    let translation = [0, 1, 2].map(|row| {
+        reference_center[row]
+            - (0..3)
+                .map(|column| rotation[row][column] * mobile_center[column])
+                .sum::<f64>()
+    });
+
+    (rotation, translation)
+}
+
+/// Eigenvalues and eigenvectors, as columns, of a symmetric 4×4 matrix by cyclic Jacobi
+/// rotations.
+fn jacobi(mut matrix: [[f64; 4]; 4]) -> ([f64; 4], [[f64; 4]; 4]) {
+    let mut vectors = [[0.0; 4]; 4];
+    for (index, row) in vectors.iter_mut().enumerate() {
+        row[index] = 1.0;
+    }
+
+    for _ in 0..50 {
+        let off_diagonal = (0..4)
+            .flat_map(|p| (p + 1..4).map(move |q| (p, q)))
+            .map(|(p, q)| matrix[p][q] * matrix[p][q])
+            .sum::<f64>();
+        if off_diagonal < 1e-22 {
+            break;
+        }
+
+        for p in 0..4 {
+            for q in p + 1..4 {
+                if matrix[p][q].abs() < 1e-300 {
+                    continue;
+                }
+                let theta = (matrix[q][q] - matrix[p][p]) / (2.0 * matrix[p][q]);
+                let t = theta.signum() / (theta.abs() + (theta * theta + 1.0).sqrt());
+                let c = 1.0 / (t * t + 1.0).sqrt();
+                let s = t * c;
+
+                let rotate_columns = |rows: &mut [[f64; 4]; 4]| {
+                    for row in rows.iter_mut() {
+                        let (kp, kq) = (row[p], row[q]);
+                        row[p] = c * kp - s * kq;
+                        row[q] = s * kp + c * kq;
+                    }
+                };
+                rotate_columns(&mut matrix);
+                rotate_columns(&mut vectors);
+                let (row_p, row_q) = (matrix[p], matrix[q]);
+                matrix[p] = [0, 1, 2, 3].map(|k| c * row_p[k] - s * row_q[k]);
+                matrix[q] = [0, 1, 2, 3].map(|k| s * row_p[k] + c * row_q[k]);
+            }
+        }
+    }
+
+    ([0, 1, 2, 3].map(|index| matrix[index][index]), vectors)
+}
+
+pub fn to_json(superposition: &Superposition) -> Json {
+    let matrix = superposition.transformation.matrix();
+    Json::object([
+        ("rmsd", Json::from(superposition.rmsd)),
+        ("atoms", Json::from(superposition.pairs.len())),
+        (
+            "matrix",
+            Json::Array(
+                matrix
+                    .iter()
+                    .map(|row| Json::Array(row.iter().map(|&value| Json::from(value)).collect()))
+                    .collect(),
+            ),
+        ),
+    ])
+}
+
+#[test]
+fn recovers_a_known_rigid_motion() {
+    use crate::scene::Scene;
+    use crate::style::Style;
+
+    let scene = Scene::open("1d66.pdb", &Style::default()).unwrap();
+    let reference = scene.structure.as_ref().unwrap();
+
+    // A copy turned and moved in its file coordinates fits back with no deviation
+    let mut pdb = reference.pdb.clone();
+    let mut motion = TransformationMatrix::rotation_z(40.0);
+    motion = motion.combine(&TransformationMatrix::rotation_x(-25.0));
+    motion = motion.combine(&TransformationMatrix::translation(12.0, -3.0, 7.5));
+    pdb.apply_transformation(&motion);
+    let mut mobile = Structure::new(pdb, &Style::default());
+
+    let pairs = match_atoms(reference, &mobile, &Selection::All, Fit::Alpha);
+    assert_eq!(
+        pairs.len(),
+        reference
+            .atoms
+            .iter()
+            .filter(|atom| atom.name == "CA")
+            .count()
+    );
+    let fitted = superpose(reference, &mut mobile, pairs).unwrap();
+    assert!(fitted.rmsd < 1e-3, "rmsd {}", fitted.rmsd);
+    for (moved, original) in mobile.atoms.iter().zip(&reference.atoms) {
+        assert!(crate::math::distance(moved.position, original.position) < 1e-2);
+    }
+    assert!(mobile.superposition.is_some());
+
+    // A shifted chain leaves a deviation the size of the shift
+    let chain: Selection = "chain A".parse().unwrap();
+    let mut shifted = reference.pdb.clone();
+    for chain in shifted.chains_mut().filter(|chain| chain.id() == "B") {
+        chain.apply_transformation(&TransformationMatrix::translation(2.0, 0.0, 0.0));
+    }
+    let mut mobile = Structure::new(shifted, &Style::default());
+    let pairs = match_atoms(reference, &mobile, &chain, Fit::Selection);
+    assert!(superpose(reference, &mut mobile, pairs).unwrap().rmsd < 1e-3);
+    let pairs = match_atoms(reference, &mobile, &Selection::All, Fit::Alpha);
+    let rmsd = superpose(reference, &mut mobile, pairs).unwrap().rmsd;
+    assert!(rmsd > 0.5 && rmsd < 2.0, "rmsd {}", rmsd);
+    assert!("selection".parse::<Fit>().is_ok() && "xyz".parse::<Fit>().is_err());
+}
+
+#[test]
+fn parses_superpose_commands() {
+    use crate::script::ScriptCommand;
+    use std::path::PathBuf;
+
+    assert_eq!(
+        ScriptCommand::parse("superpose holo form.pdb selection"),
+        Ok(Some(ScriptCommand::Superpose(
+            Some(PathBuf::from("holo form.pdb")),
+            Fit::Selection,
+            false
+        )))
+    );
+    assert_eq!(
+        ScriptCommand::parse("superpose holo.pdb ca numbered"),
+        Ok(Some(ScriptCommand::Superpose(
+            Some(PathBuf::from("holo.pdb")),
+            Fit::Alpha,
+            true
+        )))
+    );
+    assert_eq!(
+        ScriptCommand::parse("superpose"),
+        Ok(Some(ScriptCommand::Superpose(None, Fit::Alpha, false)))
+    );
+}
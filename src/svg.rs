@@ -0,0 +1,305 @@
+use std::fmt::Write as _;
+use std::fs;
+use std::path::Path;
+
+use crate::math;
+use crate::object::Object;
+use crate::scene::Scene;
+use crate::session::Camera;
+
+const LABEL_SIZE: f32 = 12.0;
+
+/// A shape of the flattened scene, drawn back to front.
+enum Shape {
+    Circle {
+        center: [f32; 2],
+        radius: f32,
+        color: [f32; 3],
+        opacity: f32,
+    },
+    Line {
+        start: [f32; 2],
+        end: [f32; 2],
+        width: f32,
+        color: [f32; 3],
+        opacity: f32,
+    },
+    Label {
+        position: [f32; 2],
+        text: String,
+    },
+}
+
+/// Projects the scene with `camera` onto a `width` by `height` picture. Spheres become circles
+/// shaded by a radial gradient, cylinders become lines and labeled residues get their name next
+/// to their alpha carbon.
+pub fn render(
+    scene: &Scene,
+    camera: &Camera,
+    (width, height): (u32, u32),
+    background: [f32; 3],
+) -> String {
+    let (width, height) = (width as f32, height as f32);
+    let project = |point: [f32; 3]| {
+        math::project(
+            point,
+            (camera.turn, camera.tilt, camera.scale),
+            (width, height),
+        )
+    };
+
+    // Smaller clip space depth is nearer, so the largest is drawn first
+    let mut shapes = Vec::<(f32, Shape)>::new();
+
+    for sphere in &scene.spheres {
+        let mut min = [f32::MAX; 3];
+        let mut max = [f32::MIN; 3];
+        for vertex in sphere.vertices.chunks(3) {
+            for axis in 0..3 {
+                min[axis] = min[axis].min(vertex[axis]);
+                max[axis] = max[axis].max(vertex[axis]);
+            }
+        }
+        let center = [0, 1, 2].map(|axis| (min[axis] + max[axis]) / 2.0);
+        let radius = (0..3).map(|axis| max[axis] - min[axis]).sum::<f32>() / 6.0;
+
+        let (center, depth) = project(center);
+        shapes.push((
+            depth,
+            Shape::Circle {
+                center,
+                radius: radius * camera.scale * width.min(height) / 2.0,
+                color: [sphere.colors[0], sphere.colors[1], sphere.colors[2]],
+                opacity: sphere.opacity(),
+            },
+        ));
+    }
+
+    for cylinder in &scene.cyliders {
+        let (start, end) = cylinder.ends();
+        let ((start, start_depth), (end, end_depth)) = (project(start), project(end));
+        shapes.push((
+            (start_depth + end_depth) / 2.0,
+            Shape::Line {
+                start,
+                end,
+                width: 2.0 * cylinder.radius() * camera.scale * width.min(height) / 2.0,
+                color: [cylinder.colors[0], cylinder.colors[1], cylinder.colors[2]],
+                opacity: cylinder.opacity(),
+            },
+        ));
+    }
+
+    if let Some(structure) = &scene.structure {
+        let chains = structure.pdb.chain_count();
+        let mut labeled = structure
+            .atoms
+            .iter()
+            .filter(|atom| atom.labeled)
+            .collect::<Vec<_>>();
+        // One label per residue, on the alpha carbon when there is one
+        labeled.sort_by_key(|atom| (atom.residue, atom.name != "CA"));
+        labeled.dedup_by_key(|atom| atom.residue);
+
+        for atom in labeled {
+            let (position, _) = project(atom.position);
+            let text = if chains > 1 {
+                format!(
+                    "{}:{} {}",
+                    atom.chain_id, atom.residue_name, atom.residue_number
+                )
+            } else {
+                format!("{} {}", atom.residue_name, atom.residue_number)
+            };
+            // Labels stay readable on top of every shape
+            shapes.push((f32::MIN, Shape::Label { position, text }));
+        }
+    }
+
+    shapes.sort_by(|(a, _), (b, _)| b.total_cmp(a));
+
+    let mut gradients = Vec::<[f32; 3]>::new();
+    let mut body = String::new();
+    for (_, shape) in &shapes {
+        let _ = match shape {
+            Shape::Circle {
+                center,
+                radius,
+                color,
+                opacity,
+            } => {
+                let gradient = match gradients.iter().position(|other| other == color) {
+                    Some(gradient) => gradient,
+                    None => {
+                        gradients.push(*color);
+                        gradients.len() - 1
+                    }
+                };
+                writeln!(
+                    body,
+                    r#"<circle cx="{:.2}" cy="{:.2}" r="{:.2}" fill="url(#shade{})"{}/>"#,
+                    center[0],
+                    center[1],
+                    radius,
+                    gradient,
+                    opacity_attribute(*opacity)
+                )
+            }
+            Shape::Line {
+                start,
+                end,
+                width,
+                color,
+                opacity,
+            } => writeln!(
+                body,
+                r#"<line x1="{:.2}" y1="{:.2}" x2="{:.2}" y2="{:.2}" stroke="{}" stroke-width="{:.2}" stroke-linecap="round"{}/>"#,
+                start[0],
+                start[1],
+                end[0],
+                end[1],
+                hex(*color),
+                width,
+                opacity_attribute(*opacity)
+            ),
+            Shape::Label { position, text } => writeln!(
+                body,
+                r#"<text x="{:.2}" y="{:.2}" class="label">{}</text>"#,
+                position[0] + LABEL_SIZE / 2.0,
+                position[1] - LABEL_SIZE / 2.0,
+                escape(text)
+            ),
+        };
+    }
+
+    let mut svg = format!(
+        "<svg xmlns=\"http://www.w3.org/2000/svg\" width=\"{w}\" height=\"{h}\" viewBox=\"0 0 {w} {h}\">\n",
+        w = width,
+        h = height
+    );
+    svg.push_str("<defs>\n");
+    // Lit from the upper left, darkening towards the silhouette
+    for (index, color) in gradients.iter().enumerate() {
+        let light = color.map(|channel| channel + (1.0 - channel) * 0.6);
+        let dark = color.map(|channel| channel * 0.45);
+        let _ = writeln!(
+            svg,
+            "<radialGradient id=\"shade{}\" cx=\"0.5\" cy=\"0.5\" r=\"0.5\" fx=\"0.35\" fy=\"0.3\">\
+             <stop offset=\"0\" stop-color=\"{}\"/><stop offset=\"0.55\" stop-color=\"{}\"/>\
+             <stop offset=\"1\" stop-color=\"{}\"/></radialGradient>",
+            index,
+            hex(light),
+            hex(*color),
+            hex(dark)
+        );
+    }
+    let _ = writeln!(
+        svg,
+        "<style>.label {{ font: {}px sans-serif; fill: {}; stroke: {}; stroke-width: 3px; \
+         paint-order: stroke; }}</style>",
+        LABEL_SIZE,
+        hex(contrasting(background)),
+        hex(background)
+    );
+    svg.push_str("</defs>\n");
+    let _ = writeln!(
+        svg,
+        "<rect width=\"100%\" height=\"100%\" fill=\"{}\"/>",
+        hex(background)
+    );
+    svg.push_str(&body);
+    svg.push_str("</svg>\n");
+
+    svg
+}
+
+pub fn save(
+    scene: &Scene,
+    camera: &Camera,
+    size: (u32, u32),
+    background: [f32; 3],
+    path: &Path,
+) -> Result<(), String> {
+    fs::write(path, render(scene, camera, size, background))
+        .map_err(|error| format!("Failed to save {}: {}", path.display(), error))
+}
+
+fn hex(color: [f32; 3]) -> String {
+    let [red, green, blue] = color.map(|channel| (channel.clamp(0.0, 1.0) * 255.0).round() as u8);
+    format!("#{:02x}{:02x}{:02x}", red, green, blue)
+}
+
+fn opacity_attribute(opacity: f32) -> String {
+    if opacity < 1.0 {
+        format!(r#" opacity="{:.2}""#, opacity)
+    } else {
+        String::new()
+    }
+}
+
+/// Black or white, whichever reads better on `background`.
+fn contrasting(background: [f32; 3]) -> [f32; 3] {
+    let luminance = 0.2126 * background[0] + 0.7152 * background[1] + 0.0722 * background[2];
+    if luminance > 0.5 {
+        [0.0; 3]
+    } else {
+        [1.0; 3]
+    }
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+}
+
+#[test]
+fn draws_depth_sorted_shapes_and_labels() {
+    use crate::style::{Representation, Style};
+
+    let style = Style {
+        representation: Representation::BallAndStick,
+        ..Style::default()
+    };
+    let mut scene = Scene::open("1d66.pdb", &style).unwrap();
+    let residue = scene.select(&"chain A and resi 20".parse().unwrap());
+    scene.label(&residue, true);
+
+    let camera = Camera {
+        turn: 0.3,
+        tilt: 0.2,
+        scale: 0.04,
+    };
+    let svg = render(&scene, &camera, (400, 300), [1.0; 3]);
+
+    assert_eq!(svg.matches("<circle").count(), scene.spheres.len());
+    assert_eq!(svg.matches("<line").count(), scene.cyliders.len());
+    assert_eq!(svg.matches("<text").count(), 1);
+    assert!(svg.contains("url(#shade0)"));
+
+    // Nearest last: the final circle belongs to the sphere closest to the viewer
+    let view_center = |sphere: &crate::sphere::Sphere| {
+        let axis_range = |axis: usize| {
+            let values = sphere.vertices.iter().skip(axis).step_by(3);
+            let (min, max) = values.fold((f32::MAX, f32::MIN), |(min, max), &value| {
+                (min.min(value), max.max(value))
+            });
+            (min + max) / 2.0
+        };
+        math::view_rotate([0, 1, 2].map(axis_range), camera.turn, camera.tilt)
+    };
+    let nearest = scene
+        .spheres
+        .iter()
+        .map(view_center)
+        .min_by(|a, b| a[2].total_cmp(&b[2]))
+        .unwrap();
+    let last_circle = svg
+        .lines()
+        .rfind(|line| line.starts_with("<circle"))
+        .unwrap();
+    assert!(last_circle.contains(&format!(
+        r#"cx="{:.2}""#,
+        (nearest[0] * camera.scale + 1.0) / 2.0 * 400.0
+    )));
+}